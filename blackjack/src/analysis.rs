@@ -0,0 +1,291 @@
+use crate::calculation::{
+    calculate_solution_without_initial_situation, calculate_split_expectation_with_limit,
+    cmp_decisions, remove_initial_cards, SolutionForInitialSituation,
+};
+use crate::strategy::Strategy;
+use crate::{CardCount, Decision, InitialSituation, Rule};
+
+/// An initial situation where two strategies make different first decisions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecisionDiff {
+    pub hand: (u8, u8),
+    pub dealer_up_card: u8,
+    pub decision_a: Decision,
+    pub decision_b: Decision,
+
+    /// The expectations of the decisions of strategy a and b, given by the solver.
+    pub ex_a: f64,
+    pub ex_b: f64,
+}
+
+/// Compares the first decisions of two strategies in each initial situation of a fresh shoe, and
+/// returns the initial situations where they disagree. The expectations of Split are given by
+/// `calculate_split_expectation_with_limit`, and those of other decisions by the solver.
+pub fn diff_strategies(
+    number_of_threads: usize,
+    rule: &Rule,
+    a: &mut impl Strategy,
+    b: &mut impl Strategy,
+) -> Vec<DecisionDiff> {
+    let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+    diff_strategies_of_shoe(number_of_threads, rule, &shoe, a, b)
+}
+
+/// Same as `diff_strategies`, but for the given shoe instead of a fresh one. The initial
+/// situations that cannot be dealt from the shoe are skipped.
+fn diff_strategies_of_shoe(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+    a: &mut impl Strategy,
+    b: &mut impl Strategy,
+) -> Vec<DecisionDiff> {
+    let shoe = *shoe;
+    a.calculate_expectation_before_bet(rule, &shoe);
+    b.calculate_expectation_before_bet(rule, &shoe);
+    let solution = calculate_solution_without_initial_situation(number_of_threads, rule, &shoe);
+
+    let mut diffs = Vec::new();
+    for dealer_up_card in 1..=10 {
+        for first_hand_card in 1..=10 {
+            for second_hand_card in 1..=first_hand_card {
+                let hand_cards = (first_hand_card, second_hand_card);
+                let initial_shoe = match remove_initial_cards(&shoe, hand_cards, dealer_up_card) {
+                    Some(initial_shoe) => initial_shoe,
+                    None => continue,
+                };
+                let initial_situation =
+                    InitialSituation::new(initial_shoe, hand_cards, dealer_up_card);
+                let mut hand = CardCount::with_number_of_decks(0);
+                hand.add_card(first_hand_card);
+                hand.add_card(second_hand_card);
+
+                a.init_with_initial_situation(rule, &initial_situation);
+                b.init_with_initial_situation(rule, &initial_situation);
+                let decision_a = a.make_decision(rule, &hand, 0, 0);
+                let decision_b = b.make_decision(rule, &hand, 0, 0);
+                if decision_a == decision_b {
+                    continue;
+                }
+
+                let solution_for_initial_situation =
+                    solution.get_solution_for_initial_situation(hand_cards, dealer_up_card);
+                let get_expectation = |decision| {
+                    get_first_decision_expectation(
+                        rule,
+                        &solution_for_initial_situation,
+                        &initial_situation,
+                        decision,
+                    )
+                };
+                diffs.push(DecisionDiff {
+                    hand: hand_cards,
+                    dealer_up_card,
+                    decision_a,
+                    decision_b,
+                    ex_a: get_expectation(decision_a),
+                    ex_b: get_expectation(decision_b),
+                });
+            }
+        }
+    }
+    diffs
+}
+
+/// The cost of making the second-best first decision in an initial situation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MistakeCost {
+    pub hand: (u8, u8),
+    pub dealer_up_card: u8,
+    pub best_decision: Decision,
+    pub mistake: Decision,
+
+    /// How much expectation (based on the original bet) is lost by the mistake.
+    pub cost: f64,
+}
+
+/// Returns the cost of the second-best first decision in each initial situation of a fresh shoe
+/// (except those with a single possible decision, i.e., naturals), sorted from the most costly
+/// mistake to the least (and then by hand and dealer up card). The expectations are given the same
+/// way as `diff_strategies`.
+pub fn mistake_costs(number_of_threads: usize, rule: &Rule) -> Vec<MistakeCost> {
+    let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+    mistake_costs_of_shoe(number_of_threads, rule, &shoe)
+}
+
+/// Same as `mistake_costs`, but for the given shoe instead of a fresh one. The initial situations
+/// that cannot be dealt from the shoe are skipped.
+fn mistake_costs_of_shoe(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+) -> Vec<MistakeCost> {
+    let shoe = *shoe;
+    let solution = calculate_solution_without_initial_situation(number_of_threads, rule, &shoe);
+
+    let mut costs = Vec::with_capacity(550);
+    for dealer_up_card in 1..=10 {
+        for first_hand_card in 1..=10 {
+            for second_hand_card in 1..=first_hand_card {
+                let hand_cards = (first_hand_card, second_hand_card);
+                let initial_shoe = match remove_initial_cards(&shoe, hand_cards, dealer_up_card) {
+                    Some(initial_shoe) => initial_shoe,
+                    None => continue,
+                };
+                let initial_situation =
+                    InitialSituation::new(initial_shoe, hand_cards, dealer_up_card);
+                let solution_for_initial_situation =
+                    solution.get_solution_for_initial_situation(hand_cards, dealer_up_card);
+
+                let mut exs: Vec<(Decision, f64)> = [
+                    Decision::Stand,
+                    Decision::Hit,
+                    Decision::Double,
+                    Decision::Split,
+                    Decision::Surrender,
+                ]
+                .into_iter()
+                .map(|decision| {
+                    let ex = get_first_decision_expectation(
+                        rule,
+                        &solution_for_initial_situation,
+                        &initial_situation,
+                        decision,
+                    );
+                    (decision, ex)
+                })
+                .filter(|&(_, ex)| ex != -f64::INFINITY)
+                .collect();
+                exs.sort_by(|&(d1, ex1), &(d2, ex2)| cmp_decisions(ex1, d1, ex2, d2));
+                if exs.len() < 2 {
+                    // E.g. a natural, which can only stand.
+                    continue;
+                }
+                costs.push(MistakeCost {
+                    hand: hand_cards,
+                    dealer_up_card,
+                    best_decision: exs[0].0,
+                    mistake: exs[1].0,
+                    cost: exs[0].1 - exs[1].1,
+                });
+            }
+        }
+    }
+    costs.sort_by(|a, b| {
+        b.cost
+            .total_cmp(&a.cost)
+            .then(a.hand.cmp(&b.hand))
+            .then(a.dealer_up_card.cmp(&b.dealer_up_card))
+    });
+    costs
+}
+
+/// Returns the expectation of making the given first decision in the given initial situation.
+/// The expectation of Split is given by `calculate_split_expectation_with_limit`, and those of
+/// other decisions by the solver.
+fn get_first_decision_expectation(
+    rule: &Rule,
+    solution_for_initial_situation: &SolutionForInitialSituation,
+    initial_situation: &InitialSituation,
+    decision: Decision,
+) -> f64 {
+    let (first_hand_card, second_hand_card) = initial_situation.hand_cards;
+    if decision == Decision::Split {
+        return calculate_split_expectation_with_limit(
+            rule,
+            initial_situation,
+            rule.split_all_limits,
+        );
+    }
+    let mut hand = CardCount::with_number_of_decks(0);
+    hand.add_card(first_hand_card);
+    hand.add_card(second_hand_card);
+    solution_for_initial_situation.decision_expectation(&hand, rule, decision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::{BasicStrategy, DpStrategySinglePlayer};
+
+    #[test]
+    fn standing_on_12_is_a_cheap_mistake() {
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[2, 1, 1, 1, 1, 1, 1, 1, 1, 6]);
+        let costs = mistake_costs_of_shoe(0, &rule, &shoe);
+
+        // Every initial situation that can be dealt from the shoe is listed, except naturals.
+        let mut number_of_situations = 0;
+        for dealer_up_card in 1..=10 {
+            for first_hand_card in 1..=10 {
+                for second_hand_card in 1..=first_hand_card {
+                    let hand_cards = (first_hand_card, second_hand_card);
+                    if hand_cards != (10, 1)
+                        && remove_initial_cards(&shoe, hand_cards, dealer_up_card).is_some()
+                    {
+                        number_of_situations += 1;
+                    }
+                }
+            }
+        }
+        assert_eq!(costs.len(), number_of_situations);
+        assert!(costs.windows(2).all(|w| w[0].cost >= w[1].cost));
+        let find = |hand, dealer_up_card| {
+            *costs
+                .iter()
+                .find(|cost| cost.hand == hand && cost.dealer_up_card == dealer_up_card)
+                .unwrap()
+        };
+
+        // Standing and hitting are close on 12 against 3. Which one is better depends on the
+        // shoe.
+        let cost = find((10, 2), 3);
+        let decisions = [cost.best_decision, cost.mistake];
+        assert!(decisions.contains(&Decision::Hit) && decisions.contains(&Decision::Stand));
+        assert!(cost.cost > 0.0 && cost.cost < 0.05);
+
+        // Splitting is the second-best decision on hard 20, and hitting is much worse.
+        let cost = find((10, 10), 6);
+        assert_eq!(cost.best_decision, Decision::Stand);
+        assert_eq!(cost.mistake, Decision::Split);
+        assert!(cost.cost > 0.0);
+        let solution = calculate_solution_without_initial_situation(0, &rule, &shoe)
+            .get_solution_for_initial_situation((10, 10), 6);
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(10);
+        hand.add_card(10);
+        let cost_of_hit = solution.decision_expectation(&hand, &rule, Decision::Stand)
+            - solution.decision_expectation(&hand, &rule, Decision::Hit);
+        assert!(cost_of_hit > 1.0);
+
+        // The most costly second-best decision is hitting a strong hand like hard 19.
+        assert_eq!(costs[0].mistake, Decision::Hit);
+        assert!(costs[0].cost > 1.0);
+    }
+
+    #[test]
+    fn solver_beats_basic_strategy_where_they_disagree() {
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[2, 1, 1, 1, 1, 1, 1, 1, 1, 6]);
+        let mut basic_strategy = BasicStrategy::new(&rule);
+        let mut dp_strategy = DpStrategySinglePlayer::new(0);
+        let diffs = diff_strategies_of_shoe(0, &rule, &shoe, &mut basic_strategy, &mut dp_strategy);
+
+        // The basic strategy is made for a full shoe, so it misses many composition-dependent
+        // plays on a shoe this small. Each of them costs expectation.
+        assert!(!diffs.is_empty());
+        for diff in &diffs {
+            assert!(diff.ex_b > diff.ex_a);
+        }
+
+        let mut another_basic_strategy = BasicStrategy::new(&rule);
+        let diffs = diff_strategies_of_shoe(
+            0,
+            &rule,
+            &shoe,
+            &mut basic_strategy,
+            &mut another_basic_strategy,
+        );
+        assert!(diffs.is_empty());
+    }
+}
@@ -1,52 +1,131 @@
 use self::calculation_states::HandShoePair;
 
-use super::{Decision, PeekPolicy, Rule};
+use super::{Decision, PeekPolicy, PushOrLose, Rule};
 use crate::{CardCount, InitialSituation, StateArray};
-use std::{cmp::Ordering, ops};
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    ops,
+    sync::atomic::{self, AtomicUsize},
+};
 
 mod calculation_states;
+mod counting;
+mod decision_chart;
+mod export;
+mod float;
+mod index_plays;
+mod known_cards;
+mod mimic_dealer;
+mod side_bets;
+mod solution_cache;
+mod split_ex;
+
+pub use self::counting::{
+    advantage_slope, insurance_correlation, is_countable, penetration_value, playing_efficiency,
+};
+pub use self::decision_chart::{
+    composition_dependent_chart, hybrid_chart, hybrid_chart_from_solution, DecisionChart,
+};
+pub use self::export::{export_strategy, read_ev_snapshot, write_ev_snapshot, ExportFormat};
+pub use self::float::Float;
+pub use self::index_plays::{index_deviations, IndexPlay};
+pub use self::known_cards::ev_with_known_next_cards;
+pub use self::mimic_dealer::mimic_dealer_ev;
+pub use self::side_bets::{
+    bust_it_expectation, lucky_ladies_expectation, match_the_dealer_expectation,
+    side_bet_expectation, LuckyLadiesPayouts, MatchTheDealerPayouts, SideBet, BUST_IT_PAYOUTS,
+};
+pub use self::solution_cache::SolutionCache;
+pub use self::split_ex::calculate_split_expectation_with_limit;
 
 #[derive(Clone, Copy, Debug)]
-pub struct Expectation {
-    pub hit: f64,
-    pub stand: f64,
+pub struct Expectation<F: Float = f64> {
+    pub hit: F,
+    pub stand: F,
+
+    /// The expectations of the squared outcomes of Hit and Stand, which give the variances.
+    pub hit_second_moment: F,
+    pub stand_second_moment: F,
 }
 
-impl Default for Expectation {
+impl<F: Float> Default for Expectation<F> {
     fn default() -> Self {
         Expectation {
-            hit: -f64::INFINITY,
-            stand: -f64::INFINITY,
+            hit: F::NEG_INFINITY,
+            stand: F::NEG_INFINITY,
+            hit_second_moment: F::default(),
+            stand_second_moment: F::default(),
         }
     }
 }
 
-pub fn get_max_expectation(
-    solution: &StateArray<Expectation>,
+/// The canonical order used to break ties between decisions with exactly the same expectation.
+/// The decision that comes first wins, i.e., Stand > Double > Hit > Split > Surrender.
+const TIE_BREAK_ORDER: [Decision; 5] = [
+    Decision::Stand,
+    Decision::Double,
+    Decision::Hit,
+    Decision::Split,
+    Decision::Surrender,
+];
+
+fn get_tie_break_rank(decision: Decision) -> usize {
+    TIE_BREAK_ORDER
+        .iter()
+        .position(|d| *d == decision)
+        .unwrap_or(TIE_BREAK_ORDER.len())
+}
+
+/// Returns true if `decision` with expectation `ex` should be preferred over `max_decision` with
+/// expectation `max_ex`. Exact ties are broken by `TIE_BREAK_ORDER`.
+pub fn is_better_decision<F: Float>(
+    ex: F,
+    decision: Decision,
+    max_ex: F,
+    max_decision: Decision,
+) -> bool {
+    match ex.partial_cmp(&max_ex) {
+        Some(Ordering::Greater) => true,
+        Some(Ordering::Equal) => get_tie_break_rank(decision) < get_tie_break_rank(max_decision),
+        _ => false,
+    }
+}
+
+/// Orders two decisions with their expectations from the better one to the worse one, i.e., by
+/// expectation and then by `TIE_BREAK_ORDER`. Unlike `is_better_decision`, this is a total order,
+/// e.g., for sorting.
+pub fn cmp_decisions(ex1: f64, decision1: Decision, ex2: f64, decision2: Decision) -> Ordering {
+    ex2.total_cmp(&ex1)
+        .then_with(|| get_tie_break_rank(decision1).cmp(&get_tie_break_rank(decision2)))
+}
+
+pub fn get_max_expectation<F: Float>(
+    solution: &StateArray<Expectation<F>>,
     state: &CardCount,
     rule: &Rule,
-) -> (f64, Decision) {
+) -> (F, Decision) {
     if state.bust() {
-        return (-1.0, Decision::Stand);
+        return (F::from_f64(-1.0), Decision::Stand);
     }
-    if state.get_total() >= rule.charlie_number as u16 {
-        return (1.0, Decision::Stand);
+    if rule.reached_charlie(state) {
+        return (F::from_f64(1.0), Decision::Stand);
     }
 
     let (mut max_ex, mut max_decision) = {
         if rule.allow_late_surrender {
-            (-0.5, Decision::Surrender)
+            (F::from_f64(-0.5), Decision::Surrender)
         } else {
-            (-f64::INFINITY, Decision::PlaceHolder)
+            (F::NEG_INFINITY, Decision::PlaceHolder)
         }
     };
 
     let ex = solution[state];
-    if max_ex < ex.stand {
+    if is_better_decision(ex.stand, Decision::Stand, max_ex, max_decision) {
         max_ex = ex.stand;
         max_decision = Decision::Stand;
     }
-    if max_ex < ex.hit {
+    if is_better_decision(ex.hit, Decision::Hit, max_ex, max_decision) {
         max_ex = ex.hit;
         max_decision = Decision::Hit;
     }
@@ -54,12 +133,55 @@ pub fn get_max_expectation(
     (max_ex, max_decision)
 }
 
+/// Returns the expectation of the squared outcome of making the given decision (returned by
+/// `get_max_expectation`) on the given state.
+fn get_second_moment<F: Float>(
+    solution: &StateArray<Expectation<F>>,
+    state: &CardCount,
+    rule: &Rule,
+    decision: Decision,
+) -> F {
+    if state.bust() || rule.reached_charlie(state) {
+        return F::from_f64(1.0);
+    }
+    match decision {
+        Decision::Stand => solution[state].stand_second_moment,
+        Decision::Hit => solution[state].hit_second_moment,
+        Decision::Surrender => F::from_f64(0.25),
+        _ => F::default(),
+    }
+}
+
+/// Returns the expectation and the expectation of the squared outcome of standing on the given
+/// hand, given the odds of standing.
+fn get_stand_moments<F: Float>(
+    rule: &Rule,
+    hand: &CardCount,
+    stand_odds: &WinLoseCasesOdds<F>,
+) -> (F, F) {
+    if hand.is_natural() {
+        let payout_blackjack = F::from_f64(rule.payout_blackjack);
+        (
+            stand_odds.win * payout_blackjack - stand_odds.lose,
+            stand_odds.win * payout_blackjack * payout_blackjack + stand_odds.lose,
+        )
+    } else {
+        (
+            stand_odds.win - stand_odds.lose,
+            stand_odds.win + stand_odds.lose,
+        )
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct SolutionForInitialSituation {
     pub ex_stand_hit: StateArray<Expectation>,
     pub ex_double: f64,
     pub ex_split: f64,
 
+    /// The expectation of the squared outcome of Double, based on the original bet.
+    pub double_second_moment: f64,
+
     /// Represents the expectation of the side bet "Buy Insurance". There is no relation between this side
     /// bet and the main game. If this expectation is positive, players should buy insurance.
     /// Note that this expectation is based on its own bet, not the main bet.
@@ -74,9 +196,120 @@ pub struct SolutionForInitialSituation {
     pub ex_summary: f64,
 }
 
+impl SolutionForInitialSituation {
+    /// Returns the best decision for the given hand among the legal ones. Double and Split are only
+    /// considered for the initial hand (i.e., a hand with 2 cards) because their expectations are
+    /// only known for it. Surrender is considered only if both `can_surrender` and the rule allow
+    /// it.
+    pub fn decision_for(
+        &self,
+        hand: &CardCount,
+        rule: &Rule,
+        can_double: bool,
+        can_split: bool,
+        can_surrender: bool,
+    ) -> Decision {
+        let mut rule = *rule;
+        rule.allow_late_surrender &= can_surrender;
+        let (mut max_ex, mut decision) = get_max_expectation(&self.ex_stand_hit, hand, &rule);
+        if hand.get_total() != 2 {
+            return decision;
+        }
+
+        if can_double && is_better_decision(self.ex_double, Decision::Double, max_ex, decision) {
+            max_ex = self.ex_double;
+            decision = Decision::Double;
+        }
+        if can_split && is_better_decision(self.ex_split, Decision::Split, max_ex, decision) {
+            decision = Decision::Split;
+        }
+        decision
+    }
+
+    /// Returns the expectation of making the given decision on the given hand, or -inf if the
+    /// decision is not possible. Like `decision_for`, Double and Split are only possible for the
+    /// initial hand.
+    pub fn decision_expectation(&self, hand: &CardCount, rule: &Rule, decision: Decision) -> f64 {
+        if hand.bust() {
+            return -1.0;
+        }
+        if rule.reached_charlie(hand) {
+            return 1.0;
+        }
+        match decision {
+            Decision::Stand => self.ex_stand_hit[hand].stand,
+            Decision::Hit => self.ex_stand_hit[hand].hit,
+            Decision::Double if hand.get_total() == 2 => self.ex_double,
+            Decision::Split if hand.get_total() == 2 => self.ex_split,
+            Decision::Surrender if rule.allow_late_surrender => -0.5,
+            _ => -f64::INFINITY,
+        }
+    }
+
+    /// Returns the variance of the outcome (based on the original bet) of making the given
+    /// decision on the given hand, assuming the best decisions are made afterwards. Returns NaN if
+    /// the decision is not possible or its variance is unknown (i.e., Split).
+    pub fn decision_variance(&self, hand: &CardCount, rule: &Rule, decision: Decision) -> f64 {
+        let ex = self.decision_expectation(hand, rule, decision);
+        if ex == -f64::INFINITY {
+            return f64::NAN;
+        }
+        let second_moment = match decision {
+            _ if hand.bust() || rule.reached_charlie(hand) => 1.0,
+            Decision::Stand => self.ex_stand_hit[hand].stand_second_moment,
+            Decision::Hit => self.ex_stand_hit[hand].hit_second_moment,
+            Decision::Double => self.double_second_moment,
+            Decision::Surrender => 0.25,
+            _ => return f64::NAN,
+        };
+        second_moment - ex * ex
+    }
+
+    /// Returns a human-readable explanation of the best decision for the given hand, e.g.
+    /// "Double: +0.2100 EV beats Hit +0.1500 and Stand -0.0500". Decisions that are not
+    /// possible are not mentioned.
+    pub fn explain(&self, hand: &CardCount, rule: &Rule) -> String {
+        let (max_ex, decision) = get_max_expectation(&self.ex_stand_hit, hand, rule);
+        if hand.bust() || rule.reached_charlie(hand) {
+            return format!("{:?}: {:+.4} EV", decision, max_ex);
+        }
+
+        let ex = self.ex_stand_hit[hand];
+        let mut exs = vec![(Decision::Stand, ex.stand), (Decision::Hit, ex.hit)];
+        if hand.get_total() == 2 {
+            exs.push((Decision::Double, self.ex_double));
+            exs.push((Decision::Split, self.ex_split));
+        }
+        if rule.allow_late_surrender {
+            exs.push((Decision::Surrender, -0.5));
+        }
+        exs.retain(|&(_, ex)| ex != -f64::INFINITY);
+        exs.sort_by(|&(d1, ex1), &(d2, ex2)| {
+            if is_better_decision(ex1, d1, ex2, d2) {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        });
+
+        let (best_decision, best_ex) = exs[0];
+        let mut explanation = format!("{:?}: {:+.4} EV", best_decision, best_ex);
+        for (i, (decision, ex)) in exs.iter().enumerate().skip(1) {
+            let separator = match i {
+                1 => " beats ",
+                _ if i + 1 == exs.len() => " and ",
+                _ => ", ",
+            };
+            explanation += &format!("{}{:?} {:+.4}", separator, decision, ex);
+        }
+        explanation
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 struct ExsOtherDecisions {
     ex_double: f64,
+    double_second_moment: f64,
     ex_split: f64,
 
     ex_extra_insurance: f64,
@@ -97,11 +330,29 @@ const fn get_prefix_sum() -> [usize; 10] {
 
 static PREFIX_SUM: [usize; 10] = get_prefix_sum();
 
+/// Returns the index (from 0 to 54) of the given initial hand, regardless of the order of the two
+/// cards.
+fn get_initial_hand_index(mut hand: (u8, u8)) -> usize {
+    if hand.0 < hand.1 {
+        (hand.0, hand.1) = (hand.1, hand.0);
+    }
+    PREFIX_SUM[(hand.0 - 1) as usize] + (hand.1 - 1) as usize
+}
+
+/// The solution of a shoe before the initial cards are dealt. Every expectation in it depends on
+/// the whole shoe, e.g., the dealer outcomes against any hand change when a single card leaves
+/// the shoe, so there is no part of a solution that stays valid after a card is dealt. To follow
+/// the shoe as cards are dealt, solve the reduced shoe again with
+/// `calculate_solution_without_initial_situation`.
 #[derive(Debug)]
 pub struct SolutionForBettingPhase {
     exs_stand_hit: [StateArray<Expectation>; 10],
     exs_other_decisions: [[ExsOtherDecisions; 55]; 10],
     ex_total_summary: f64,
+
+    // The shoe and options this solution is solved with.
+    shoe: CardCount,
+    options: SolveOptions,
 }
 
 impl Default for SolutionForBettingPhase {
@@ -111,6 +362,8 @@ impl Default for SolutionForBettingPhase {
             exs_stand_hit: Default::default(),
             exs_other_decisions,
             ex_total_summary: Default::default(),
+            shoe: CardCount::with_number_of_decks(0),
+            options: Default::default(),
         }
     }
 }
@@ -120,12 +373,33 @@ impl SolutionForBettingPhase {
         mut self,
         hand: (u8, u8),
         dealer_up_card: u8,
+    ) -> SolutionForInitialSituation {
+        self.take_solution_for_initial_situation(hand, dealer_up_card)
+    }
+
+    /// Same as `into_solution_for_initial_situation`, but the solution is kept. The expectations
+    /// of Stand and Hit of the dealer up card are moved out of it, and must be given back by
+    /// `return_solution_for_initial_situation` before it is used for this dealer up card again.
+    pub fn take_solution_for_initial_situation(
+        &mut self,
+        hand: (u8, u8),
+        dealer_up_card: u8,
     ) -> SolutionForInitialSituation {
         let mut sol = self.get_solution_for_initial_situation_aux(hand, dealer_up_card);
         sol.ex_stand_hit = core::mem::take(&mut self.exs_stand_hit[(dealer_up_card - 1) as usize]);
         sol
     }
 
+    /// Gives back the expectations of Stand and Hit moved out by
+    /// `take_solution_for_initial_situation` for the given dealer up card.
+    pub fn return_solution_for_initial_situation(
+        &mut self,
+        solution: SolutionForInitialSituation,
+        dealer_up_card: u8,
+    ) {
+        self.exs_stand_hit[(dealer_up_card - 1) as usize] = solution.ex_stand_hit;
+    }
+
     pub fn get_solution_for_initial_situation(
         &self,
         hand: (u8, u8),
@@ -140,21 +414,205 @@ impl SolutionForBettingPhase {
         self.ex_total_summary
     }
 
+    /// Returns the classic "hit until N" table, i.e., for each dealer up card (from Ace to 10),
+    /// the minimum hard total at which standing beats hitting. Each hard total is represented by
+    /// the most common two-card hand with this total, i.e., a 10 and another card. Totals that
+    /// cannot be dealt this way from the shoe are skipped.
+    pub fn stand_thresholds(&self, rule: &Rule) -> [u16; 10] {
+        let mut thresholds = [21; 10];
+        for (threshold, exs_stand_hit) in thresholds.iter_mut().zip(&self.exs_stand_hit) {
+            for total in (12..=20).rev() {
+                let mut hand = CardCount::with_number_of_decks(0);
+                hand.add_card(10);
+                hand.add_card((total - 10) as u8);
+                if !exs_stand_hit.contains_state(&hand) {
+                    continue;
+                }
+                let (_, decision) = get_max_expectation(exs_stand_hit, &hand, rule);
+                if decision != Decision::Stand {
+                    break;
+                }
+                *threshold = total;
+            }
+        }
+        thresholds
+    }
+
+    /// Returns all the initial situations, i.e., the initial hand (with the larger card first) and
+    /// the dealer up card, along with their summary expectations, sorted from the best to the
+    /// worst. Initial situations that cannot be dealt from the shoe are left out.
+    pub fn hands_by_ev(&self) -> Vec<((u8, u8), u8, f64)> {
+        let mut hands = Vec::with_capacity(550);
+        for dealer_up_card in 1..=10 {
+            for first_hand_card in 1..=10 {
+                for second_hand_card in 1..=first_hand_card {
+                    let hand = (first_hand_card, second_hand_card);
+                    if !self.can_be_dealt(hand, dealer_up_card) {
+                        continue;
+                    }
+                    let ex = self.exs_other_decisions[(dealer_up_card - 1) as usize]
+                        [get_initial_hand_index(hand)]
+                    .ex_summary;
+                    hands.push((hand, dealer_up_card, ex));
+                }
+            }
+        }
+        hands.sort_by(|a, b| b.2.total_cmp(&a.2));
+        hands
+    }
+
+    /// Returns the expectations of all the initial situations as a map ordered by key, e.g., to
+    /// compare the solver output with a snapshot saved by `write_ev_snapshot`. The summary
+    /// expectation of an initial situation is keyed by the dealer up card and the hand (with the
+    /// larger card first), e.g., "6 10,5", and the expectation of each possible decision on it is
+    /// keyed like "6 10,5 Double". The total expectation is keyed by "total". Initial situations
+    /// the shoe cannot deal are left out.
+    pub fn ev_snapshot(&self, rule: &Rule) -> BTreeMap<String, f64> {
+        let mut snapshot = BTreeMap::new();
+        snapshot.insert(String::from("total"), self.ex_total_summary);
+        for dealer_up_card in 1..=10 {
+            for first_hand_card in 1..=10 {
+                for second_hand_card in 1..=first_hand_card {
+                    let hand = (first_hand_card, second_hand_card);
+                    if !self.can_be_dealt(hand, dealer_up_card) {
+                        continue;
+                    }
+                    let key = format!("{} {},{}", dealer_up_card, hand.0, hand.1);
+                    let exs =
+                        decision_chart::get_initial_expectations(self, rule, hand, dealer_up_card);
+                    for (decision, ex) in exs {
+                        if ex != -f64::INFINITY {
+                            snapshot.insert(format!("{} {:?}", key, decision), ex);
+                        }
+                    }
+                    let ex = self.exs_other_decisions[(dealer_up_card - 1) as usize]
+                        [get_initial_hand_index(hand)]
+                    .ex_summary;
+                    snapshot.insert(key, ex);
+                }
+            }
+        }
+        snapshot
+    }
+
+    /// Returns the expectation of making the given decision on the initial hand, for each player
+    /// total (from 1 to 21, by row) and each dealer up card (from Ace to 10, by column), e.g., for
+    /// plotting. Each cell averages the initial hands of the total, weighted by their
+    /// probabilities. Cells where no initial hand can make the decision (e.g., totals 1 to 3, or
+    /// Split on an odd hard total) have no meaning and are NaN.
+    pub fn ev_heatmap(&self, rule: &Rule, decision: Decision) -> [[f64; 10]; 21] {
+        let mut heatmap = [[f64::NAN; 10]; 21];
+        for dealer_up_card in 1..=10 {
+            let d = (dealer_up_card - 1) as usize;
+            let mut shoe = self.shoe;
+            shoe.remove_card(dealer_up_card);
+            let mut sums = [(0.0, 0.0); 21];
+            for (hand_cards, weight) in starting_hand_frequencies(&shoe) {
+                if weight == 0.0 {
+                    continue;
+                }
+                let mut hand = CardCount::with_number_of_decks(0);
+                hand.add_card(hand_cards.0);
+                hand.add_card(hand_cards.1);
+                let other = &self.exs_other_decisions[d][get_initial_hand_index(hand_cards)];
+                let ex = match decision {
+                    Decision::Stand => self.exs_stand_hit[d][&hand].stand,
+                    Decision::Hit => self.exs_stand_hit[d][&hand].hit,
+                    Decision::Double => other.ex_double,
+                    Decision::Split => other.ex_split,
+                    Decision::Surrender if rule.allow_late_surrender => -0.5,
+                    _ => -f64::INFINITY,
+                };
+                if ex == -f64::INFINITY {
+                    continue;
+                }
+                let sum = &mut sums[(hand.get_actual_sum() - 1) as usize];
+                sum.0 += weight * ex;
+                sum.1 += weight;
+            }
+            for (row, (weighted_ex, weight)) in heatmap.iter_mut().zip(sums) {
+                if weight > 0.0 {
+                    row[d] = weighted_ex / weight;
+                }
+            }
+        }
+        heatmap
+    }
+
+    /// Returns whether the initial situation can be dealt from the shoe, i.e., whether it is
+    /// solved.
+    fn can_be_dealt(&self, hand: (u8, u8), dealer_up_card: u8) -> bool {
+        remove_initial_cards(&self.shoe, hand, dealer_up_card).is_some()
+    }
+
+    /// Solves all the initial situations of the shoe and fills in the solution.
+    fn solve(&mut self, number_of_threads: usize, rule: &Rule) {
+        let mut initial_situation = InitialSituation::new(self.shoe, (1, 1), 1);
+        let total_combs = self.shoe.get_total() as u32;
+        let total_combs = total_combs * (total_combs - 1) * (total_combs - 2);
+        let total_combs = total_combs as f64;
+        // Enumerate all possible combinations.
+        for dealer_up_card in 1..=10 {
+            let idx10 = (dealer_up_card - 1) as usize;
+            if initial_situation.shoe[dealer_up_card] == 0 {
+                continue;
+            }
+            initial_situation.dealer_up_card = dealer_up_card;
+            let combs = initial_situation.shoe[dealer_up_card] as u32;
+            initial_situation.shoe.remove_card(dealer_up_card);
+            for first_hand_card in 1..=10 {
+                if initial_situation.shoe[first_hand_card] == 0 {
+                    continue;
+                }
+                initial_situation.hand_cards.0 = first_hand_card;
+                let combs = combs * initial_situation.shoe[first_hand_card] as u32;
+                initial_situation.shoe.remove_card(first_hand_card);
+                for second_hand_card in 1..=first_hand_card {
+                    // Initial situations that cannot be dealt from the shoe are left unsolved.
+                    if initial_situation.shoe[second_hand_card] == 0 {
+                        continue;
+                    }
+                    let idx55 = PREFIX_SUM[(first_hand_card - 1) as usize]
+                        + (second_hand_card - 1) as usize;
+                    initial_situation.hand_cards.1 = second_hand_card;
+                    let mut combs = combs * initial_situation.shoe[second_hand_card] as u32;
+                    if second_hand_card != first_hand_card {
+                        combs *= 2;
+                    }
+                    let combs = combs;
+                    initial_situation.shoe.remove_card(second_hand_card);
+
+                    // Core logic
+                    let p = combs as f64 / total_combs;
+                    let ex_other = calculate_expectations(
+                        number_of_threads,
+                        rule,
+                        &self.options,
+                        &initial_situation,
+                        &mut self.exs_stand_hit[idx10],
+                    );
+                    self.exs_other_decisions[idx10][idx55] = ex_other;
+                    self.ex_total_summary += p * ex_other.ex_summary;
+
+                    initial_situation.shoe.add_card(second_hand_card);
+                }
+                initial_situation.shoe.add_card(first_hand_card);
+            }
+            initial_situation.shoe.add_card(dealer_up_card);
+        }
+    }
+
     fn get_solution_for_initial_situation_aux(
         &self,
-        mut hand: (u8, u8),
+        hand: (u8, u8),
         dealer_up_card: u8,
     ) -> SolutionForInitialSituation {
-        if hand.0 < hand.1 {
-            (hand.0, hand.1) = (hand.1, hand.0);
-        }
-        let a = (hand.0 - 1) as usize;
-        let b = (hand.1 - 1) as usize;
         let d = (dealer_up_card - 1) as usize;
-        let other = &self.exs_other_decisions[d][PREFIX_SUM[a] + b];
+        let other = &self.exs_other_decisions[d][get_initial_hand_index(hand)];
         SolutionForInitialSituation {
             ex_stand_hit: Default::default(),
             ex_double: other.ex_double,
+            double_second_moment: other.double_second_moment,
             ex_split: other.ex_split,
             ex_extra_insurance: other.ex_extra_insurance,
             ex_summary: other.ex_summary,
@@ -162,6 +620,23 @@ impl SolutionForBettingPhase {
     }
 }
 
+/// Returns the shoe after dealing the given initial hand and dealer up card, or None if they
+/// cannot be dealt from the shoe.
+pub(crate) fn remove_initial_cards(
+    shoe: &CardCount,
+    hand_cards: (u8, u8),
+    dealer_up_card: u8,
+) -> Option<CardCount> {
+    let mut shoe = *shoe;
+    for card in [dealer_up_card, hand_cards.0, hand_cards.1] {
+        if shoe[card] == 0 {
+            return None;
+        }
+        shoe.remove_card(card);
+    }
+    Some(shoe)
+}
+
 fn get_card_probability(shoe: &CardCount, impossible_dealer_hole_card: u8, target_card: u8) -> f64 {
     let total = shoe.get_total() as f64;
     let target_number = shoe[target_card] as f64;
@@ -177,7 +652,8 @@ fn get_card_probability(shoe: &CardCount, impossible_dealer_hole_card: u8, targe
         }
     };
     let shoe_total_minus_one = (shoe.get_total() - 1) as f64;
-    let p1 = p_hole_card_is_target_card * (shoe[target_card] - 1) as f64 / shoe_total_minus_one;
+    let p1 = p_hole_card_is_target_card * shoe[target_card].saturating_sub(1) as f64
+        / shoe_total_minus_one;
     let p2 = (1.0 - p_hole_card_is_target_card) * target_number / shoe_total_minus_one;
     p1 + p2
 }
@@ -197,8 +673,26 @@ fn get_impossible_dealer_hole_card(rule: &Rule, dealer_up_card: u8) -> u8 {
     }
 }
 
+/// The maximum number of threads used by the solvers. 0 means no limit.
+static MAX_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the maximum number of threads used by the solvers, which is useful on shared machines.
+/// Any requested number of threads (including the one got from
+/// `std::thread::available_parallelism` when 0 is requested) is clamped to this maximum. Setting
+/// it to 0 removes the limit.
+pub fn set_max_threads(max_threads: usize) {
+    MAX_THREADS.store(max_threads, atomic::Ordering::Relaxed);
+}
+
 fn get_number_of_threads(number_of_threads: usize) -> usize {
-    if number_of_threads == 0 {
+    clamp_number_of_threads(
+        number_of_threads,
+        MAX_THREADS.load(atomic::Ordering::Relaxed),
+    )
+}
+
+fn clamp_number_of_threads(number_of_threads: usize, max_threads: usize) -> usize {
+    let number_of_threads = if number_of_threads == 0 {
         let ret = std::thread::available_parallelism();
         match ret {
             Ok(x) => x.get(),
@@ -206,6 +700,33 @@ fn get_number_of_threads(number_of_threads: usize) -> usize {
         }
     } else {
         number_of_threads
+    };
+
+    match max_threads {
+        0 => number_of_threads,
+        max_threads => number_of_threads.min(max_threads),
+    }
+}
+
+/// Options to skip parts of the solver for speed, e.g. when only Hit, Stand and Double decisions
+/// are needed to generate a simple chart.
+#[derive(Debug, Clone, Copy)]
+pub struct SolveOptions {
+    /// Whether to calculate the expectation of Split. If not, its expectation is -inf and the
+    /// summary assumes players never split.
+    pub compute_split: bool,
+
+    /// Whether to calculate the expectation of the side bet "Buy Insurance". If not, its
+    /// expectation is -inf and the summary assumes players never buy insurance.
+    pub compute_insurance: bool,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        SolveOptions {
+            compute_split: true,
+            compute_insurance: true,
+        }
     }
 }
 
@@ -215,56 +736,152 @@ pub fn calculate_solution_without_initial_situation(
     rule: &Rule,
     shoe: &CardCount,
 ) -> SolutionForBettingPhase {
-    let number_of_threads = get_number_of_threads(number_of_threads);
-    let mut solution: SolutionForBettingPhase = Default::default();
+    calculate_solution_without_initial_situation_with_options(
+        number_of_threads,
+        rule,
+        shoe,
+        &Default::default(),
+    )
+}
 
-    let mut initial_situation = InitialSituation::new(*shoe, (1, 1), 1);
-    let total_combs = rule.number_of_decks as u32 * 52;
-    let total_combs = total_combs * (total_combs - 1) * (total_combs - 2);
-    let total_combs = total_combs as f64;
-    // Enumerate all possible combinations.
-    for dealer_up_card in 1..=10 {
-        let idx10 = (dealer_up_card - 1) as usize;
-        initial_situation.dealer_up_card = dealer_up_card;
-        let combs = initial_situation.shoe[dealer_up_card] as u32;
-        initial_situation.shoe.remove_card(dealer_up_card);
-        for first_hand_card in 1..=10 {
-            initial_situation.hand_cards.0 = first_hand_card;
-            let combs = combs * initial_situation.shoe[first_hand_card] as u32;
-            initial_situation.shoe.remove_card(first_hand_card);
-            for second_hand_card in 1..=first_hand_card {
-                let idx55 =
-                    PREFIX_SUM[(first_hand_card - 1) as usize] + (second_hand_card - 1) as usize;
-                initial_situation.hand_cards.1 = second_hand_card;
-                let mut combs = combs * initial_situation.shoe[second_hand_card] as u32;
-                if second_hand_card != first_hand_card {
-                    combs *= 2;
-                }
-                let combs = combs;
-                initial_situation.shoe.remove_card(second_hand_card);
-
-                // Core logic
-                let p = combs as f64 / total_combs;
-                let ex_other = calculate_expectations(
-                    number_of_threads,
-                    rule,
-                    &initial_situation,
-                    &mut solution.exs_stand_hit[idx10],
-                );
-                solution.exs_other_decisions[idx10][idx55] = ex_other;
-                solution.ex_total_summary += p * ex_other.ex_summary;
+/// Same as `calculate_solution_without_initial_situation`, but parts of the solver can be skipped
+/// by the given options.
+pub fn calculate_solution_without_initial_situation_with_options(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+    options: &SolveOptions,
+) -> SolutionForBettingPhase {
+    let mut solution = SolutionForBettingPhase {
+        shoe: *shoe,
+        options: *options,
+        ..Default::default()
+    };
+    solution.solve(get_number_of_threads(number_of_threads), rule);
+    solution
+}
 
-                initial_situation.shoe.add_card(second_hand_card);
-            }
-            initial_situation.shoe.add_card(first_hand_card);
+/// Calculates the expectation of the money you win in a round (i.e., your profit), where
+/// `main_bet` is placed on the main game and `side_bet` is placed on the given side bet.
+pub fn combined_ev(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+    main_bet: u32,
+    side_bet: u32,
+    side: SideBet,
+) -> f64 {
+    let ex_main = {
+        if main_bet == 0 {
+            0.0
+        } else {
+            calculate_solution_without_initial_situation(number_of_threads, rule, shoe)
+                .get_total_expectation()
         }
-        initial_situation.shoe.add_card(dealer_up_card);
-    }
+    };
+    let ex_side = {
+        if side_bet == 0 {
+            0.0
+        } else {
+            side_bet_expectation(side, shoe)
+        }
+    };
+    main_bet as f64 * ex_main + side_bet as f64 * ex_side
+}
 
-    solution
+/// Returns how much expectation of a fresh shoe the player loses when a natural pays 6:5 instead
+/// of 3:2, with all the other rules taken from `base_rule`.
+pub fn blackjack_payout_cost(number_of_threads: usize, base_rule: &Rule) -> f64 {
+    let shoe = CardCount::with_number_of_decks(base_rule.number_of_decks);
+    blackjack_payout_cost_of_shoe(number_of_threads, base_rule, &shoe)
+}
+
+/// Same as `blackjack_payout_cost`, but for the given shoe instead of a fresh one.
+fn blackjack_payout_cost_of_shoe(
+    number_of_threads: usize,
+    base_rule: &Rule,
+    shoe: &CardCount,
+) -> f64 {
+    let get_expectation = |payout_blackjack: f64| {
+        let rule = Rule {
+            payout_blackjack,
+            ..*base_rule
+        };
+        calculate_solution_without_initial_situation(number_of_threads, &rule, shoe)
+            .get_total_expectation()
+    };
+    get_expectation(1.5) - get_expectation(1.2)
+}
+
+/// Returns the house edge (i.e., the negated expectation of a fresh shoe) for each of the given
+/// numbers of decks, with all the other rules taken from `base_rule`.
+pub fn deck_count_ev_curve(
+    number_of_threads: usize,
+    base_rule: &Rule,
+    decks: &[u8],
+) -> Vec<(u8, f64)> {
+    let deck = CardCount::with_number_of_decks(1);
+    shoe_count_ev_curve(number_of_threads, base_rule, &deck, decks)
+}
+
+/// Same as `deck_count_ev_curve`, but each shoe is made of copies of the given `deck` instead of
+/// standard decks.
+fn shoe_count_ev_curve(
+    number_of_threads: usize,
+    base_rule: &Rule,
+    deck: &CardCount,
+    decks: &[u8],
+) -> Vec<(u8, f64)> {
+    decks
+        .iter()
+        .map(|&number_of_decks| {
+            let rule = Rule {
+                number_of_decks,
+                ..*base_rule
+            };
+            let mut counts = [0; 10];
+            for (card, count) in (1..=10).zip(&mut counts) {
+                *count = deck[card] * number_of_decks as u16;
+            }
+            let shoe = CardCount::new(&counts);
+            let ex = calculate_solution_without_initial_situation(number_of_threads, &rule, &shoe)
+                .get_total_expectation();
+            (number_of_decks, -ex)
+        })
+        .collect()
+}
+
+/// Returns the house edge (i.e., the negated expectation of a fresh shoe) under each peek policy,
+/// with all the other rules taken from `base_rule`.
+pub fn peek_policy_ev(number_of_threads: usize, base_rule: &Rule) -> Vec<(PeekPolicy, f64)> {
+    let shoe = CardCount::with_number_of_decks(base_rule.number_of_decks);
+    peek_policy_ev_of_shoe(number_of_threads, base_rule, &shoe)
+}
+
+/// Same as `peek_policy_ev`, but for the given shoe instead of a fresh one.
+fn peek_policy_ev_of_shoe(
+    number_of_threads: usize,
+    base_rule: &Rule,
+    shoe: &CardCount,
+) -> Vec<(PeekPolicy, f64)> {
+    [
+        PeekPolicy::UpAceOrTen,
+        PeekPolicy::UpAce,
+        PeekPolicy::NoPeek,
+    ]
+    .into_iter()
+    .map(|peek_policy| {
+        let rule = Rule {
+            peek_policy,
+            ..*base_rule
+        };
+        let ex = calculate_solution_without_initial_situation(number_of_threads, &rule, shoe)
+            .get_total_expectation();
+        (peek_policy, -ex)
+    })
+    .collect()
 }
 
-/// Note that this function hasn't considered Split yet.
 pub fn calculate_solution_with_initial_situation(
     number_of_threads: usize,
     rule: &Rule,
@@ -277,20 +894,241 @@ pub fn calculate_solution_with_initial_situation(
     let exs_other = calculate_expectations(
         number_of_threads,
         rule,
+        &Default::default(),
         initial_situation,
         &mut ex_stand_hit,
     );
 
-    // TODO: Calculate the expectation when able to split.
     SolutionForInitialSituation {
         ex_stand_hit,
         ex_double: exs_other.ex_double,
+        double_second_moment: exs_other.double_second_moment,
         ex_split: exs_other.ex_split,
         ex_extra_insurance: exs_other.ex_extra_insurance,
         ex_summary: exs_other.ex_summary,
     }
 }
 
+/// Calculates the expectations of Stand and Hit of the initial hand of the given initial situation
+/// and of all the hands it can reach, using the float type `F`. Unlike
+/// `calculate_solution_with_initial_situation`, only Stand and Hit are solved and a single thread
+/// is used, but `f32` can be chosen to trade a little precision for memory and speed.
+pub fn calculate_stand_hit_expectation<F: Float>(
+    rule: &Rule,
+    initial_situation: &InitialSituation,
+) -> StateArray<Expectation<F>> {
+    let mut initial_hand = CardCount::with_number_of_decks(0);
+    initial_hand.add_card(initial_situation.hand_cards.0);
+    initial_hand.add_card(initial_situation.hand_cards.1);
+    let mut shoe = initial_situation.shoe;
+    let impossible_dealer_hole_card =
+        get_impossible_dealer_hole_card(rule, initial_situation.dealer_up_card);
+
+    let mut ex_stand_hit = StateArray::new();
+    memoization_calculate_stand_hit_expectation(
+        rule,
+        &initial_situation.dealer_up_card,
+        &impossible_dealer_hole_card,
+        &mut shoe,
+        &mut initial_hand,
+        &mut ex_stand_hit,
+    );
+    ex_stand_hit
+}
+
+/// Returns the probability of each unordered two-card starting hand (with the larger card first)
+/// dealt from the given shoe.
+pub fn starting_hand_frequencies(shoe: &CardCount) -> Vec<((u8, u8), f64)> {
+    let total = shoe.get_total() as f64;
+    let total_combs = total * (total - 1.0);
+    let mut frequencies = Vec::with_capacity(55);
+    for first_hand_card in 1..=10 {
+        for second_hand_card in 1..=first_hand_card {
+            let combs = if first_hand_card == second_hand_card {
+                shoe[first_hand_card] as f64 * shoe[first_hand_card].saturating_sub(1) as f64
+            } else {
+                2.0 * shoe[first_hand_card] as f64 * shoe[second_hand_card] as f64
+            };
+            frequencies.push(((first_hand_card, second_hand_card), combs / total_combs));
+        }
+    }
+    frequencies
+}
+
+/// Returns the number of player hand states reachable by drawing from the given shoe, starting
+/// from an empty hand, i.e., the size of the state space the solver works on for each dealer up
+/// card. A hand stops drawing once it reaches 21, busts or reaches the Charlie number. No
+/// expectation is computed, so this is cheap even for a full shoe.
+pub fn estimate_state_count(rule: &Rule, shoe: &CardCount) -> usize {
+    let feature_fn = |c: &'_ CardCount| c.get_total() as usize;
+    calculation_states::gather_hand_count_states(
+        &CardCount::with_number_of_decks(0),
+        shoe,
+        rule.charlie_number,
+        feature_fn,
+        &StateArray::<()>::new(),
+    )
+    .iter()
+    .map(|states| states.len())
+    .sum()
+}
+
+/// Calculates the expectations of Stand and Hit of the given player hand against each dealer up
+/// card (indexed by up card - 1). The shoe should contain the cards remaining after the hand is
+/// dealt, and each up card is removed from it in turn. If the dealer peeks, the expectations are
+/// given when the dealer doesn't have a natural. Up cards that are not in the shoe are left with
+/// the default expectation.
+///
+/// This is a convenience for studying one hand, not a shortcut: each up card runs its own Stand
+/// and Hit recursion from the hand, and no dealer work is shared between up cards, since the
+/// dealer outcomes depend on the up card and the shoe it leaves. It is still much cheaper than
+/// solving each initial situation, as Double, Split and the other hands are not solved.
+pub fn hand_vs_all_upcards(rule: &Rule, shoe: &CardCount, hand: &CardCount) -> [Expectation; 10] {
+    let mut exs = [Expectation::default(); 10];
+    for dealer_up_card in 1..=10 {
+        if shoe[dealer_up_card] == 0 {
+            continue;
+        }
+        let mut current_shoe = *shoe;
+        current_shoe.remove_card(dealer_up_card);
+        let mut current_hand = *hand;
+        let impossible_dealer_hole_card = get_impossible_dealer_hole_card(rule, dealer_up_card);
+
+        let mut ex_stand_hit = StateArray::new();
+        memoization_calculate_stand_hit_expectation(
+            rule,
+            &dealer_up_card,
+            &impossible_dealer_hole_card,
+            &mut current_shoe,
+            &mut current_hand,
+            &mut ex_stand_hit,
+        );
+        exs[(dealer_up_card - 1) as usize] = ex_stand_hit[hand];
+    }
+    exs
+}
+
+/// Calculates the expectation of the best of Stand and Hit with a hard 16 made of 10 and 6 against
+/// a dealer 10, with the given number of decks, dealer standing on soft 17 and peeking for a
+/// natural (the expectation is given when the dealer doesn't have a natural). Double, Split,
+/// Surrender and Charlie are not considered.
+///
+/// This is a correctness anchor for the solver: it plainly enumerates every draw of the player
+/// and the dealer without sharing any code with the solver. As a sanity check, the Wizard of
+/// Odds' infinite deck hit / stand tables give -0.5398 for Hit and -0.5404 for Stand with 16
+/// against 10, which the exact figures for a finite shoe approach as the number of decks grows.
+pub fn reference_ev_16_vs_10(decks: u8) -> f64 {
+    let mut shoe = CardCount::with_number_of_decks(decks);
+    for card in [10, 6, 10] {
+        shoe.remove_card(card);
+    }
+    get_reference_ex(&mut shoe, 16)
+}
+
+/// Returns the expectation of the best of Stand and Hit with the given hard total against a dealer
+/// 10, whose hole card is any card of the shoe but an Ace.
+fn get_reference_ex(shoe: &mut CardCount, total: u16) -> f64 {
+    let n = shoe.get_total() as f64;
+    let n_ace = shoe[1] as f64;
+
+    // The hole card is equally likely to be any non-Ace card of the shoe.
+    let mut ex_stand = 0.0;
+    for hole_card in 2..=10 {
+        if shoe[hole_card] == 0 {
+            continue;
+        }
+        let p = shoe[hole_card] as f64 / (n - n_ace);
+        shoe.remove_card(hole_card);
+        ex_stand += p * get_reference_stand_ex(shoe, total, 10 + hole_card as u16, false);
+        shoe.add_card(hole_card);
+    }
+
+    // The next card and the hole card are two different cards of the shoe, given the hole card is
+    // not an Ace.
+    let mut ex_hit = 0.0;
+    for card in 1..=10 {
+        if shoe[card] == 0 {
+            continue;
+        }
+        let n_non_ace_hole_cards = n - 1.0 - n_ace + if card == 1 { 1.0 } else { 0.0 };
+        let p = shoe[card] as f64 * n_non_ace_hole_cards / ((n - 1.0) * (n - n_ace));
+        let new_total = total + card as u16;
+        if new_total > 21 {
+            ex_hit -= p;
+            continue;
+        }
+        shoe.remove_card(card);
+        ex_hit += p * get_reference_ex(shoe, new_total);
+        shoe.add_card(card);
+    }
+    ex_stand.max(ex_hit)
+}
+
+/// Returns the expectation of standing on the given player total against the dealer hand with the
+/// given hard total, where `has_ace` tells whether it has an Ace.
+fn get_reference_stand_ex(
+    shoe: &mut CardCount,
+    total: u16,
+    dealer_total: u16,
+    has_ace: bool,
+) -> f64 {
+    let dealer_soft_total = if has_ace && dealer_total + 10 <= 21 {
+        dealer_total + 10
+    } else {
+        dealer_total
+    };
+    if dealer_total > 21 {
+        return 1.0;
+    }
+    if dealer_soft_total >= 17 {
+        return match total.cmp(&dealer_soft_total) {
+            std::cmp::Ordering::Greater => 1.0,
+            std::cmp::Ordering::Equal => 0.0,
+            std::cmp::Ordering::Less => -1.0,
+        };
+    }
+
+    let n = shoe.get_total() as f64;
+    let mut ex = 0.0;
+    for card in 1..=10 {
+        if shoe[card] == 0 {
+            continue;
+        }
+        let p = shoe[card] as f64 / n;
+        shoe.remove_card(card);
+        ex += p * get_reference_stand_ex(
+            shoe,
+            total,
+            dealer_total + card as u16,
+            has_ace || card == 1,
+        );
+        shoe.add_card(card);
+    }
+    ex
+}
+
+/// Calculates the expectation of making the given first decision on the initial hand, and then
+/// playing optimally afterwards, e.g., to find out how much always hitting 12 vs 2 costs. Here
+/// `shoe` is the shoe after the initial cards are dealt, and the expectation is given when the
+/// round doesn't end early (i.e., the dealer doesn't have a natural). Returns -inf if the forced
+/// decision is not possible.
+pub fn ev_with_forced_first_decision(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+    hand: (u8, u8),
+    dealer_up_card: u8,
+    forced: Decision,
+) -> f64 {
+    let initial_situation = InitialSituation::new(*shoe, hand, dealer_up_card);
+    let solution =
+        calculate_solution_with_initial_situation(number_of_threads, rule, &initial_situation);
+    let mut initial_hand = CardCount::with_number_of_decks(0);
+    initial_hand.add_card(hand.0);
+    initial_hand.add_card(hand.1);
+    solution.decision_expectation(&initial_hand, rule, forced)
+}
+
 // Updates the expectations of Stand and Hit in the input parameter ex_stand_hit.
 // Returns the expectations of other decisions in the return value.
 // If the given number_of_threads is 0, the function will use
@@ -298,6 +1136,7 @@ pub fn calculate_solution_with_initial_situation(
 fn calculate_expectations(
     number_of_threads: usize,
     rule: &Rule,
+    options: &SolveOptions,
     initial_situation: &InitialSituation,
     ex_stand_hit: &mut StateArray<Expectation>,
 ) -> ExsOtherDecisions {
@@ -331,12 +1170,16 @@ fn calculate_expectations(
     }
 
     // Calculate expectation of Double.
-    let ex_double = {
-        if initial_hand.is_natural() {
-            -f64::INFINITY
+    let (ex_double, double_second_moment) = {
+        if initial_hand.is_natural() || !rule.double_policy.allows(&initial_hand) {
+            (-f64::INFINITY, 0.0)
         } else {
             let mut ex_double = 0.0;
+            let mut double_second_moment = 0.0;
             for third_card in 1..=10 {
+                if initial_situation.shoe[third_card] == 0 {
+                    continue;
+                }
                 initial_hand.add_card(third_card);
                 let p = get_card_probability(
                     &initial_situation.shoe,
@@ -344,13 +1187,21 @@ fn calculate_expectations(
                     third_card,
                 );
                 ex_double += p * ex_stand_hit[&initial_hand].stand;
+                double_second_moment += p * ex_stand_hit[&initial_hand].stand_second_moment;
                 initial_hand.remove_card(third_card);
             }
-            ex_double * 2.0
+            (ex_double * 2.0, double_second_moment * 4.0)
         }
     };
 
-    // TODO: Calculate expectation of Split
+    // Calculate expectation of Split.
+    let ex_split = {
+        if options.compute_split {
+            calculate_split_expectation_with_limit(rule, initial_situation, rule.split_all_limits)
+        } else {
+            -f64::INFINITY
+        }
+    };
 
     // Calculate extra expectation of side bet "Buy Insurance".
     let p_early_end = {
@@ -360,12 +1211,23 @@ fn calculate_expectations(
             get_card_probability(&initial_situation.shoe, 0, impossible_dealer_hole_card)
         }
     };
-    let ex_extra_insurance = p_early_end * rule.payout_insurance - (1.0 - p_early_end);
+    let ex_extra_insurance = {
+        if options.compute_insurance {
+            p_early_end * rule.payout_insurance - (1.0 - p_early_end)
+        } else {
+            -f64::INFINITY
+        }
+    };
 
-    // Calculate expectation summary.
+    // Calculate expectation summary. When the game ends early because dealer peeks and gets a
+    // natural, player cannot make any decision, so Surrender only applies to ex_no_early_end.
     let mut ex_early_end = {
         if initial_hand.is_natural() {
-            0.0
+            // The player natural against the dealer natural.
+            match rule.player_natural_vs_dealer_natural {
+                PushOrLose::Push => 0.0,
+                PushOrLose::Lose => -1.0,
+            }
         } else {
             -1.0
         }
@@ -375,18 +1237,21 @@ fn calculate_expectations(
         ex_early_end += ex_extra_insurance * 0.5;
     }
     let ex_no_early_end = {
-        let (mut ex, _) = get_max_expectation(&ex_stand_hit, &initial_hand, rule);
+        let (mut ex, _) = get_max_expectation(ex_stand_hit, &initial_hand, rule);
         if ex < ex_double {
             ex = ex_double;
         }
-        // TODO: Compare Split EX here.
+        if ex < ex_split {
+            ex = ex_split;
+        }
         ex
     };
     let ex_summary = p_early_end * ex_early_end + (1.0 - p_early_end) * ex_no_early_end;
 
     ExsOtherDecisions {
         ex_double,
-        ex_split: -f64::INFINITY,
+        double_second_moment,
+        ex_split,
         ex_extra_insurance,
         ex_summary,
     }
@@ -425,30 +1290,34 @@ fn multithreading_calculate_stand_hit_expectation(
             if pair.hand.bust() {
                 ex_stand_hit[&pair.hand] = Expectation {
                     stand: -1.0,
+                    stand_second_moment: 1.0,
                     ..Default::default()
                 };
                 continue;
             }
 
             // Obvious case 2: Charlie number reached.
-            if pair.hand.get_total() == rule.charlie_number as u16 {
+            if rule.reached_charlie(&pair.hand) {
                 ex_stand_hit[&pair.hand] = Expectation {
                     stand: 1.0,
+                    stand_second_moment: 1.0,
                     ..Default::default()
                 };
                 continue;
             }
 
-            if pair.hand.get_actual_sum() <= 11 && pair.hand.get_total() != 3 {
+            if pair.hand.get_actual_sum() <= 11 && pair.hand.get_total() > 3 {
                 ex_stand_hit[&pair.hand] = Expectation {
                     stand: -f64::INFINITY,
                     hit: 0.0,
+                    ..Default::default()
                 };
                 continue;
             }
             ex_stand_hit[&pair.hand] = Expectation {
                 stand: 0.0,
                 hit: 0.0,
+                ..Default::default()
             };
 
             // Obvious case 3: Current actual sum is 21. Stand!
@@ -478,27 +1347,18 @@ fn multithreading_calculate_stand_hit_expectation(
                 unsafe {
                     // This is OK, since the threads are not modifying the same memory.
                     let ex_stand_hit = &mut *(raw_ex_stand_hit as *mut StateArray<Expectation>);
-                    ex_stand_hit[&pair.hand].stand = {
-                        if pair.hand.is_natural() {
-                            stand_odds.win * rule.payout_blackjack - stand_odds.lose
-                        } else {
-                            stand_odds.win - stand_odds.lose
-                        }
-                    };
+                    let ex = &mut ex_stand_hit[&pair.hand];
+                    (ex.stand, ex.stand_second_moment) =
+                        get_stand_moments(&rule, &pair.hand, &stand_odds);
                 }
             }
         });
         threads.push(thread);
     }
     for pair in dispatched_hands.first().unwrap() {
-        let stand_odds = calculate_stand_odds(&rule, &pair.hand, &dealer_up_card, &pair.shoe);
-        ex_stand_hit[&pair.hand].stand = {
-            if pair.hand.is_natural() {
-                stand_odds.win * rule.payout_blackjack - stand_odds.lose
-            } else {
-                stand_odds.win - stand_odds.lose
-            }
-        };
+        let stand_odds = calculate_stand_odds(rule, &pair.hand, &dealer_up_card, &pair.shoe);
+        let ex = &mut ex_stand_hit[&pair.hand];
+        (ex.stand, ex.stand_second_moment) = get_stand_moments(rule, &pair.hand, &stand_odds);
     }
     for thread in threads {
         let _ = thread.join();
@@ -516,16 +1376,18 @@ fn multithreading_calculate_stand_hit_expectation(
                     continue;
                 }
                 pair.hand.add_card(next_card);
-                let (ex_max, _) = get_max_expectation(ex_stand_hit, &pair.hand, rule);
+                let (ex_max, decision) = get_max_expectation(ex_stand_hit, &pair.hand, rule);
+                let second_moment = get_second_moment(ex_stand_hit, &pair.hand, rule, decision);
                 pair.hand.remove_card(next_card);
                 let p = get_card_probability(&pair.shoe, impossible_dealer_hole_card, next_card);
                 ex_stand_hit[&pair.hand].hit += p * ex_max;
+                ex_stand_hit[&pair.hand].hit_second_moment += p * second_moment;
             }
         }
     }
 }
 
-fn memoization_calculate_stand_hit_expectation(
+fn memoization_calculate_stand_hit_expectation<F: Float>(
     // Input parameters
     rule: &Rule,
     dealer_up_card: &u8,
@@ -536,7 +1398,7 @@ fn memoization_calculate_stand_hit_expectation(
     current_hand: &mut CardCount,
 
     // Output parameters
-    ex_stand_hit: &mut StateArray<Expectation>,
+    ex_stand_hit: &mut StateArray<Expectation<F>>,
 ) {
     if ex_stand_hit.contains_state(current_hand) {
         return;
@@ -545,16 +1407,18 @@ fn memoization_calculate_stand_hit_expectation(
     // Obvious case 1: Bust
     if current_hand.bust() {
         ex_stand_hit[current_hand] = Expectation {
-            stand: -1.0,
+            stand: F::from_f64(-1.0),
+            stand_second_moment: F::from_f64(1.0),
             ..Default::default()
         };
         return;
     }
 
     // Obvious case 2: Charlie number reached.
-    if current_hand.get_total() == rule.charlie_number as u16 {
+    if rule.reached_charlie(current_hand) {
         ex_stand_hit[current_hand] = Expectation {
-            stand: 1.0,
+            stand: F::from_f64(1.0),
+            stand_second_moment: F::from_f64(1.0),
             ..Default::default()
         };
         return;
@@ -563,16 +1427,10 @@ fn memoization_calculate_stand_hit_expectation(
     // Obvious case 3: Current actual sum is 21. Stand!
     if current_hand.get_actual_sum() == 21 {
         let stand_odds = calculate_stand_odds(rule, current_hand, dealer_up_card, current_shoe);
-
-        let stand = {
-            if current_hand.is_natural() {
-                stand_odds.win * rule.payout_blackjack - stand_odds.lose
-            } else {
-                stand_odds.win - stand_odds.lose
-            }
-        };
+        let (stand, stand_second_moment) = get_stand_moments(rule, current_hand, &stand_odds);
         ex_stand_hit[current_hand] = Expectation {
             stand,
+            stand_second_moment,
             ..Default::default()
         };
         return;
@@ -580,7 +1438,7 @@ fn memoization_calculate_stand_hit_expectation(
 
     // End of obvious cases. Calculate expectation of Hit using theory of total expectation.
     ex_stand_hit[current_hand] = Expectation {
-        hit: 0.0,
+        hit: F::default(),
         ..Default::default()
     };
 
@@ -601,48 +1459,54 @@ fn memoization_calculate_stand_hit_expectation(
             ex_stand_hit,
         );
 
-        let (ex_max, _) = get_max_expectation(ex_stand_hit, current_hand, rule);
+        let (ex_max, decision) = get_max_expectation(ex_stand_hit, current_hand, rule);
+        let second_moment = get_second_moment(ex_stand_hit, current_hand, rule, decision);
 
         current_hand.remove_card(i);
         current_shoe.add_card(i);
 
-        let p = get_card_probability(current_shoe, *impossible_dealer_hole_card, i);
+        let p = F::from_f64(get_card_probability(
+            current_shoe,
+            *impossible_dealer_hole_card,
+            i,
+        ));
         ex_stand_hit[current_hand].hit += p * ex_max;
+        ex_stand_hit[current_hand].hit_second_moment += p * second_moment;
     }
 
     // Calculate expectation of Stand.
-    ex_stand_hit[current_hand].stand = {
-        // Optimization here. No need to calculate stand odds when player's hands is <= 11 and total number of cards != 3, because
-        // in this case, player should obviously hit.
-        // When total number of cards is 3, we still need to calculate stand odds, because the stand expectation is used to
-        // calculate double expectation.
-        if current_hand.get_actual_sum() <= 11 && current_hand.get_total() != 3 {
-            -f64::INFINITY
-        } else {
-            let stand_odds = calculate_stand_odds(rule, current_hand, dealer_up_card, current_shoe);
-            stand_odds.win - stand_odds.lose
-        }
-    };
+    // Optimization here. No need to calculate stand odds when player's hands is <= 11 and total number of cards > 3, because
+    // in this case, player should obviously hit.
+    // When total number of cards is 3, we still need to calculate stand odds, because the stand expectation is used to
+    // calculate double expectation. When it is 2, the hand is the initial hand, whose stand expectation is reported even
+    // though it is never the best.
+    if current_hand.get_actual_sum() <= 11 && current_hand.get_total() > 3 {
+        ex_stand_hit[current_hand].stand = F::NEG_INFINITY;
+    } else {
+        let stand_odds = calculate_stand_odds(rule, current_hand, dealer_up_card, current_shoe);
+        let ex = &mut ex_stand_hit[current_hand];
+        (ex.stand, ex.stand_second_moment) = get_stand_moments(rule, current_hand, &stand_odds);
+    }
 }
 
 #[derive(Clone, Copy, Default, Debug)]
-struct WinLoseCasesOdds {
-    win: f64,
-    push: f64,
-    lose: f64,
+struct WinLoseCasesOdds<F: Float = f64> {
+    win: F,
+    push: F,
+    lose: F,
 }
 
-impl ops::AddAssign<&WinLoseCasesOdds> for WinLoseCasesOdds {
-    fn add_assign(&mut self, rhs: &WinLoseCasesOdds) {
+impl<F: Float> ops::AddAssign<&WinLoseCasesOdds<F>> for WinLoseCasesOdds<F> {
+    fn add_assign(&mut self, rhs: &WinLoseCasesOdds<F>) {
         self.win += rhs.win;
         self.push += rhs.push;
         self.lose += rhs.lose;
     }
 }
 
-impl ops::Mul<f64> for WinLoseCasesOdds {
-    type Output = WinLoseCasesOdds;
-    fn mul(self, rhs: f64) -> Self::Output {
+impl<F: Float> ops::Mul<F> for WinLoseCasesOdds<F> {
+    type Output = WinLoseCasesOdds<F>;
+    fn mul(self, rhs: F) -> Self::Output {
         WinLoseCasesOdds {
             win: self.win * rhs,
             push: self.push * rhs,
@@ -651,12 +1515,225 @@ impl ops::Mul<f64> for WinLoseCasesOdds {
     }
 }
 
-fn calculate_stand_odds(
+/// Returns the probabilities of winning, pushing and losing when the player stands on the given
+/// hand. The shoe should contain the cards remaining, i.e., the player hand and dealer up card
+/// must have been removed. If the dealer peeks, the probabilities are conditioned on the dealer not
+/// having a natural, except when the player has a natural. Charlie is not considered.
+pub fn outcome_probabilities(
+    rule: &Rule,
+    shoe: &CardCount,
+    hand: &CardCount,
+    dealer_up_card: u8,
+) -> (f64, f64, f64) {
+    if hand.bust() {
+        return (0.0, 0.0, 1.0);
+    }
+    let odds = calculate_stand_odds(rule, hand, &dealer_up_card, shoe);
+    (odds.win, odds.push, odds.lose)
+}
+
+/// Returns the probabilities of the final hands of the dealer when the player stands, i.e., bust,
+/// 17, 18, 19, 20, 21, natural Blackjack and any total below 17 (only possible when
+/// `dealer_stand_value` is below 17), in this order, so they always sum to 1. The shoe should
+/// contain the cards remaining, i.e., the dealer up card (and the player hand) must have been
+/// removed. If the dealer peeks, the probabilities are conditioned on the dealer not having a
+/// natural.
+pub fn dealer_total_given_standing(rule: &Rule, shoe: &CardCount, dealer_up_card: u8) -> [f64; 8] {
+    let impossible_dealer_hole_card = get_impossible_dealer_hole_card(rule, dealer_up_card);
+    let valid_shoe_total = match impossible_dealer_hole_card {
+        0 => shoe.get_total(),
+        card => shoe.get_total() - shoe[card],
+    } as f64;
+
+    let mut probabilities = [0.0; 8];
+    let mut shoe = *shoe;
+    let mut dealer_hand = CardCount::with_number_of_decks(0);
+    dealer_hand.add_card(dealer_up_card);
+    let mut memo = StateArray::new();
+    for hole_card in 1..=10 {
+        if shoe[hole_card] == 0 || hole_card == impossible_dealer_hole_card {
+            continue;
+        }
+        let p = shoe[hole_card] as f64 / valid_shoe_total;
+        shoe.remove_card(hole_card);
+        dealer_hand.add_card(hole_card);
+        let sub =
+            memoization_dealer_total_probabilities(rule, &mut shoe, &mut dealer_hand, &mut memo);
+        dealer_hand.remove_card(hole_card);
+        shoe.add_card(hole_card);
+        for (probability, sub) in probabilities.iter_mut().zip(sub) {
+            *probability += p * sub;
+        }
+    }
+    probabilities
+}
+
+/// Returns the probabilities of the final hands of the dealer (see `dealer_total_given_standing`)
+/// starting from the given dealer hand, which has at least two cards.
+fn memoization_dealer_total_probabilities(
+    rule: &Rule,
+    current_shoe: &mut CardCount,
+    dealer_hand: &mut CardCount,
+    memo: &mut StateArray<[f64; 8]>,
+) -> [f64; 8] {
+    if memo.contains_state(dealer_hand) {
+        return memo[dealer_hand];
+    }
+    let mut probabilities = [0.0; 8];
+    if dealer_hand.bust() {
+        probabilities[0] = 1.0;
+    } else if dealer_hand.is_natural() {
+        probabilities[6] = 1.0;
+    } else if rule.dealer_must_stand(dealer_hand) {
+        let total = dealer_hand.get_actual_sum();
+        if total >= 17 {
+            probabilities[(total - 16) as usize] = 1.0;
+        } else {
+            probabilities[7] = 1.0;
+        }
+    } else {
+        let shoe_total = current_shoe.get_total() as f64;
+        for card in 1..=10 {
+            if current_shoe[card] == 0 {
+                continue;
+            }
+            let p = current_shoe[card] as f64 / shoe_total;
+            current_shoe.remove_card(card);
+            dealer_hand.add_card(card);
+            let sub = memoization_dealer_total_probabilities(rule, current_shoe, dealer_hand, memo);
+            dealer_hand.remove_card(card);
+            current_shoe.add_card(card);
+            for (probability, sub) in probabilities.iter_mut().zip(sub) {
+                *probability += p * sub;
+            }
+        }
+    }
+    memo[dealer_hand] = probabilities;
+    probabilities
+}
+
+/// Returns the expectation of standing on each player total from 0 to 21 against the given dealer
+/// up card, where the player hand is not a natural and Charlie is not considered. The shoe should
+/// contain the cards remaining, i.e., the dealer up card must have been removed. If the dealer
+/// peeks, the expectations are given when the dealer doesn't have a natural.
+pub fn stand_ev_by_total(rule: &Rule, shoe: &CardCount, dealer_up_card: u8) -> [f64; 22] {
+    let mut exs = [0.0; 22];
+    for (player_sum, ex) in exs.iter_mut().enumerate() {
+        let mut odds: StateArray<WinLoseCasesOdds> = StateArray::new();
+        let mut dealer_extra_hand = CardCount::with_number_of_decks(0);
+        memoization_find_win_lose_odds(
+            rule,
+            &(player_sum as u16),
+            &dealer_up_card,
+            shoe,
+            &mut dealer_extra_hand,
+            &mut odds,
+        );
+        let odds = odds[&dealer_extra_hand];
+        *ex = odds.win - odds.lose;
+    }
+    exs
+}
+
+/// Returns the probability that the given hand busts if the player hits once. The shoe should
+/// contain the cards remaining, i.e., the player hand and dealer up card must have been removed.
+/// If the dealer peeks, the hole card is known not to make a natural, which is taken into account.
+pub fn bust_probability_on_hit(
+    shoe: &CardCount,
+    rule: &Rule,
+    dealer_up_card: u8,
+    hand: &CardCount,
+) -> f64 {
+    if hand.bust() {
+        return 1.0;
+    }
+    let impossible_dealer_hole_card = get_impossible_dealer_hole_card(rule, dealer_up_card);
+    (1..=10)
+        .filter(|&card| shoe[card] > 0 && hand.get_sum() + card as u16 > 21)
+        .map(|card| get_card_probability(shoe, impossible_dealer_hole_card, card))
+        .sum()
+}
+
+/// Returns the probability that hitting the given soft hand once makes a total of 18 to 21, i.e.,
+/// a total worth standing on, rather than a low hard total. The shoe should contain the cards
+/// remaining, i.e., the player hand and dealer up card must have been removed. If the dealer peeks,
+/// the hole card is known not to make a natural, which is taken into account.
+pub fn p_improve_soft_hand(
+    shoe: &CardCount,
+    rule: &Rule,
+    dealer_up_card: u8,
+    soft_hand: &CardCount,
+) -> f64 {
+    let impossible_dealer_hole_card = get_impossible_dealer_hole_card(rule, dealer_up_card);
+    (1..=10)
+        .filter(|&card| {
+            let mut hand = *soft_hand;
+            hand.add_card(card);
+            shoe[card] > 0 && (18..=21).contains(&hand.get_actual_sum())
+        })
+        .map(|card| get_card_probability(shoe, impossible_dealer_hole_card, card))
+        .sum()
+}
+
+/// Returns the probabilities of winning, pushing and losing when the player stands on the given
+/// hand, where the dealer has already revealed the cards in `dealer_partial_hand` (e.g., the up
+/// card and the hole card) but hasn't drawn yet. The shoe should contain the cards remaining, i.e.,
+/// the player hand and the dealer partial hand must have been removed. If the dealer partial hand
+/// is just the up card, this is the same as `outcome_probabilities`. Charlie is not considered.
+pub fn continuation_ev(
+    rule: &Rule,
+    shoe: &CardCount,
+    player_hand: &CardCount,
+    dealer_partial_hand: &CardCount,
+) -> (f64, f64, f64) {
+    let dealer_up_card = (1..=10)
+        .find(|&card| dealer_partial_hand[card] > 0)
+        .expect("The dealer partial hand must not be empty");
+    let mut dealer_extra_hand = *dealer_partial_hand;
+    dealer_extra_hand.remove_card(dealer_up_card);
+    if dealer_extra_hand.get_total() == 0 {
+        return outcome_probabilities(rule, shoe, player_hand, dealer_up_card);
+    }
+    if player_hand.bust() {
+        return (0.0, 0.0, 1.0);
+    }
+    if player_hand.is_natural() {
+        if dealer_partial_hand.is_natural() {
+            return match rule.player_natural_vs_dealer_natural {
+                PushOrLose::Push => (0.0, 1.0, 0.0),
+                PushOrLose::Lose => (0.0, 0.0, 1.0),
+            };
+        }
+        return (1.0, 0.0, 0.0);
+    }
+
+    // The dealer recursion draws from the shoe just before the hole card is revealed, so the cards
+    // revealed after the up card are put back.
+    let mut original_shoe = *shoe;
+    for card in 1..=10 {
+        for _ in 0..dealer_extra_hand[card] {
+            original_shoe.add_card(card);
+        }
+    }
+    let mut odds = StateArray::new();
+    memoization_find_win_lose_odds(
+        rule,
+        &player_hand.get_actual_sum(),
+        &dealer_up_card,
+        &original_shoe,
+        &mut dealer_extra_hand,
+        &mut odds,
+    );
+    let odds = odds[&dealer_extra_hand];
+    (odds.win, odds.push, odds.lose)
+}
+
+fn calculate_stand_odds<F: Float>(
     rule: &Rule,
     player_hand: &CardCount,
     dealer_up_card: &u8,
     shoe: &CardCount,
-) -> WinLoseCasesOdds {
+) -> WinLoseCasesOdds<F> {
     let mut dealer_extra_hand = CardCount::new(&[0; 10]);
     let player_sum = player_hand.get_actual_sum();
 
@@ -674,10 +1751,15 @@ fn calculate_stand_odds(
                 _ => 0.0,
             },
         };
+        let p_dealer_also_natural = F::from_f64(p_dealer_also_natural);
+        let (push, lose) = match rule.player_natural_vs_dealer_natural {
+            PushOrLose::Push => (p_dealer_also_natural, F::default()),
+            PushOrLose::Lose => (F::default(), p_dealer_also_natural),
+        };
         return WinLoseCasesOdds {
-            win: 1.0 - p_dealer_also_natural,
-            push: p_dealer_also_natural,
-            lose: 0.0,
+            win: F::from_f64(1.0) - p_dealer_also_natural,
+            push,
+            lose,
         };
     }
 
@@ -687,7 +1769,7 @@ fn calculate_stand_odds(
         rule,
         &player_sum,
         dealer_up_card,
-        &shoe,
+        shoe,
         &mut dealer_extra_hand,
         &mut odds,
     );
@@ -698,7 +1780,7 @@ fn calculate_stand_odds(
 /// Note that the callers of this function must ensure that if player_sum is 21, it must NOT be
 /// a natural Blackjack. Player natural Blackjack should be handled separately as a special
 /// case before recursively calling this function.
-fn memoization_find_win_lose_odds(
+fn memoization_find_win_lose_odds<F: Float>(
     // Input parameters
     rule: &Rule,
     player_sum: &u16,
@@ -707,7 +1789,7 @@ fn memoization_find_win_lose_odds(
 
     // Parameters to maintain current state
     dealer_extra_hand: &mut CardCount, // Dealer's hand except for the up card
-    odds: &mut StateArray<WinLoseCasesOdds>,
+    odds: &mut StateArray<WinLoseCasesOdds<F>>,
 ) {
     if odds.contains_state(dealer_extra_hand) {
         return;
@@ -718,14 +1800,19 @@ fn memoization_find_win_lose_odds(
     let is_soft = dealer_extra_hand.is_soft() || *dealer_up_card == 1;
     if dealer_sum > 21 {
         odds[dealer_extra_hand] = WinLoseCasesOdds {
-            win: 1.0,
+            win: F::from_f64(1.0),
             ..Default::default()
         };
         return;
     }
-    if dealer_sum >= 17 {
-        // Hard sum >= 17
-        add_to_win_lose_cases_count(*player_sum, dealer_sum, &mut odds[dealer_extra_hand], 1.0);
+    if dealer_sum >= rule.dealer_stand_value {
+        // Hard sum reaches the stand value.
+        add_to_win_lose_cases_count(
+            *player_sum,
+            dealer_sum,
+            &mut odds[dealer_extra_hand],
+            F::from_f64(1.0),
+        );
         return;
     }
     if is_soft {
@@ -735,25 +1822,18 @@ fn memoization_find_win_lose_odds(
         // impossible to run.
         if dealer_sum + 10 == 21 && dealer_extra_hand.get_total() == 1 {
             odds[dealer_extra_hand] = WinLoseCasesOdds {
-                lose: 1.0,
+                lose: F::from_f64(1.0),
                 ..Default::default()
             };
             return;
         }
-
-        let lower_bound = {
-            if rule.dealer_hit_on_soft17 {
-                18
-            } else {
-                17
-            }
-        };
-        if dealer_sum + 10 >= lower_bound && dealer_sum + 10 <= 21 {
+
+        if dealer_sum + 10 >= rule.dealer_soft_stand_value() && dealer_sum + 10 <= 21 {
             add_to_win_lose_cases_count(
                 *player_sum,
                 dealer_sum + 10,
                 &mut odds[dealer_extra_hand],
-                1.0,
+                F::from_f64(1.0),
             );
             return;
         }
@@ -788,6 +1868,22 @@ fn memoization_find_win_lose_odds(
             }
         }
     };
+    // The dealer stands with what she has if no card is left to draw, which only happens near the
+    // end of a small shoe.
+    if (next_card_min..=next_card_max).all(|card| dealer_extra_hand[card] == original_shoe[card]) {
+        let dealer_sum = if is_soft && dealer_sum + 10 <= 21 {
+            dealer_sum + 10
+        } else {
+            dealer_sum
+        };
+        add_to_win_lose_cases_count(
+            *player_sum,
+            dealer_sum,
+            &mut odds[dealer_extra_hand],
+            F::from_f64(1.0),
+        );
+        return;
+    }
     let current_valid_shoe_total = current_valid_shoe_total as f64;
 
     for card in next_card_min..=next_card_max {
@@ -808,15 +1904,15 @@ fn memoization_find_win_lose_odds(
         dealer_extra_hand.remove_card(card);
 
         let p = ((original_shoe[card] - dealer_extra_hand[card]) as f64) / current_valid_shoe_total;
-        odds[dealer_extra_hand] += &(next_state_odds * p);
+        odds[dealer_extra_hand] += &(next_state_odds * F::from_f64(p));
     }
 }
 
-fn add_to_win_lose_cases_count(
+fn add_to_win_lose_cases_count<F: Float>(
     player_sum: u16,
     dealer_sum: u16,
-    count: &mut WinLoseCasesOdds,
-    delta: f64,
+    count: &mut WinLoseCasesOdds<F>,
+    delta: F,
 ) {
     match player_sum.cmp(&dealer_sum) {
         Ordering::Less => count.lose += delta,
@@ -829,31 +1925,930 @@ fn add_to_win_lose_cases_count(
 mod tests {
     use super::*;
 
-    fn get_typical_rule() -> Rule {
-        Rule {
-            number_of_decks: 8,
-            cut_card_proportion: 0.5,
-            split_all_limits: 1,
-            split_ace_limits: 1,
-            double_policy: crate::DoublePolicy::AnyTwo,
-            dealer_hit_on_soft17: false,
-            allow_das: false,
-            allow_late_surrender: false,
-            peek_policy: crate::PeekPolicy::UpAce,
-            charlie_number: 6,
+    #[test]
+    fn outcome_probabilities_sum_to_one() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        let mut hand = CardCount::with_number_of_decks(0);
+        for card in [10, 10] {
+            shoe.remove_card(card);
+            hand.add_card(card);
+        }
+        shoe.remove_card(10);
+        let (win, push, lose) = outcome_probabilities(&rule, &shoe, &hand, 10);
+        assert!((win + push + lose - 1.0).abs() < 1e-9);
+        assert!(win > lose && push > 0.0);
+    }
+
+    #[test]
+    fn continuation_from_revealed_hole_card() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        let mut hand = CardCount::with_number_of_decks(0);
+        for card in [10, 10] {
+            shoe.remove_card(card);
+            hand.add_card(card);
+        }
+        shoe.remove_card(10);
+        let mut dealer_hand = CardCount::with_number_of_decks(0);
+        dealer_hand.add_card(10);
+        let up_card_only = outcome_probabilities(&rule, &shoe, &hand, 10);
+        assert_eq!(
+            continuation_ev(&rule, &shoe, &hand, &dealer_hand),
+            up_card_only
+        );
+
+        // The dealer doesn't peek on 10, so averaging over the hole card gives the same result.
+        let mut averaged = (0.0, 0.0, 0.0);
+        for hole_card in 1..=10 {
+            let p = get_card_probability(&shoe, 0, hole_card);
+            shoe.remove_card(hole_card);
+            dealer_hand.add_card(hole_card);
+            let (win, push, lose) = continuation_ev(&rule, &shoe, &hand, &dealer_hand);
+            averaged = (
+                averaged.0 + p * win,
+                averaged.1 + p * push,
+                averaged.2 + p * lose,
+            );
+            dealer_hand.remove_card(hole_card);
+            shoe.add_card(hole_card);
+        }
+        assert!((averaged.0 - up_card_only.0).abs() < 1e-9);
+        assert!((averaged.1 - up_card_only.1).abs() < 1e-9);
+        assert!((averaged.2 - up_card_only.2).abs() < 1e-9);
+
+        dealer_hand.add_card(7);
+        shoe.remove_card(7);
+        assert_eq!(
+            continuation_ev(&rule, &shoe, &hand, &dealer_hand),
+            (1.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn decision_for_respects_legality_flags() {
+        let rule = Rule {
+            allow_late_surrender: true,
+            ..Default::default()
+        };
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(10);
+        hand.add_card(6);
+        let mut ex_stand_hit = StateArray::new();
+        ex_stand_hit[&hand] = Expectation {
+            hit: -0.6,
+            stand: -0.7,
+            ..Default::default()
+        };
+        let solution = SolutionForInitialSituation {
+            ex_stand_hit,
+            ex_double: -0.55,
+            ex_split: -0.52,
+            ..Default::default()
+        };
+
+        let decide = |can_double, can_split, can_surrender| {
+            solution.decision_for(&hand, &rule, can_double, can_split, can_surrender)
+        };
+        assert_eq!(decide(true, true, true), Decision::Surrender);
+        assert_eq!(decide(true, true, false), Decision::Split);
+        assert_eq!(decide(true, false, false), Decision::Double);
+        assert_eq!(decide(false, false, false), Decision::Hit);
+
+        // Double and Split are never chosen after the initial hand.
+        hand.add_card(2);
+        let mut solution = solution;
+        solution.ex_stand_hit[&hand] = Expectation {
+            hit: -0.8,
+            stand: -0.7,
+            ..Default::default()
+        };
+        assert_eq!(
+            solution.decision_for(&hand, &rule, true, true, false),
+            Decision::Stand
+        );
+    }
+
+    #[test]
+    fn double_has_higher_variance_than_hit() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [6, 5, 6] {
+            shoe.remove_card(card);
+        }
+        let initial_situation = InitialSituation::new(shoe, (6, 5), 6);
+        let solution = calculate_solution_with_initial_situation(1, &rule, &initial_situation);
+
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(6);
+        hand.add_card(5);
+        let variance_hit = solution.decision_variance(&hand, &rule, Decision::Hit);
+        let variance_double = solution.decision_variance(&hand, &rule, Decision::Double);
+        assert!(variance_hit > 0.0 && variance_hit < 1.0);
+        assert!(variance_double > 2.0 * variance_hit);
+        assert!(solution
+            .decision_variance(&hand, &rule, Decision::Split)
+            .is_nan());
+
+        hand.add_card(10);
+        hand.add_card(10);
+        assert_eq!(
+            solution.decision_variance(&hand, &rule, Decision::Stand),
+            0.0
+        );
+    }
+
+    #[test]
+    fn forcing_stand_on_hard_8_is_worse() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [5, 3, 10] {
+            shoe.remove_card(card);
+        }
+        let initial_situation = InitialSituation::new(shoe, (5, 3), 10);
+        let solution = calculate_solution_with_initial_situation(1, &rule, &initial_situation);
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(5);
+        hand.add_card(3);
+        let (ex_optimal, decision) = get_max_expectation(&solution.ex_stand_hit, &hand, &rule);
+        assert_eq!(decision, Decision::Hit);
+
+        let ex_stand = ev_with_forced_first_decision(1, &rule, &shoe, (5, 3), 10, Decision::Stand);
+        let ex_hit = ev_with_forced_first_decision(1, &rule, &shoe, (5, 3), 10, Decision::Hit);
+        assert_eq!(ex_hit, ex_optimal);
+        assert!(ex_stand.is_finite());
+        assert!(ex_stand < ex_optimal - 0.1);
+        // Like any total below 17, hard 8 only wins when the dealer busts. The dealer doesn't peek
+        // with a 10 here, so a dealer natural loses too, which gives about -0.577.
+        let p_dealer_bust = dealer_total_given_standing(&rule, &shoe, 10)[0];
+        assert!((ex_stand - (2.0 * p_dealer_bust - 1.0)).abs() < 1e-9);
+        assert!((ex_stand - -0.577).abs() < 0.001);
+        assert_eq!(
+            ev_with_forced_first_decision(1, &rule, &shoe, (5, 3), 10, Decision::Split),
+            -f64::INFINITY
+        );
+    }
+
+    #[test]
+    fn stand_on_soft_21_with_multiple_aces() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [1, 1, 6] {
+            shoe.remove_card(card);
+        }
+        let initial_situation = InitialSituation::new(shoe, (1, 1), 6);
+        let solution = calculate_solution_with_initial_situation(1, &rule, &initial_situation);
+
+        let mut hand = CardCount::with_number_of_decks(0);
+        for card in [1, 1, 9] {
+            hand.add_card(card);
+        }
+        assert_eq!(hand.get_actual_sum(), 21);
+        let (_, decision) = get_max_expectation(&solution.ex_stand_hit, &hand, &rule);
+        assert_eq!(decision, Decision::Stand);
+    }
+
+    #[test]
+    fn explanation_names_decision_and_runner_up() {
+        let rule = Rule::default();
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(5);
+        hand.add_card(6);
+        let mut ex_stand_hit = StateArray::new();
+        ex_stand_hit[&hand] = Expectation {
+            hit: 0.15,
+            stand: -0.05,
+            ..Default::default()
+        };
+        let solution = SolutionForInitialSituation {
+            ex_stand_hit,
+            ex_double: 0.21,
+            ex_split: -f64::INFINITY,
+            ..Default::default()
+        };
+        assert_eq!(
+            solution.explain(&hand, &rule),
+            "Double: +0.2100 EV beats Hit +0.1500 and Stand -0.0500"
+        );
+    }
+
+    #[test]
+    fn surrender_is_excluded_from_early_end() {
+        let rule = Rule {
+            allow_late_surrender: true,
+            ..Default::default()
+        };
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [10, 6, 1] {
+            shoe.remove_card(card);
+        }
+        let initial_situation = InitialSituation::new(shoe, (10, 6), 1);
+        let solution = calculate_solution_with_initial_situation(1, &rule, &initial_situation);
+
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(10);
+        hand.add_card(6);
+        let (ex_no_early_end, decision) = get_max_expectation(&solution.ex_stand_hit, &hand, &rule);
+        assert_eq!(decision, Decision::Surrender);
+        assert_eq!(ex_no_early_end, -0.5);
+        assert!(solution.ex_extra_insurance < 0.0);
+
+        // When dealer has a natural, the whole bet is lost instead of half of it.
+        let p_early_end = shoe[10] as f64 / shoe.get_total() as f64;
+        let gt = -p_early_end - (1.0 - p_early_end) * 0.5;
+        assert!((solution.ex_summary - gt).abs() < 1e-12);
+    }
+
+    #[test]
+    fn eights_are_split_vs_six() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [8, 8, 6] {
+            shoe.remove_card(card);
+        }
+        let initial_situation = InitialSituation::new(shoe, (8, 8), 6);
+        let solution = calculate_solution_with_initial_situation(1, &rule, &initial_situation);
+        assert_eq!(
+            solution.ex_split,
+            calculate_split_expectation_with_limit(&rule, &initial_situation, 1)
+        );
+
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(8);
+        hand.add_card(8);
+        assert_eq!(
+            solution.decision_for(&hand, &rule, true, true, true),
+            Decision::Split
+        );
+        // The dealer doesn't peek with a 6, so the round never ends early.
+        assert_eq!(solution.ex_summary, solution.ex_split);
+    }
+
+    #[test]
+    fn natural_is_never_doubled_when_losing_to_dealer_natural() {
+        let rule = Rule {
+            player_natural_vs_dealer_natural: PushOrLose::Lose,
+            ..Default::default()
+        };
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [1, 10, 1] {
+            shoe.remove_card(card);
+        }
+        let initial_situation = InitialSituation::new(shoe, (1, 10), 1);
+        let solution = calculate_solution_with_initial_situation(1, &rule, &initial_situation);
+        assert_eq!(solution.ex_double, -f64::INFINITY);
+
+        // The natural loses the whole bet when the dealer peeks and has a natural too.
+        let p_early_end = shoe[10] as f64 / shoe.get_total() as f64;
+        let gt = -p_early_end + (1.0 - p_early_end) * rule.payout_blackjack;
+        assert!((solution.ex_summary - gt).abs() < 1e-12);
+    }
+
+    #[test]
+    fn stand_thresholds_of_small_shoe() {
+        // About a third of a deck, where the stiffs against 7 to 10 are hit even more often.
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[2, 1, 1, 1, 1, 1, 1, 1, 1, 6]);
+        let solution = calculate_solution_without_initial_situation(0, &rule, &shoe);
+        let thresholds = solution.stand_thresholds(&rule);
+        for dealer_up_card in 2..=6 {
+            let threshold = thresholds[dealer_up_card - 1];
+            assert!(threshold == 12 || threshold == 13);
+        }
+        for dealer_up_card in 7..=10 {
+            assert!(thresholds[dealer_up_card - 1] >= 17);
+        }
+    }
+
+    #[test]
+    fn stand_heatmap_increases_with_total() {
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[2, 1, 1, 1, 1, 1, 1, 1, 1, 6]);
+        let heatmap = calculate_solution_without_initial_situation(0, &rule, &shoe)
+            .ev_heatmap(&rule, Decision::Stand);
+        for d in 0..10 {
+            // Totals 1 to 3 are impossible.
+            for row in &heatmap[..3] {
+                assert!(row[d].is_nan());
+            }
+            for total in 17..=21 {
+                assert!(heatmap[total - 1][d] > heatmap[total - 2][d]);
+            }
+        }
+
+        // Totals up to 16 only win when the dealer busts, which is rare with a high up card.
+        // Totals that cannot be dealt from this small shoe (e.g., 4) are NaN.
+        for dealer_up_card in [7, 8, 9, 10, 1] {
+            for row in &heatmap[3..16] {
+                let ex = row[dealer_up_card - 1];
+                assert!(ex.is_nan() || ex < 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn naturals_are_among_the_best_hands_and_stiffs_the_worst() {
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[2, 1, 1, 1, 1, 1, 1, 1, 1, 6]);
+        let hands = calculate_solution_without_initial_situation(0, &rule, &shoe).hands_by_ev();
+
+        // Only the initial situations that can be dealt from the shoe are listed.
+        let number_of_situations: usize = (1..=10)
+            .filter(|&dealer_up_card| shoe[dealer_up_card] > 0)
+            .map(|dealer_up_card| {
+                let mut shoe = shoe;
+                shoe.remove_card(dealer_up_card);
+                starting_hand_frequencies(&shoe)
+                    .iter()
+                    .filter(|&&(_, p)| p > 0.0)
+                    .count()
+            })
+            .sum();
+        assert_eq!(hands.len(), number_of_situations);
+        assert!(hands.windows(2).all(|w| w[0].2 >= w[1].2));
+
+        // A natural pays 3:2 against 2 to 9. On a shoe this rich in 10s, a few doubles may win
+        // even more.
+        for dealer_up_card in 2..=9 {
+            let rank = hands
+                .iter()
+                .position(|&(hand, d, _)| hand == (10, 1) && d == dealer_up_card)
+                .unwrap();
+            assert!(rank < 10);
+            assert_eq!(hands[rank].2, 1.5);
+        }
+        for &(hand, dealer_up_card, _) in &hands[hands.len() - 5..] {
+            assert!((12..=16).contains(&(hand.0 + hand.1)));
+            assert!(dealer_up_card == 1 || dealer_up_card >= 7);
+        }
+    }
+
+    #[test]
+    fn six_to_five_blackjack_costs_0_3_of_each_natural() {
+        // Without insurance, the payout only changes the expectation of a player natural which
+        // the dealer doesn't match.
+        let rule = Rule {
+            offer_insurance_on_ace: false,
+            ..Default::default()
+        };
+        let shoe = CardCount::new(&[2, 1, 1, 1, 1, 1, 1, 1, 1, 6]);
+        let cost = blackjack_payout_cost_of_shoe(0, &rule, &shoe);
+
+        let is_natural = |a: u8, b: u8| a.min(b) == 1 && a.max(b) == 10;
+        let mut p_natural_wins = 0.0;
+        for up_card in 1..=10 {
+            for first_card in 1..=10 {
+                for second_card in 1..=10 {
+                    for hole_card in 1..=10 {
+                        if !is_natural(first_card, second_card) || is_natural(up_card, hole_card) {
+                            continue;
+                        }
+                        let mut shoe = shoe;
+                        let mut p = 1.0;
+                        for card in [up_card, first_card, second_card, hole_card] {
+                            p *= shoe[card] as f64 / shoe.get_total() as f64;
+                            if shoe[card] == 0 {
+                                break;
+                            }
+                            shoe.remove_card(card);
+                        }
+                        p_natural_wins += p;
+                    }
+                }
+            }
+        }
+        assert!(p_natural_wins > 0.0);
+        assert!((cost - 0.3 * p_natural_wins).abs() < 1e-12);
+    }
+
+    #[test]
+    fn fewer_decks_have_lower_house_edge() {
+        // Copies of a 9-card deck (an Ace, a 3, a 5, a 7, a 9 and four 10s) instead of standard
+        // decks, so that solving two of them stays fast.
+        let rule = Rule::default();
+        let deck = CardCount::new(&[1, 0, 1, 0, 1, 0, 1, 0, 1, 4]);
+        let curve = shoe_count_ev_curve(0, &rule, &deck, &[1, 2]);
+        assert_eq!(
+            curve.iter().map(|&(decks, _)| decks).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(curve[0].1 < curve[1].1);
+
+        let shoe = CardCount::new(&[2, 0, 2, 0, 2, 0, 2, 0, 2, 8]);
+        let ex =
+            calculate_solution_without_initial_situation(0, &rule, &shoe).get_total_expectation();
+        assert_eq!(curve[1].1, -ex);
+    }
+
+    #[test]
+    fn no_peek_has_higher_house_edge() {
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[1, 1, 1, 1, 1, 1, 1, 1, 1, 4]);
+        let house_edges = peek_policy_ev_of_shoe(0, &rule, &shoe);
+        assert_eq!(house_edges[0].0, PeekPolicy::UpAceOrTen);
+        assert_eq!(house_edges[2].0, PeekPolicy::NoPeek);
+        assert!(house_edges[2].1 > house_edges[0].1);
+    }
+
+    #[test]
+    fn dealer_stand_value_changes_player_expectation() {
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[2, 1, 1, 1, 1, 1, 1, 1, 1, 6]);
+        let get_expectation = |rule: &Rule| {
+            calculate_solution_without_initial_situation(0, rule, &shoe).get_total_expectation()
+        };
+        let ex_s17 = get_expectation(&rule);
+
+        // Hitting soft 17 makes the dealer stronger.
+        let ex_h17 = get_expectation(&Rule {
+            dealer_hit_on_soft: true,
+            ..rule
+        });
+        assert!(ex_h17 < ex_s17);
+
+        // But hitting hard 17 makes her bust much more often, which is good for the player.
+        let ex_18 = get_expectation(&Rule {
+            dealer_stand_value: 18,
+            ..rule
+        });
+        assert!(ex_18 > ex_s17 + 0.02);
+    }
+
+    #[test]
+    fn dealer_stands_according_to_stand_value() {
+        let mut rule = Rule::default();
+        let hand = |cards: &[u8]| {
+            let mut hand = CardCount::with_number_of_decks(0);
+            for &card in cards {
+                hand.add_card(card);
+            }
+            hand
+        };
+        assert!(rule.dealer_must_stand(&hand(&[1, 6])));
+        assert!(!rule.dealer_must_stand(&hand(&[10, 6])));
+        rule.dealer_hit_on_soft = true;
+        assert!(!rule.dealer_must_stand(&hand(&[1, 6])));
+        assert!(rule.dealer_must_stand(&hand(&[10, 7])));
+        // Soft 17 counted as hard 17 with several Aces is still soft.
+        assert!(!rule.dealer_must_stand(&hand(&[1, 1, 5])));
+        rule.dealer_stand_value = 18;
+        assert!(!rule.dealer_must_stand(&hand(&[10, 7])));
+        assert!(!rule.dealer_must_stand(&hand(&[1, 7])));
+        assert!(rule.dealer_must_stand(&hand(&[1, 8])));
+
+        // Hitting soft 18 doesn't make the dealer hit hard 17 or 18.
+        rule.dealer_stand_value = 17;
+        rule.dealer_soft_hit_up_to = Some(18);
+        assert!(!rule.dealer_must_stand(&hand(&[1, 7])));
+        assert!(rule.dealer_must_stand(&hand(&[1, 8])));
+        assert!(rule.dealer_must_stand(&hand(&[10, 7])));
+        assert!(rule.dealer_must_stand(&hand(&[10, 8])));
+        // It replaces dealer_hit_on_soft for soft totals.
+        rule.dealer_hit_on_soft = false;
+        assert!(!rule.dealer_must_stand(&hand(&[1, 6])));
+        assert!(!rule.dealer_must_stand(&hand(&[1, 7])));
+
+        // Without it, standing on 16 also stands on soft 16.
+        let rule = Rule {
+            dealer_stand_value: 16,
+            ..Rule::default()
+        };
+        assert!(rule.dealer_must_stand(&hand(&[1, 5])));
+        assert!(rule.dealer_must_stand(&hand(&[10, 6])));
+        assert!(!rule.dealer_must_stand(&hand(&[10, 5])));
+    }
+
+    #[test]
+    fn dealer_hitting_soft_18_changes_outcomes() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        shoe.remove_card(7);
+        let s17 = dealer_total_given_standing(&rule, &shoe, 7);
+        let h18 = dealer_total_given_standing(
+            &Rule {
+                dealer_soft_hit_up_to: Some(18),
+                ..rule
+            },
+            &shoe,
+            7,
+        );
+        // Soft 18 (7 and Ace) is the most common way to make 18, so it becomes much rarer.
+        assert!(h18[2] < s17[2] - 0.05);
+        assert!(h18[0] > s17[0]);
+        for probabilities in [s17, h18] {
+            assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn busted_double_loses_two_units() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [10, 2, 6] {
+            shoe.remove_card(card);
+        }
+        let initial_situation = InitialSituation::new(shoe, (10, 2), 6);
+        let solution = calculate_solution_with_initial_situation(1, &rule, &initial_situation);
+
+        // Only a third card of 10 busts hard 12, and the stand expectation of the busted hand is
+        // -1, i.e., the doubled bet loses two units.
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(10);
+        hand.add_card(2);
+        let mut ex_double = 0.0;
+        for third_card in 1..=10 {
+            hand.add_card(third_card);
+            let ex_stand = solution.ex_stand_hit[&hand].stand;
+            assert_eq!(hand.bust(), third_card == 10);
+            if hand.bust() {
+                assert_eq!(ex_stand, -1.0);
+            }
+            ex_double += 2.0 * get_card_probability(&shoe, 0, third_card) * ex_stand;
+            hand.remove_card(third_card);
+        }
+        assert!((solution.ex_double - ex_double).abs() < 1e-12);
+    }
+
+    #[test]
+    fn stand_ev_increases_with_total() {
+        let rule = Rule::default();
+        for dealer_up_card in [1, 6, 10] {
+            let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+            shoe.remove_card(dealer_up_card);
+            let exs = stand_ev_by_total(&rule, &shoe, dealer_up_card);
+            for total in 1..=21 {
+                assert!(exs[total] >= exs[total - 1]);
+            }
+            // Totals up to 16 only win when the dealer busts.
+            assert_eq!(exs[0], exs[16]);
+            assert!(exs[21] > exs[17]);
+
+            let mut hand = CardCount::with_number_of_decks(0);
+            hand.add_card(10);
+            hand.add_card(8);
+            let (win, _, lose) = outcome_probabilities(&rule, &shoe, &hand, dealer_up_card);
+            assert!((exs[18] - (win - lose)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn f32_solver_matches_f64_solver() {
+        let rule = Rule::default();
+        for (hand_cards, dealer_up_card) in [((10, 6), 10), ((10, 2), 4), ((1, 7), 9), ((5, 6), 1)]
+        {
+            let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+            shoe.remove_card(hand_cards.0);
+            shoe.remove_card(hand_cards.1);
+            shoe.remove_card(dealer_up_card);
+            let initial_situation = InitialSituation::new(shoe, hand_cards, dealer_up_card);
+            let ex_f32 = calculate_stand_hit_expectation::<f32>(&rule, &initial_situation);
+            let ex_f64 = calculate_stand_hit_expectation::<f64>(&rule, &initial_situation);
+
+            let mut hand = CardCount::with_number_of_decks(0);
+            hand.add_card(hand_cards.0);
+            hand.add_card(hand_cards.1);
+            let mut hands = vec![hand];
+            for card in 1..=10 {
+                let mut next_hand = hand;
+                next_hand.add_card(card);
+                hands.push(next_hand);
+            }
+            for hand in &hands {
+                let (max_ex_f32, decision_f32) = get_max_expectation(&ex_f32, hand, &rule);
+                let (max_ex_f64, decision_f64) = get_max_expectation(&ex_f64, hand, &rule);
+                assert_eq!(decision_f32, decision_f64);
+                assert!((max_ex_f32.to_f64() - max_ex_f64).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn starting_hand_frequencies_sum_to_one() {
+        let shoe = CardCount::with_number_of_decks(6);
+        let frequencies = starting_hand_frequencies(&shoe);
+        assert_eq!(frequencies.len(), 55);
+        let sum: f64 = frequencies.iter().map(|&(_, p)| p).sum();
+        assert!((sum - 1.0).abs() < 1e-12);
+        let (_, p_ten_ten) = frequencies
+            .iter()
+            .find(|&&(hand, _)| hand == (10, 10))
+            .unwrap();
+        assert!((p_ten_ten - 96.0 * 95.0 / (312.0 * 311.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn larger_charlie_number_has_more_states() {
+        let mut rule = Rule::default();
+        let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        rule.charlie_number = 5;
+        let count5 = estimate_state_count(&rule, &shoe);
+        rule.charlie_number = 6;
+        let count6 = estimate_state_count(&rule, &shoe);
+        rule.charlie_number = 0;
+        let count_no_charlie = estimate_state_count(&rule, &shoe);
+        assert!(count5 > 0);
+        assert!(count6 > count5);
+        assert!(count_no_charlie > count6);
+    }
+
+    #[test]
+    fn hard_16_stands_best_vs_six() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        let mut hand = CardCount::with_number_of_decks(0);
+        for card in [10, 6] {
+            shoe.remove_card(card);
+            hand.add_card(card);
+        }
+        let exs = hand_vs_all_upcards(&rule, &shoe, &hand).map(|ex| ex.stand);
+        let best = (1..=10).max_by(|&a, &b| exs[a - 1].total_cmp(&exs[b - 1]));
+        assert_eq!(best, Some(6));
+        // The Ace is even worse than 10, since the dealer has already checked for a natural.
+        let worst_non_ace = (2..=10).min_by(|&a, &b| exs[a - 1].total_cmp(&exs[b - 1]));
+        assert_eq!(worst_non_ace, Some(10));
+        assert!(exs[0] < exs[9]);
+    }
+
+    #[test]
+    fn reference_ev_16_vs_10_matches_solver() {
+        let rule = Rule {
+            number_of_decks: 1,
+            peek_policy: PeekPolicy::UpAceOrTen,
+            charlie_number: 0,
+            ..Rule::default()
+        };
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        let mut hand = CardCount::with_number_of_decks(0);
+        for card in [10, 6] {
+            shoe.remove_card(card);
+            hand.add_card(card);
+        }
+        let ex = hand_vs_all_upcards(&rule, &shoe, &hand)[9];
+        let ex_reference = reference_ev_16_vs_10(1);
+        assert!((ex.stand.max(ex.hit) - ex_reference).abs() < 1e-12);
+
+        // The Wizard of Odds' infinite deck figure of Hit, which is the better play.
+        assert!((reference_ev_16_vs_10(8) - -0.5398).abs() < 0.005);
+        // Removing a 10 from a small shoe makes hitting 16 noticeably better.
+        assert!(ex_reference > reference_ev_16_vs_10(8));
+    }
+
+    #[test]
+    fn dealer_six_busts_often() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        shoe.remove_card(6);
+        let probabilities = dealer_total_given_standing(&rule, &shoe, 6);
+        assert!(probabilities[0] > 0.4);
+        assert_eq!(probabilities[6], 0.0);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+
+        // The dealer peeks when showing an Ace, so she doesn't have a natural afterwards.
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        shoe.remove_card(1);
+        let probabilities = dealer_total_given_standing(&rule, &shoe, 1);
+        assert_eq!(probabilities[6], 0.0);
+        let no_peek_rule = Rule {
+            peek_policy: PeekPolicy::NoPeek,
+            ..rule
+        };
+        let probabilities = dealer_total_given_standing(&no_peek_rule, &shoe, 1);
+        assert!((probabilities[6] - 128.0 / 415.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn dealer_standing_on_16_keeps_all_probabilities() {
+        let rule = Rule {
+            dealer_stand_value: 16,
+            ..Rule::default()
+        };
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        shoe.remove_card(6);
+        let probabilities = dealer_total_given_standing(&rule, &shoe, 6);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        // 6 and 10 is the most common way to make 16.
+        assert!(probabilities[7] > 0.1);
+
+        let probabilities = dealer_total_given_standing(&Rule::default(), &shoe, 6);
+        assert_eq!(probabilities[7], 0.0);
+    }
+
+    #[test]
+    fn hitting_soft_13_improves_with_five_to_eight() {
+        let rule = Rule::default();
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(1);
+        hand.add_card(2);
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [1, 2, 7] {
+            shoe.remove_card(card);
+        }
+        let p = p_improve_soft_hand(&shoe, &rule, 7, &hand);
+        assert!((p - 127.0 / 413.0).abs() < 1e-12);
+
+        // Soft 20 stays at 18 or more with an Ace, 8, 9 or 10.
+        hand.add_card(7);
+        let p = p_improve_soft_hand(&shoe, &rule, 7, &hand);
+        assert!((p - 223.0 / 413.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn hard_16_busts_often_in_ten_rich_shoe() {
+        let rule = Rule::default();
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(10);
+        hand.add_card(6);
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [10, 6, 7] {
+            shoe.remove_card(card);
+        }
+
+        // 6 to 10 bust hard 16, i.e., 253 out of 413 cards.
+        let p = bust_probability_on_hit(&shoe, &rule, 7, &hand);
+        assert!((p - 253.0 / 413.0).abs() < 1e-12);
+
+        // With fewer small cards, 253 out of 353 cards.
+        let mut shoe =
+            CardCount::custom(rule.number_of_decks, &[(2, -20), (3, -20), (4, -20)]).unwrap();
+        for card in [10, 6, 7] {
+            shoe.remove_card(card);
+        }
+        let p = bust_probability_on_hit(&shoe, &rule, 7, &hand);
+        assert!((p - 253.0 / 353.0).abs() < 1e-12);
+        assert!(p > 0.7);
+    }
+
+    #[test]
+    fn number_of_threads_is_capped() {
+        assert_eq!(clamp_number_of_threads(100, 4), 4);
+        assert_eq!(clamp_number_of_threads(3, 4), 3);
+        assert!(clamp_number_of_threads(0, 4) <= 4);
+        assert_eq!(clamp_number_of_threads(100, 0), 100);
+    }
+
+    #[test]
+    fn exact_ties_follow_tie_break_order() {
+        let rule = Rule {
+            allow_late_surrender: true,
+            ..Default::default()
+        };
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(10);
+        hand.add_card(6);
+        let mut solution = StateArray::new();
+
+        solution[&hand] = Expectation {
+            hit: 0.1,
+            stand: 0.1,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_max_expectation(&solution, &hand, &rule),
+            (0.1, Decision::Stand)
+        );
+
+        solution[&hand] = Expectation {
+            hit: -0.5,
+            stand: -0.6,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_max_expectation(&solution, &hand, &rule),
+            (-0.5, Decision::Hit)
+        );
+
+        solution[&hand] = Expectation {
+            hit: -0.7,
+            stand: -0.5,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_max_expectation(&solution, &hand, &rule),
+            (-0.5, Decision::Stand)
+        );
+
+        assert!(is_better_decision(
+            0.2,
+            Decision::Double,
+            0.2,
+            Decision::Hit
+        ));
+        assert!(!is_better_decision(
+            0.2,
+            Decision::Split,
+            0.2,
+            Decision::Double
+        ));
+    }
 
-            payout_blackjack: 1.5,
-            payout_insurance: 2.0,
+    #[test]
+    fn skipping_split_keeps_other_expectations() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [8, 8, 6] {
+            shoe.remove_card(card);
         }
+        let initial_situation = InitialSituation::new(shoe, (8, 8), 6);
+        let mut initial_hand = CardCount::with_number_of_decks(0);
+        initial_hand.add_card(8);
+        initial_hand.add_card(8);
+
+        let mut ex_stand_hit_full = StateArray::new();
+        let full = calculate_expectations(
+            1,
+            &rule,
+            &SolveOptions::default(),
+            &initial_situation,
+            &mut ex_stand_hit_full,
+        );
+        let mut ex_stand_hit_fast = StateArray::new();
+        let options = SolveOptions {
+            compute_split: false,
+            ..Default::default()
+        };
+        let fast = calculate_expectations(
+            1,
+            &rule,
+            &options,
+            &initial_situation,
+            &mut ex_stand_hit_fast,
+        );
+
+        assert!(full.ex_split.is_finite());
+        assert_eq!(fast.ex_split, -f64::INFINITY);
+        assert_eq!(full.ex_double, fast.ex_double);
+        assert_eq!(
+            ex_stand_hit_full[&initial_hand].stand,
+            ex_stand_hit_fast[&initial_hand].stand
+        );
+        assert_eq!(
+            ex_stand_hit_full[&initial_hand].hit,
+            ex_stand_hit_fast[&initial_hand].hit
+        );
+        // Splitting 8s is the best play against a 6.
+        assert!(fast.ex_summary < full.ex_summary);
+    }
+
+    #[test]
+    fn skipping_insurance_keeps_other_expectations() {
+        // Make insurance profitable, so that it matters in the summary.
+        let rule = Rule {
+            payout_insurance: 3.0,
+            ..Default::default()
+        };
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        let hand_cards = (10, 6);
+        let dealer_up_card = 1;
+        shoe.remove_card(hand_cards.0);
+        shoe.remove_card(hand_cards.1);
+        shoe.remove_card(dealer_up_card);
+        let initial_situation = InitialSituation::new(shoe, hand_cards, dealer_up_card);
+        let mut initial_hand = CardCount::with_number_of_decks(0);
+        initial_hand.add_card(hand_cards.0);
+        initial_hand.add_card(hand_cards.1);
+
+        let mut ex_stand_hit_full = StateArray::new();
+        let full = calculate_expectations(
+            1,
+            &rule,
+            &SolveOptions::default(),
+            &initial_situation,
+            &mut ex_stand_hit_full,
+        );
+        let mut ex_stand_hit_fast = StateArray::new();
+        let options = SolveOptions {
+            compute_split: false,
+            compute_insurance: false,
+        };
+        let fast = calculate_expectations(
+            1,
+            &rule,
+            &options,
+            &initial_situation,
+            &mut ex_stand_hit_fast,
+        );
+
+        assert!(full.ex_extra_insurance > 0.0);
+        assert_eq!(fast.ex_extra_insurance, -f64::INFINITY);
+        assert_eq!(full.ex_double, fast.ex_double);
+        assert_eq!(
+            ex_stand_hit_full[&initial_hand].stand,
+            ex_stand_hit_fast[&initial_hand].stand
+        );
+        assert_eq!(
+            ex_stand_hit_full[&initial_hand].hit,
+            ex_stand_hit_fast[&initial_hand].hit
+        );
+        assert!(fast.ex_summary < full.ex_summary);
     }
 
     #[test]
     #[ignore]
     fn test_find_win_lose_cases_count() {
-        let rule = get_typical_rule();
+        let rule = Rule::default();
         let original_shoe = CardCount::new(&[0, 0, 1, 0, 0, 0, 1, 0, 0, 1]);
         let mut dealer_extra_hand = CardCount::new(&[0; 10]);
-        let mut odds = StateArray::new();
+        let mut odds: StateArray<WinLoseCasesOdds> = StateArray::new();
         memoization_find_win_lose_odds(
             &rule,
             &18,
@@ -871,7 +2866,7 @@ mod tests {
     #[test]
     #[ignore]
     fn test_decision() {
-        let rule = get_typical_rule();
+        let rule = Rule::default();
 
         let mut counts = [4 * (rule.number_of_decks as u16); 10];
         counts[9] = 16 * (rule.number_of_decks as u16);
@@ -898,7 +2893,7 @@ mod tests {
     #[test]
     #[ignore]
     fn test_calculate_with_unknown_player_cards() {
-        let rule = get_typical_rule();
+        let rule = Rule::default();
         let mut shoe = CardCount::with_number_of_decks(8);
         let dealer_up_card = 10;
         shoe.remove_card(dealer_up_card);
@@ -920,7 +2915,7 @@ mod tests {
     #[test]
     #[ignore]
     fn test_calculate_with_unknown_dealer_up_card() {
-        let rule = get_typical_rule();
+        let rule = Rule::default();
         let shoe = CardCount::with_number_of_decks(8);
         let time_start = std::time::SystemTime::now();
         let sol = calculate_solution_without_initial_situation(1, &rule, &shoe);
@@ -937,7 +2932,7 @@ mod tests {
     #[test]
     #[ignore]
     fn print_decision_chart_with_known_initial_situations() {
-        let rule = get_typical_rule();
+        let rule = Rule::default();
 
         println!("Hard:");
         for my_hand_total in 5..=18 {
@@ -1017,7 +3012,7 @@ mod tests {
     #[test]
     #[ignore]
     fn print_decision_chart_without_initial_situation() {
-        let rule = get_typical_rule();
+        let rule = Rule::default();
         let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
 
         let sol = calculate_solution_without_initial_situation(3, &rule, &shoe);
@@ -1072,6 +3067,18 @@ mod tests {
         println!("Expectation is {}", sol.get_total_expectation());
     }
 
+    #[test]
+    fn negative_side_bet_lowers_combined_ev() {
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[1, 1, 1, 1, 1, 1, 1, 1, 1, 4]);
+        assert!(side_bet_expectation(SideBet::PerfectPairs, &shoe) < 0.0);
+        let main_only = combined_ev(0, &rule, &shoe, 100, 0, SideBet::PerfectPairs);
+        let with_side_bet = combined_ev(0, &rule, &shoe, 100, 10, SideBet::PerfectPairs);
+        assert!(with_side_bet < main_only);
+        let gt = main_only + 10.0 * side_bet_expectation(SideBet::PerfectPairs, &shoe);
+        assert!((with_side_bet - gt).abs() < 1e-9);
+    }
+
     fn decision_to_char(decision: Decision) -> char {
         match decision {
             Decision::Hit => 'H',
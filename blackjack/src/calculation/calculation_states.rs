@@ -56,7 +56,7 @@ fn gather_hand_count_states_aux<F, T: Copy + Default>(
     });
 
     if current_card_count.get_sum() >= 21
-        || current_card_count.get_total() == *charlie_number as u16
+        || (*charlie_number != 0 && current_card_count.get_total() >= *charlie_number as u16)
     {
         return;
     }
@@ -81,8 +81,11 @@ fn gather_hand_count_states_aux<F, T: Copy + Default>(
     }
 }
 
+#[allow(dead_code)]
 pub fn gather_dealer_count_states<F>(
-    dealer_hit_on_soft17: bool,
+    dealer_stand_value: u16,
+    dealer_hit_on_soft: bool,
+    dealer_soft_hit_up_to: Option<u16>,
     mut feature_fn: F,
 ) -> Vec<Vec<CardCount>>
 where
@@ -92,7 +95,9 @@ where
     let mut card_count = CardCount::with_number_of_decks(0);
     let mut is_visited = StateArray::new();
     gather_dealer_count_states_aux(
-        &dealer_hit_on_soft17,
+        dealer_stand_value,
+        dealer_hit_on_soft,
+        dealer_soft_hit_up_to,
         &mut feature_fn,
         &mut card_count,
         &mut is_visited,
@@ -101,8 +106,11 @@ where
     ret
 }
 
+#[allow(dead_code)]
 fn gather_dealer_count_states_aux<F>(
-    dealer_hit_on_soft17: &bool,
+    dealer_stand_value: u16,
+    dealer_hit_on_soft: bool,
+    dealer_soft_hit_up_to: Option<u16>,
     feature_fn: &mut F,
     current_card_count: &mut CardCount,
     is_visited: &mut StateArray<()>,
@@ -124,17 +132,13 @@ fn gather_dealer_count_states_aux<F>(
 
     let must_stand = {
         let actual_sum = current_card_count.get_actual_sum();
-        let is_soft = current_card_count.is_soft();
-        if actual_sum > 17 {
-            true
-        } else if actual_sum < 17 {
-            false
-        } else {
-            if !is_soft {
-                true
-            } else {
-                !dealer_hit_on_soft17
+        if current_card_count.can_be_soft_total(actual_sum) {
+            match dealer_soft_hit_up_to {
+                Some(dealer_soft_hit_up_to) => actual_sum > dealer_soft_hit_up_to,
+                None => actual_sum >= dealer_stand_value + dealer_hit_on_soft as u16,
             }
+        } else {
+            actual_sum >= dealer_stand_value
         }
     };
 
@@ -145,7 +149,9 @@ fn gather_dealer_count_states_aux<F>(
     for i in 1..=10 {
         current_card_count.add_card(i);
         gather_dealer_count_states_aux(
-            dealer_hit_on_soft17,
+            dealer_stand_value,
+            dealer_hit_on_soft,
+            dealer_soft_hit_up_to,
             feature_fn,
             current_card_count,
             is_visited,
@@ -180,12 +186,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn empty_hand_draws_without_charlie_rule() {
+        let f = |card_count: &CardCount| card_count.get_total() as usize;
+        let initial_hand = CardCount::with_number_of_decks(0);
+        let initial_shoe = CardCount::with_number_of_decks(1);
+        let gathered_states =
+            gather_hand_count_states(&initial_hand, &initial_shoe, 0, f, &StateArray::<()>::new());
+        // Without a Charlie rule, the longest hand is A,A,A,A,2,2,2,2,3,3,3 with 11 cards.
+        assert_eq!(gathered_states.len(), 12);
+        assert_eq!(gathered_states[1].len(), 10);
+    }
+
     #[test]
     #[ignore]
     fn get_number_of_dealer_states() {
-        let dealer_hit_on_soft17 = false;
+        let dealer_stand_value = 17;
+        let dealer_hit_on_soft = false;
+        let dealer_soft_hit_up_to = None;
         let f = |card_count: &CardCount| card_count.get_actual_sum() as usize;
-        let gathered_states = gather_dealer_count_states(dealer_hit_on_soft17, f);
+        let gathered_states = gather_dealer_count_states(
+            dealer_stand_value,
+            dealer_hit_on_soft,
+            dealer_soft_hit_up_to,
+            f,
+        );
         let mut acc = 0;
         for (i, states) in gathered_states.iter().enumerate() {
             acc += states.len();
@@ -0,0 +1,523 @@
+use super::decision_chart::{get_initial_expectations, sort_expectations};
+use super::index_plays::{
+    get_index_play_advantage, get_shoe_with_true_count, get_true_count, index_deviations_of_shoe,
+};
+use super::{calculate_solution_without_initial_situation, starting_hand_frequencies};
+use crate::strategy::edge_based_bet;
+use crate::{CardCount, Decision, Rule};
+
+/// The tags of the Hi-Lo counting system, from Ace to 10.
+const HI_LO_TAGS: [i32; 10] = [-1, 1, 1, 1, 1, 1, 0, 0, 0, -1];
+
+/// The approximate player advantage gained per Hi-Lo true count.
+const ADVANTAGE_PER_TRUE_COUNT: f64 = 0.005;
+
+/// The maximum bet of the counter in the betting model, in units of the minimum bet.
+const MAX_BET_SPREAD: u32 = 16;
+
+/// The effects of removal of each card value (from Ace to 10) on the expectation of insurance, up
+/// to a scale. Removing any card other than 10 makes insurance better, and removing a 10 makes it
+/// worse, where the effects of the 13 ranks sum to 0.
+const INSURANCE_EFFECTS_OF_REMOVAL: [f64; 10] =
+    [1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, -2.25];
+
+/// Returns the number of ranks in a deck of the card value of the given index, i.e., 4 for 10
+/// (whose index is 9) and 1 for the others.
+fn get_ranks(index: usize) -> f64 {
+    if index == 9 {
+        4.0
+    } else {
+        1.0
+    }
+}
+
+/// Returns the correlation between the tags (from Ace to 10) and the given effects of removal,
+/// taken over the 13 ranks of a deck, or 0 if either of them is constant.
+fn get_correlation(tags: &[i32; 10], effects_of_removal: &[f64; 10]) -> f64 {
+    let mean = |values: &dyn Fn(usize) -> f64| {
+        (0..10).map(|i| get_ranks(i) * values(i)).sum::<f64>() / 13.0
+    };
+    let tag = |i: usize| tags[i] as f64;
+    let effect = |i: usize| effects_of_removal[i];
+    let mean_tag = mean(&tag);
+    let mean_effect = mean(&effect);
+
+    let mut covariance = 0.0;
+    let mut variance_tag = 0.0;
+    let mut variance_effect = 0.0;
+    for i in 0..10 {
+        let d_tag = tag(i) - mean_tag;
+        let d_effect = effect(i) - mean_effect;
+        covariance += get_ranks(i) * d_tag * d_effect;
+        variance_tag += get_ranks(i) * d_tag * d_tag;
+        variance_effect += get_ranks(i) * d_effect * d_effect;
+    }
+    if variance_tag == 0.0 || variance_effect == 0.0 {
+        return 0.0;
+    }
+    covariance / (variance_tag * variance_effect).sqrt()
+}
+
+/// Returns the variance of the given effects of removal (from Ace to 10), taken over the 13 ranks
+/// of a deck.
+fn get_variance(effects_of_removal: &[f64; 10]) -> f64 {
+    let mean = (0..10)
+        .map(|i| get_ranks(i) * effects_of_removal[i])
+        .sum::<f64>()
+        / 13.0;
+    (0..10)
+        .map(|i| get_ranks(i) * (effects_of_removal[i] - mean).powi(2))
+        .sum::<f64>()
+        / 13.0
+}
+
+/// Returns the expectation of max(X, 0), where X is normally distributed with the given mean and
+/// standard deviation, i.e., the gain of deviating from a play whose expectation is worse by X.
+fn get_deviation_gain(mean: f64, std_dev: f64) -> f64 {
+    if std_dev == 0.0 {
+        return mean.max(0.0);
+    }
+    let z = mean / std_dev;
+    let density = (-z * z / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt();
+    // The logistic approximation of the standard normal CDF.
+    let cdf = 1.0 / (1.0 + (-1.702 * z).exp());
+    std_dev * density + mean * cdf
+}
+
+/// Returns the insurance correlation of a counting system, i.e., how well the tags (from Ace to
+/// 10) predict the density of 10s, which decides whether insurance is worth buying. A positive
+/// tag should be given to the cards whose removal makes the count higher, as in Hi-Lo.
+pub fn insurance_correlation(tags: &[i32; 10]) -> f64 {
+    get_correlation(tags, &INSURANCE_EFFECTS_OF_REMOVAL)
+}
+
+/// Estimates the playing efficiency of a counting system, i.e., the proportion of the gain from
+/// deviating from basic strategy that a counter using the tags (from Ace to 10) can capture.
+///
+/// For each initial situation, the effects of removal of each card value on the difference
+/// between the expectations of the best and the second best decisions are calculated. Half way
+/// through the shoe, the difference is modeled by a normal distribution whose spread comes from
+/// these effects, and the count predicts the part of it that correlates with the tags. Only the
+/// first decision on the initial hands is considered, and insurance is not.
+pub fn playing_efficiency(number_of_threads: usize, rule: &Rule, tags: &[i32; 10]) -> f64 {
+    let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+    let situations = get_first_decision_effects(number_of_threads, rule, &shoe);
+    get_playing_efficiency(&situations, shoe.get_total(), tags)
+}
+
+/// Returns the probability of each initial situation of the given shoe (except those with a single
+/// possible decision), the difference between the expectations of its second best and best first
+/// decisions, and the effects of removal of each card value on this difference. The effect of
+/// removing a card is measured by adding one more card of the value and negating the change, which
+/// is the same up to the second order, so that it is also defined for a situation that needs all
+/// the cards of a value in the shoe.
+fn get_first_decision_effects(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+) -> Vec<(f64, f64, [f64; 10])> {
+    let solve = |shoe: &CardCount| {
+        calculate_solution_without_initial_situation(number_of_threads, rule, shoe)
+    };
+    let solution = solve(shoe);
+    let solutions_after_addition: Vec<_> = (1..=10)
+        .map(|card| {
+            let mut shoe = *shoe;
+            shoe.add_card(card);
+            solve(&shoe)
+        })
+        .collect();
+    let number_of_cards = shoe.get_total() as f64;
+
+    let mut situations = Vec::new();
+    for dealer_up_card in 1..=10 {
+        let p_up_card = shoe[dealer_up_card] as f64 / number_of_cards;
+        if p_up_card == 0.0 {
+            continue;
+        }
+        let mut shoe_after_up_card = *shoe;
+        shoe_after_up_card.remove_card(dealer_up_card);
+        for (hand, p_hand) in starting_hand_frequencies(&shoe_after_up_card) {
+            if p_hand == 0.0 {
+                continue;
+            }
+            let mut exs = get_initial_expectations(&solution, rule, hand, dealer_up_card);
+            sort_expectations(&mut exs);
+            if exs.len() < 2 {
+                continue;
+            }
+            let (best, second_best) = (exs[0].0, exs[1].0);
+            let get_difference = |exs: Vec<(Decision, f64)>| {
+                let get_ex = |decision| exs.iter().find(|&&(d, _)| d == decision).unwrap().1;
+                get_ex(second_best) - get_ex(best)
+            };
+            let difference = exs[1].1 - exs[0].1;
+            let mut effects_of_removal = [0.0; 10];
+            for (effect, solution) in effects_of_removal.iter_mut().zip(&solutions_after_addition) {
+                let exs = get_initial_expectations(solution, rule, hand, dealer_up_card);
+                *effect = difference - get_difference(exs);
+            }
+            situations.push((p_up_card * p_hand, difference, effects_of_removal));
+        }
+    }
+    situations
+}
+
+/// Returns the playing efficiency (see `playing_efficiency`) of the given tags, from the situations
+/// given by `get_first_decision_effects` on a shoe of `number_of_cards` cards.
+fn get_playing_efficiency(
+    situations: &[(f64, f64, [f64; 10])],
+    number_of_cards: u16,
+    tags: &[i32; 10],
+) -> f64 {
+    let mut counter_gain = 0.0;
+    let mut perfect_gain = 0.0;
+    for (p, difference, effects_of_removal) in situations {
+        // Half way through the shoe, the sum of the effects of removal of the dealt cards has a
+        // variance of about a quarter of the number of cards times that of the effects.
+        let std_dev = (number_of_cards as f64 / 4.0 * get_variance(effects_of_removal)).sqrt();
+        let correlation = get_correlation(tags, effects_of_removal).abs();
+        perfect_gain += p * get_deviation_gain(*difference, std_dev);
+        counter_gain += p * get_deviation_gain(*difference, correlation * std_dev);
+    }
+    counter_gain / perfect_gain
+}
+
+/// Estimates the expectation per round (in units of the minimum bet) of a Hi-Lo counter at each
+/// of the given penetrations (the proportions of cards dealt before the shoe is reshuffled).
+///
+/// The counter bets by the count-indexed betting model of `edge_based_bet`, with a spread up to
+/// 16 units, where the advantage at a true count is the expectation of a fresh shoe plus 0.5% per
+/// true count. The true count at each depth of the shoe is approximated by a normal distribution.
+/// The spread is also capped by the table maximum of the rule, which is converted to units by the
+/// given minimum bet of the counter (taken as 1 if it is 0). The counter plays basic strategy, i.e., index plays are not
+/// taken into account.
+pub fn penetration_value(
+    number_of_threads: usize,
+    rule: &Rule,
+    penetrations: &[f64],
+    min_bet: u32,
+) -> Vec<(f64, f64)> {
+    let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+    get_penetration_values(
+        number_of_threads,
+        rule,
+        &shoe,
+        penetrations,
+        min_bet,
+        MAX_BET_SPREAD,
+        &[],
+    )
+}
+
+/// Returns whether a Hi-Lo counter can get a positive expectation in the given game, where the
+/// shoe is reshuffled after `penetration` of the cards are dealt (0 for a continuous shuffling
+/// machine), and the counter bets from `min_bet` up to `spread` units (or the table maximum). The
+/// same model as `penetration_value` is used, except that the counter also makes the Hi-Lo index
+/// plays found by `index_deviations`.
+pub fn is_countable(
+    number_of_threads: usize,
+    rule: &Rule,
+    penetration: f64,
+    min_bet: u32,
+    spread: u32,
+) -> bool {
+    let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+    is_countable_of_shoe(number_of_threads, rule, &shoe, penetration, min_bet, spread)
+}
+
+/// Same as `is_countable`, but for the given fresh shoe.
+fn is_countable_of_shoe(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+    penetration: f64,
+    min_bet: u32,
+    spread: u32,
+) -> bool {
+    let index_play_gains = get_index_play_gains(number_of_threads, rule, shoe);
+    let values = get_penetration_values(
+        number_of_threads,
+        rule,
+        shoe,
+        &[penetration],
+        min_bet,
+        spread,
+        &index_play_gains,
+    );
+    values[0].1 > 0.0
+}
+
+/// Returns the probability of the situation of each Hi-Lo index play in a round, its index, and how
+/// much deviating from basic strategy gains per true count beyond the index. The gain is taken as
+/// linear in the true count, with the slope measured between the index and the true count below.
+/// Only the hand of each index play is counted (e.g., 10 and 6 but not 9 and 7 for hard 16), so
+/// the gain is underestimated. Insurance is offered on any hand. The index plays are searched on
+/// the given fresh shoe.
+fn get_index_play_gains(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+) -> Vec<(f64, i32, f64)> {
+    let shoe = *shoe;
+    let number_of_cards = shoe.get_total() as f64;
+    index_deviations_of_shoe(number_of_threads, rule, &shoe, &HI_LO_TAGS)
+        .iter()
+        .map(|index_play| {
+            let get_advantage = |true_count| {
+                get_index_play_advantage(
+                    number_of_threads,
+                    rule,
+                    &shoe,
+                    &HI_LO_TAGS,
+                    index_play,
+                    true_count,
+                )
+            };
+            let slope = get_advantage(index_play.index) - get_advantage(index_play.index - 1);
+
+            let dealer_up_card = index_play.dealer_up_card;
+            let mut probability = shoe[dealer_up_card] as f64 / number_of_cards;
+            if index_play.decision_at_or_above != Decision::Insurance {
+                let mut shoe = shoe;
+                shoe.remove_card(dealer_up_card);
+                probability *= starting_hand_frequencies(&shoe)
+                    .into_iter()
+                    .find(|&(hand, _)| hand == index_play.hand)
+                    .map_or(0.0, |(_, p_hand)| p_hand);
+            }
+            (probability, index_play.index, slope)
+        })
+        .collect()
+}
+
+/// Returns the change of the player expectation per Hi-Lo true count, i.e., the slope of the line
+/// fitted (by least squares) to the expectations of shoes with true counts from -2 to 2, where half
+/// of the cards are dealt.
+pub fn advantage_slope(number_of_threads: usize, rule: &Rule) -> f64 {
+    let full_shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+    advantage_slope_of_shoe(number_of_threads, rule, &full_shoe)
+}
+
+/// Same as `advantage_slope`, but half of the cards of the given shoe are dealt instead of a fresh
+/// one. The line is fitted against the actual true counts of the shoes, which can be far from the
+/// targets when the shoe is small.
+fn advantage_slope_of_shoe(number_of_threads: usize, rule: &Rule, full_shoe: &CardCount) -> f64 {
+    const TRUE_COUNTS: [i32; 5] = [-2, -1, 0, 1, 2];
+    let points: Vec<(f64, f64)> = TRUE_COUNTS
+        .iter()
+        .map(|&true_count| {
+            let shoe = get_shoe_with_true_count(full_shoe, &HI_LO_TAGS, true_count);
+            let ex = calculate_solution_without_initial_situation(number_of_threads, rule, &shoe)
+                .get_total_expectation();
+            (get_true_count(full_shoe, &shoe, &HI_LO_TAGS), ex)
+        })
+        .collect();
+
+    let n = points.len() as f64;
+    let mean_count = points
+        .iter()
+        .map(|&(true_count, _)| true_count)
+        .sum::<f64>()
+        / n;
+    let mean_ex = points.iter().map(|&(_, ex)| ex).sum::<f64>() / n;
+    let mut covariance = 0.0;
+    let mut variance = 0.0;
+    for &(true_count, ex) in &points {
+        let d_count = true_count - mean_count;
+        covariance += d_count * (ex - mean_ex);
+        variance += d_count * d_count;
+    }
+    covariance / variance
+}
+
+/// Same as `penetration_value`, but for the given fresh shoe, and the counter bets up to
+/// `max_bet_spread` units and makes the index plays of the given gains (see
+/// `get_index_play_gains`).
+fn get_penetration_values(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+    penetrations: &[f64],
+    min_bet: u32,
+    max_bet_spread: u32,
+    index_play_gains: &[(f64, i32, f64)],
+) -> Vec<(f64, f64)> {
+    let ex_fresh_shoe = calculate_solution_without_initial_situation(number_of_threads, rule, shoe)
+        .get_total_expectation();
+    let number_of_cards = shoe.get_total() as f64;
+    let variance_of_tag = (1..=10)
+        .map(|card| shoe[card] as f64 * (HI_LO_TAGS[(card - 1) as usize] as f64).powi(2))
+        .sum::<f64>()
+        / number_of_cards;
+    // The bets below are in units of the minimum bet, and so is the cap. A minimum bet of 0 is
+    // taken as 1.
+    let max_bet = max_bet_spread
+        .min(rule.get_max_bet() / min_bet.max(1))
+        .max(1);
+
+    // The advantage at the given true count. The basic play of an index play is its best play at
+    // the true count 0, so deviating from it gains beyond the index on the other side.
+    let get_advantage = |true_count: f64| {
+        let playing_gain = index_play_gains
+            .iter()
+            .map(|&(probability, index, slope)| {
+                let beyond_index = if index > 0 {
+                    true_count - index as f64
+                } else {
+                    index as f64 - true_count
+                };
+                probability * (slope * beyond_index).max(0.0)
+            })
+            .sum::<f64>();
+        ex_fresh_shoe + ADVANTAGE_PER_TRUE_COUNT * true_count + playing_gain
+    };
+
+    // The expectation of a round with the given standard deviation of the true count.
+    let get_round_expectation = |std_dev: f64| {
+        if std_dev == 0.0 {
+            let ex = get_advantage(0.0);
+            return edge_based_bet(ex, 1, max_bet) as f64 * ex;
+        }
+        const STEPS: i32 = 400;
+        const MAX_Z: f64 = 5.0;
+        let dz = 2.0 * MAX_Z / STEPS as f64;
+        (0..=STEPS)
+            .map(|i| {
+                let z = -MAX_Z + i as f64 * dz;
+                let density = (-z * z / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt();
+                let ex = get_advantage(z * std_dev);
+                density * dz * edge_based_bet(ex, 1, max_bet) as f64 * ex
+            })
+            .sum::<f64>()
+    };
+
+    const DEPTHS: usize = 50;
+    penetrations
+        .iter()
+        .map(|&penetration| {
+            let ex = (0..DEPTHS)
+                .map(|i| {
+                    let cards_dealt = number_of_cards * penetration * i as f64 / DEPTHS as f64;
+                    let cards_left = number_of_cards - cards_dealt;
+                    let variance_of_running_count =
+                        cards_dealt * cards_left / (number_of_cards - 1.0) * variance_of_tag;
+                    let decks_left = cards_left / 52.0;
+                    get_round_expectation(variance_of_running_count.sqrt() / decks_left)
+                })
+                .sum::<f64>()
+                / DEPTHS as f64;
+            (penetration, ex)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DoublePolicy;
+
+    #[test]
+    fn ten_count_predicts_insurance_better_than_hi_lo() {
+        let hi_lo = insurance_correlation(&HI_LO_TAGS);
+        let ten_count = insurance_correlation(&[4, 4, 4, 4, 4, 4, 4, 4, 4, -9]);
+        assert!(hi_lo > 0.7 && hi_lo < 0.8);
+        assert!((ten_count - 1.0).abs() < 1e-12);
+        assert!(hi_lo < ten_count);
+        assert_eq!(insurance_correlation(&[0; 10]), 0.0);
+    }
+
+    #[test]
+    fn constant_effects_have_no_correlation() {
+        assert_eq!(get_correlation(&HI_LO_TAGS, &[0.0; 10]), 0.0);
+        assert_eq!(get_correlation(&HI_LO_TAGS, &[0.01; 10]), 0.0);
+        assert_eq!(get_correlation(&[0; 10], &[0.0; 10]), 0.0);
+    }
+
+    #[test]
+    fn advantage_grows_with_true_count() {
+        // Half of a 26-card shoe is dealt, so each shoe has only 13 cards left.
+        let rule = Rule::default();
+        let full_shoe = CardCount::new(&[2, 2, 2, 2, 2, 2, 2, 2, 2, 8]);
+        let slope = advantage_slope_of_shoe(0, &rule, &full_shoe);
+        assert!(slope > 0.003 && slope < 0.008);
+    }
+
+    #[test]
+    fn playing_efficiency_of_hi_lo() {
+        // The situations are solved once on a 13-card shoe and shared by all the counting systems.
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[1, 1, 1, 1, 1, 1, 1, 1, 1, 4]);
+        let situations = get_first_decision_effects(0, &rule, &shoe);
+        let get_efficiency = |tags| get_playing_efficiency(&situations, shoe.get_total(), tags);
+        let hi_lo = get_efficiency(&HI_LO_TAGS);
+        assert!(hi_lo > 0.4 && hi_lo < 0.6);
+        // The multi-level Omega II is known to play better.
+        let omega_2 = get_efficiency(&[0, 1, 1, 2, 2, 2, 1, 0, -1, -2]);
+        assert!(omega_2 > hi_lo);
+        assert_eq!(get_efficiency(&[0; 10]), 0.0);
+    }
+
+    #[test]
+    fn deeper_penetration_is_more_valuable() {
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[1, 1, 1, 1, 1, 1, 1, 1, 1, 3]);
+        let get_values = |rule: &Rule, penetrations: &[f64], max_bet_spread| {
+            get_penetration_values(0, rule, &shoe, penetrations, 10, max_bet_spread, &[])
+        };
+        let values = get_values(&rule, &[0.5, 0.75, 0.9], MAX_BET_SPREAD);
+        assert_eq!(values.len(), 3);
+        assert!(values[0].1 < values[1].1);
+        assert!(values[1].1 < values[2].1);
+
+        // A table maximum of 40 caps the spread of a counter betting 10 at 4 units.
+        let capped_rule = Rule {
+            max_bet: 40,
+            ..rule
+        };
+        let capped_values = get_values(&capped_rule, &[0.9], MAX_BET_SPREAD);
+        assert!(capped_values[0].1 < values[2].1);
+        assert_eq!(capped_values, get_values(&rule, &[0.9], 4));
+
+        // A minimum bet of 0 doesn't divide the table maximum by 0.
+        let values = get_penetration_values(0, &capped_rule, &shoe, &[0.9], 0, 4, &[]);
+        assert!(values[0].1.is_finite());
+    }
+
+    #[test]
+    fn deeply_dealt_game_is_countable() {
+        // A full shoe is too slow to solve repeatedly in a test, so this uses a
+        // 13-card shoe with a single card of each rank but ten.
+        let shoe = CardCount::new(&[1, 1, 1, 1, 1, 1, 1, 1, 1, 3]);
+        let rule = Rule {
+            allow_das: true,
+            allow_late_surrender: true,
+            allow_surrender_after_split: false,
+            ..Rule::default()
+        };
+        assert!(is_countable_of_shoe(0, &rule, &shoe, 0.85, 10, 12));
+
+        // The index plays add to the advantage of betting.
+        let index_play_gains = get_index_play_gains(0, &rule, &shoe);
+        assert!(!index_play_gains.is_empty());
+        assert!(index_play_gains
+            .iter()
+            .all(|&(probability, _, slope)| probability > 0.0 && slope > 0.0));
+        let get_value = |index_play_gains| {
+            get_penetration_values(0, &rule, &shoe, &[0.85], 10, 12, index_play_gains)[0].1
+        };
+        assert!(get_value(&index_play_gains) > get_value(&[]));
+
+        // Such a small shoe favors the player even at 6:5, so it takes an even
+        // money blackjack and a dealer hitting soft 17 to give the house the
+        // edge a continuous shuffler cannot count away.
+        let rule = Rule {
+            payout_blackjack: 1.0,
+            dealer_hit_on_soft: true,
+            charlie_number: 0,
+            double_policy: DoublePolicy::TenElevenOnly,
+            ..rule
+        };
+        assert!(!is_countable_of_shoe(0, &rule, &shoe, 0.0, 10, 12));
+    }
+}
@@ -0,0 +1,430 @@
+use super::{
+    calculate_solution_without_initial_situation, get_initial_hand_index, is_better_decision,
+    SolutionForBettingPhase,
+};
+use crate::{CardCount, Decision, Rule};
+use std::collections::HashMap;
+
+/// The best first decision for each initial hand against each dealer up card.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecisionChart {
+    decisions: [[Decision; 55]; 10],
+}
+
+impl Default for DecisionChart {
+    fn default() -> Self {
+        DecisionChart {
+            decisions: [[Decision::PlaceHolder; 55]; 10],
+        }
+    }
+}
+
+impl DecisionChart {
+    /// Returns the decision for the given initial hand (in any order) and dealer up card.
+    pub fn get(&self, hand: (u8, u8), dealer_up_card: u8) -> Decision {
+        self.decisions[(dealer_up_card - 1) as usize][get_initial_hand_index(hand)]
+    }
+
+    fn set(&mut self, hand: (u8, u8), dealer_up_card: u8, decision: Decision) {
+        self.decisions[(dealer_up_card - 1) as usize][get_initial_hand_index(hand)] = decision;
+    }
+
+    /// Checks that every entry of the chart is a decision the given rule allows, e.g. no
+    /// Surrender when late surrender is not allowed, and no Split of a non-pair. Returns a
+    /// description of each violation otherwise.
+    pub fn validate(&self, rule: &Rule) -> Result<(), Vec<String>> {
+        let mut violations = Vec::new();
+        for dealer_up_card in 1..=10 {
+            for first_hand_card in 1..=10 {
+                for second_hand_card in 1..=first_hand_card {
+                    let hand = (first_hand_card, second_hand_card);
+                    let decision = self.get(hand, dealer_up_card);
+                    if let Err(reason) = check_initial_decision(rule, hand, decision) {
+                        violations.push(format!(
+                            "{:?} on {:?} against {}: {}",
+                            decision, hand, dealer_up_card, reason
+                        ));
+                    }
+                }
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+/// Checks whether the rule allows making the decision on the given initial hand.
+fn check_initial_decision(rule: &Rule, hand: (u8, u8), decision: Decision) -> Result<(), String> {
+    let mut hand_card_count = CardCount::with_number_of_decks(0);
+    hand_card_count.add_card(hand.0);
+    hand_card_count.add_card(hand.1);
+    match decision {
+        Decision::Hit | Decision::Stand => Ok(()),
+        Decision::Double if !rule.double_policy.allows(&hand_card_count) => Err(format!(
+            "the double policy {} forbids it",
+            rule.double_policy
+        )),
+        Decision::Double => Ok(()),
+        Decision::Surrender if !rule.allow_late_surrender => {
+            Err(String::from("surrender is not allowed"))
+        }
+        Decision::Surrender => Ok(()),
+        Decision::Split if hand.0 != hand.1 => Err(String::from("the hand is not a pair")),
+        Decision::Split => {
+            let limits = if hand.0 == 1 {
+                rule.split_all_limits.min(rule.split_ace_limits)
+            } else {
+                rule.split_all_limits
+            };
+            if limits == 0 {
+                Err(String::from("split is not allowed"))
+            } else {
+                Ok(())
+            }
+        }
+        Decision::Insurance | Decision::PlaceHolder => {
+            Err(String::from("it is not a playing decision"))
+        }
+    }
+}
+
+/// Groups initial hands the way a total-dependent chart does: pairs and soft hands have their own
+/// rows, and other hands are grouped by their hard totals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ChartRow {
+    Pair(u8),
+    Soft(u8),
+    Hard(u8),
+}
+
+/// An initial hand and the number of ways to deal it.
+type WeightedHand = ((u8, u8), f64);
+
+fn get_chart_row(hand: (u8, u8)) -> ChartRow {
+    if hand.0 == hand.1 {
+        ChartRow::Pair(hand.0)
+    } else if hand.0 == 1 || hand.1 == 1 {
+        ChartRow::Soft(hand.0 + hand.1)
+    } else {
+        ChartRow::Hard(hand.0 + hand.1)
+    }
+}
+
+/// Returns the chart where each initial hand gets its own best decision, i.e., the decisions
+/// depend on the exact cards. Hands the shoe of the solution cannot deal are left as PlaceHolder.
+pub fn composition_dependent_chart(
+    solution: &SolutionForBettingPhase,
+    rule: &Rule,
+) -> DecisionChart {
+    let mut chart = DecisionChart::default();
+    for dealer_up_card in 1..=10 {
+        for first_hand_card in 1..=10 {
+            for second_hand_card in 1..=first_hand_card {
+                let hand = (first_hand_card, second_hand_card);
+                if get_hand_weight(&solution.shoe, hand, dealer_up_card) == 0.0 {
+                    continue;
+                }
+                let decision = get_best_initial_decision(solution, rule, hand, dealer_up_card);
+                chart.set(hand, dealer_up_card, decision);
+            }
+        }
+    }
+    chart
+}
+
+/// Calculates a chart for a fresh shoe that is total-dependent except for close decisions. Each
+/// row of a total-dependent chart (e.g. hard 12) takes the decision with the maximum expectation
+/// averaged over the hands in this row. If the top two averaged expectations of a row are within
+/// `epsilon`, each hand in this row takes its own best decision instead.
+///
+/// So a larger `epsilon` refines more rows: an infinite one gives the composition-dependent chart,
+/// and a negative one gives the total-dependent chart. A tiny `epsilon` only refines the rows whose
+/// top two decisions are almost tied, so it is close to the total-dependent chart, not the
+/// composition-dependent one.
+pub fn hybrid_chart(number_of_threads: usize, rule: &Rule, epsilon: f64) -> DecisionChart {
+    let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+    let solution = calculate_solution_without_initial_situation(number_of_threads, rule, &shoe);
+    hybrid_chart_from_solution(&solution, rule, epsilon)
+}
+
+/// Same as `hybrid_chart`, but reads the expectations from the given solution instead of solving
+/// again, e.g., to compare several values of `epsilon`. The hands of a row are weighted by the shoe
+/// the solution is solved with, and hands this shoe cannot deal are left as PlaceHolder.
+pub fn hybrid_chart_from_solution(
+    solution: &SolutionForBettingPhase,
+    rule: &Rule,
+    epsilon: f64,
+) -> DecisionChart {
+    let shoe = &solution.shoe;
+    let mut chart = DecisionChart::default();
+    for dealer_up_card in 1..=10 {
+        let mut rows: HashMap<ChartRow, Vec<WeightedHand>> = HashMap::new();
+        for first_hand_card in 1..=10 {
+            for second_hand_card in 1..=first_hand_card {
+                let hand = (first_hand_card, second_hand_card);
+                let weight = get_hand_weight(shoe, hand, dealer_up_card);
+                if weight == 0.0 {
+                    continue;
+                }
+                rows.entry(get_chart_row(hand))
+                    .or_default()
+                    .push((hand, weight));
+            }
+        }
+
+        for hands in rows.values() {
+            let total_weight: f64 = hands.iter().map(|&(_, weight)| weight).sum();
+            let mut row_exs: Vec<(Decision, f64)> = Vec::new();
+            for &(hand, weight) in hands {
+                let exs = get_initial_expectations(solution, rule, hand, dealer_up_card);
+                if row_exs.is_empty() {
+                    row_exs = exs.iter().map(|&(decision, _)| (decision, 0.0)).collect();
+                }
+                for (row_ex, (_, ex)) in row_exs.iter_mut().zip(exs) {
+                    row_ex.1 += ex * weight / total_weight;
+                }
+            }
+            sort_expectations(&mut row_exs);
+
+            let is_close = row_exs.len() > 1 && row_exs[0].1 - row_exs[1].1 <= epsilon;
+            for &(hand, _) in hands {
+                let decision = if is_close {
+                    get_best_initial_decision(solution, rule, hand, dealer_up_card)
+                } else {
+                    row_exs[0].0
+                };
+                chart.set(hand, dealer_up_card, decision);
+            }
+        }
+    }
+    chart
+}
+
+fn get_best_initial_decision(
+    solution: &SolutionForBettingPhase,
+    rule: &Rule,
+    hand: (u8, u8),
+    dealer_up_card: u8,
+) -> Decision {
+    let mut exs = get_initial_expectations(solution, rule, hand, dealer_up_card);
+    sort_expectations(&mut exs);
+    exs[0].0
+}
+
+/// Returns the number of ways to deal the given initial hand after the dealer up card is dealt.
+/// An empty shoe means all hands are weighted equally.
+fn get_hand_weight(shoe: &CardCount, hand: (u8, u8), dealer_up_card: u8) -> f64 {
+    if shoe.get_total() == 0 {
+        return 1.0;
+    }
+    let mut shoe = *shoe;
+    shoe.remove_card(dealer_up_card);
+    let n = |card: u8| shoe[card] as f64;
+    if hand.0 == hand.1 {
+        n(hand.0) * (n(hand.0) - 1.0)
+    } else {
+        2.0 * n(hand.0) * n(hand.1)
+    }
+}
+
+/// Returns the expectations of all decisions of the given initial hand, in a fixed order. An
+/// impossible decision has an expectation of -inf.
+pub(super) fn get_initial_expectations(
+    solution: &SolutionForBettingPhase,
+    rule: &Rule,
+    hand: (u8, u8),
+    dealer_up_card: u8,
+) -> Vec<(Decision, f64)> {
+    let d = (dealer_up_card - 1) as usize;
+    let mut hand_card_count = CardCount::with_number_of_decks(0);
+    hand_card_count.add_card(hand.0);
+    hand_card_count.add_card(hand.1);
+    let ex_stand_hit = solution.exs_stand_hit[d][&hand_card_count];
+    let other = &solution.exs_other_decisions[d][get_initial_hand_index(hand)];
+    let ex_surrender = if rule.allow_late_surrender {
+        -0.5
+    } else {
+        -f64::INFINITY
+    };
+    vec![
+        (Decision::Stand, ex_stand_hit.stand),
+        (Decision::Hit, ex_stand_hit.hit),
+        (Decision::Double, other.ex_double),
+        (Decision::Split, other.ex_split),
+        (Decision::Surrender, ex_surrender),
+    ]
+}
+
+/// Sorts the expectations from the best decision to the worst one, and removes the impossible
+/// decisions.
+pub(super) fn sort_expectations(exs: &mut Vec<(Decision, f64)>) {
+    exs.retain(|&(_, ex)| ex != -f64::INFINITY);
+    exs.sort_by(|&(d1, ex1), &(d2, ex2)| {
+        if is_better_decision(ex1, d1, ex2, d2) {
+            std::cmp::Ordering::Less
+        } else {
+            std::cmp::Ordering::Greater
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculation::Expectation;
+
+    #[test]
+    fn chart_rows() {
+        assert_eq!(get_chart_row((8, 8)), ChartRow::Pair(8));
+        assert_eq!(get_chart_row((7, 1)), ChartRow::Soft(8));
+        assert_eq!(get_chart_row((10, 2)), ChartRow::Hard(12));
+        assert_eq!(get_chart_row((4, 8)), ChartRow::Hard(12));
+    }
+
+    #[test]
+    fn surrender_cells_are_invalid_without_surrender() {
+        let mut rule = Rule::default();
+        let mut chart = DecisionChart {
+            decisions: [[Decision::Stand; 55]; 10],
+        };
+        assert_eq!(chart.validate(&rule), Ok(()));
+
+        chart.set((10, 6), 10, Decision::Surrender);
+        chart.set((9, 6), 10, Decision::Surrender);
+        chart.set((8, 8), 1, Decision::Split);
+        let violations = chart.validate(&rule).unwrap_err();
+        assert_eq!(violations.len(), 2);
+        assert!(violations.iter().all(|v| v.starts_with("Surrender")));
+
+        rule.allow_late_surrender = true;
+        assert_eq!(chart.validate(&rule), Ok(()));
+        rule.double_policy = crate::DoublePolicy::TenElevenOnly;
+        chart.set((6, 3), 3, Decision::Double);
+        chart.set((10, 7), 3, Decision::Split);
+        assert_eq!(chart.validate(&rule).unwrap_err().len(), 2);
+    }
+
+    /// Returns a solution of a fresh shoe where Stand is the best decision of every initial hand,
+    /// except hard 12 against 2, where Hit is much better on 10 and 2 only. Averaged over the
+    /// row, Hit is better by 0.11.
+    fn get_solution_with_a_close_row(rule: &Rule) -> SolutionForBettingPhase {
+        let mut solution = SolutionForBettingPhase {
+            shoe: CardCount::with_number_of_decks(rule.number_of_decks),
+            ..Default::default()
+        };
+        for dealer_up_card in 1..=10 {
+            let d = (dealer_up_card - 1) as usize;
+            for first_hand_card in 1..=10 {
+                for second_hand_card in 1..=first_hand_card {
+                    let hand = (first_hand_card, second_hand_card);
+                    let mut hand_card_count = CardCount::with_number_of_decks(0);
+                    hand_card_count.add_card(hand.0);
+                    hand_card_count.add_card(hand.1);
+                    let hit = match (hand, dealer_up_card) {
+                        ((10, 2), 2) => 0.1,
+                        ((9, 3) | (8, 4) | (7, 5), 2) => -0.11,
+                        _ => -0.2,
+                    };
+                    solution.exs_stand_hit[d][&hand_card_count] = Expectation {
+                        hit,
+                        stand: -0.1,
+                        ..Default::default()
+                    };
+                    let other = &mut solution.exs_other_decisions[d][get_initial_hand_index(hand)];
+                    other.ex_double = -f64::INFINITY;
+                    other.ex_split = -f64::INFINITY;
+                }
+            }
+        }
+        solution
+    }
+
+    #[test]
+    fn hybrid_chart_refines_close_rows_only() {
+        let rule = Rule::default();
+        let solution = get_solution_with_a_close_row(&rule);
+        let full = composition_dependent_chart(&solution, &rule);
+        assert_eq!(full.get((10, 2), 2), Decision::Hit);
+        assert_eq!(full.get((9, 3), 2), Decision::Stand);
+
+        // The same solution is reused for each epsilon.
+        assert_eq!(hybrid_chart_from_solution(&solution, &rule, 0.2), full);
+        let total_dependent = hybrid_chart_from_solution(&solution, &rule, 0.05);
+        for hand in [(10, 2), (9, 3), (8, 4), (7, 5)] {
+            assert_eq!(total_dependent.get(hand, 2), Decision::Hit);
+            assert_eq!(total_dependent.get(hand, 3), Decision::Stand);
+        }
+        // Pairs make their own row.
+        assert_eq!(total_dependent.get((6, 6), 2), Decision::Stand);
+        assert_eq!(
+            hybrid_chart_from_solution(&solution, &rule, -1.0),
+            total_dependent
+        );
+    }
+
+    #[test]
+    fn hybrid_chart_bounds() {
+        // A full shoe is too slow to solve in a test, so this uses an 18-card shoe, where hands
+        // such as a pair of 3s cannot be dealt.
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[2, 1, 1, 1, 1, 1, 1, 1, 1, 6]);
+        let solution = calculate_solution_without_initial_situation(0, &rule, &shoe);
+        let mut dealt_cells = Vec::new();
+        for dealer_up_card in 1..=10 {
+            for first_hand_card in 1..=10 {
+                for second_hand_card in 1..=first_hand_card {
+                    let hand = (first_hand_card, second_hand_card);
+                    if get_hand_weight(&shoe, hand, dealer_up_card) > 0.0 {
+                        dealt_cells.push((hand, dealer_up_card));
+                    }
+                }
+            }
+        }
+
+        // The composition-dependent chart takes the decision of the maximum expectation of each
+        // hand.
+        let full = composition_dependent_chart(&solution, &rule);
+        for &(hand, dealer_up_card) in &dealt_cells {
+            let (best_decision, _) =
+                get_initial_expectations(&solution, &rule, hand, dealer_up_card)
+                    .into_iter()
+                    .max_by(|(_, ex1), (_, ex2)| ex1.partial_cmp(ex2).unwrap())
+                    .unwrap();
+            assert_eq!(full.get(hand, dealer_up_card), best_decision);
+        }
+        assert_eq!(full.get((3, 3), 2), Decision::PlaceHolder);
+
+        // An infinite epsilon refines every row, which gives the composition-dependent chart.
+        assert_eq!(
+            hybrid_chart_from_solution(&solution, &rule, f64::INFINITY),
+            full
+        );
+
+        // A negative epsilon refines no row, which gives a total-dependent chart.
+        let total_dependent = hybrid_chart_from_solution(&solution, &rule, -1.0);
+        for &(hand1, dealer_up_card) in &dealt_cells {
+            for &(hand2, _) in dealt_cells.iter().filter(|&&(hand2, d)| {
+                d == dealer_up_card && get_chart_row(hand2) == get_chart_row(hand1)
+            }) {
+                assert_eq!(
+                    total_dependent.get(hand1, dealer_up_card),
+                    total_dependent.get(hand2, dealer_up_card)
+                );
+            }
+        }
+        assert_ne!(total_dependent, full);
+
+        // Otherwise, each hand takes either of them.
+        let hybrid = hybrid_chart_from_solution(&solution, &rule, 0.01);
+        for &(hand, dealer_up_card) in &dealt_cells {
+            let decision = hybrid.get(hand, dealer_up_card);
+            assert!(
+                decision == full.get(hand, dealer_up_card)
+                    || decision == total_dependent.get(hand, dealer_up_card)
+            );
+        }
+    }
+}
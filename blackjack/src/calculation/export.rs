@@ -0,0 +1,184 @@
+use super::decision_chart::get_initial_expectations;
+use super::{
+    calculate_solution_without_initial_situation, is_better_decision, remove_initial_cards,
+    SolutionForBettingPhase,
+};
+use crate::{CardCount, Rule};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// The file formats that `export_strategy` supports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// One line per initial situation: `dealer_up_card,first_card,second_card,decision,ex`, after
+    /// a header line.
+    Csv,
+
+    /// 12 bytes per initial situation: dealer up card, first card, second card and decision (as
+    /// `Decision as u8`) in one byte each, followed by the expectation as a little-endian f64.
+    Binary,
+}
+
+/// Solves all 550 initial situations of a fresh shoe, and writes the best decision and its
+/// expectation of each of them to the given file, so that the strategy can be used without the
+/// solver. Initial situations are written in the order of dealer up card, then first card, then
+/// second card (not greater than the first card).
+pub fn export_strategy(
+    number_of_threads: usize,
+    rule: &Rule,
+    path: &str,
+    format: ExportFormat,
+) -> Result<(), String> {
+    let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+    let solution = calculate_solution_without_initial_situation(number_of_threads, rule, &shoe);
+    export_solution(&solution, rule, path, format)
+}
+
+fn export_solution(
+    solution: &SolutionForBettingPhase,
+    rule: &Rule,
+    path: &str,
+    format: ExportFormat,
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut writer = BufWriter::new(file);
+    write_strategy(solution, rule, &mut writer, format).map_err(|e| e.to_string())
+}
+
+/// Writes the given snapshot (see `SolutionForBettingPhase::ev_snapshot`) to the given file, one
+/// `key<TAB>ex` line per entry in the order of keys. The expectations are written with full
+/// precision, so `read_ev_snapshot` gets exactly the same values back.
+pub fn write_ev_snapshot(snapshot: &BTreeMap<String, f64>, path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut writer = BufWriter::new(file);
+    for (key, ex) in snapshot {
+        writeln!(writer, "{}\t{:?}", key, ex).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
+
+/// Reads a snapshot written by `write_ev_snapshot`.
+pub fn read_ev_snapshot(path: &str) -> Result<BTreeMap<String, f64>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    content
+        .lines()
+        .map(|line| {
+            let (key, ex) = line
+                .split_once('\t')
+                .ok_or_else(|| format!("Invalid snapshot line: {}", line))?;
+            let ex = ex
+                .parse()
+                .map_err(|_| format!("Invalid expectation: {}", ex))?;
+            Ok((String::from(key), ex))
+        })
+        .collect()
+}
+
+/// Writes the initial situations of the given solution, leaving out those its shoe cannot deal.
+fn write_strategy<W: Write>(
+    solution: &SolutionForBettingPhase,
+    rule: &Rule,
+    writer: &mut W,
+    format: ExportFormat,
+) -> std::io::Result<()> {
+    if format == ExportFormat::Csv {
+        writeln!(writer, "dealer_up_card,first_card,second_card,decision,ex")?;
+    }
+    for dealer_up_card in 1..=10 {
+        for first_hand_card in 1..=10 {
+            for second_hand_card in 1..=first_hand_card {
+                let hand = (first_hand_card, second_hand_card);
+                if remove_initial_cards(&solution.shoe, hand, dealer_up_card).is_none() {
+                    continue;
+                }
+                let (decision, ex) = get_initial_expectations(solution, rule, hand, dealer_up_card)
+                    .into_iter()
+                    .reduce(|best, (decision, ex)| {
+                        if is_better_decision(ex, decision, best.1, best.0) {
+                            (decision, ex)
+                        } else {
+                            best
+                        }
+                    })
+                    .unwrap();
+                match format {
+                    ExportFormat::Csv => writeln!(
+                        writer,
+                        "{},{},{},{:?},{}",
+                        dealer_up_card, first_hand_card, second_hand_card, decision, ex
+                    )?,
+                    ExportFormat::Binary => {
+                        writer.write_all(&[
+                            dealer_up_card,
+                            first_hand_card,
+                            second_hand_card,
+                            decision as u8,
+                        ])?;
+                        writer.write_all(&ex.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Decision;
+
+    #[test]
+    fn export_and_read_back() {
+        // A full shoe is too slow to solve in a test, so this exports the solution of a 15-card
+        // shoe, which cannot deal every initial situation.
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[1, 1, 1, 1, 2, 2, 1, 1, 1, 4]);
+        let solution = calculate_solution_without_initial_situation(0, &rule, &shoe);
+        let mut number_of_situations = 0;
+        for dealer_up_card in 1..=10 {
+            for first_hand_card in 1..=10 {
+                for second_hand_card in 1..=first_hand_card {
+                    let hand = (first_hand_card, second_hand_card);
+                    if remove_initial_cards(&shoe, hand, dealer_up_card).is_some() {
+                        number_of_situations += 1;
+                    }
+                }
+            }
+        }
+        assert!(number_of_situations < 550);
+
+        let dir = std::env::temp_dir();
+        let csv_path = dir.join("blackjack_export_test.csv");
+        let csv_path = csv_path.to_str().unwrap();
+        export_solution(&solution, &rule, csv_path, ExportFormat::Csv).unwrap();
+        let content = std::fs::read_to_string(csv_path).unwrap();
+        std::fs::remove_file(csv_path).unwrap();
+        assert_eq!(content.lines().count(), number_of_situations + 1);
+        assert!(!content.lines().any(|line| line.starts_with("1,1,1,")));
+        // 11 (6 + 5) against 6 doubles.
+        let line = content
+            .lines()
+            .find(|line| line.starts_with("6,6,5,"))
+            .unwrap();
+        let fields: Vec<&str> = line.split(',').collect();
+        assert_eq!(fields[3], "Double");
+        let ex_csv: f64 = fields[4].parse().unwrap();
+        assert!(ex_csv > 0.0);
+
+        let binary_path = dir.join("blackjack_export_test.bin");
+        let binary_path = binary_path.to_str().unwrap();
+        export_solution(&solution, &rule, binary_path, ExportFormat::Binary).unwrap();
+        let content = std::fs::read(binary_path).unwrap();
+        std::fs::remove_file(binary_path).unwrap();
+        assert_eq!(content.len(), number_of_situations * 12);
+        let record = content
+            .chunks(12)
+            .find(|record| record[..3] == [6, 6, 5])
+            .unwrap();
+        assert_eq!(record[3], Decision::Double as u8);
+        let ex_binary = f64::from_le_bytes(record[4..].try_into().unwrap());
+        assert_eq!(ex_binary, ex_csv);
+    }
+}
@@ -0,0 +1,290 @@
+use super::{
+    calculate_solution_with_initial_situation, remove_initial_cards, SolutionForInitialSituation,
+};
+use crate::{CardCount, Decision, InitialSituation, Rule};
+
+/// A play that deviates from the best play of a neutral shoe when the true count is high or low
+/// enough.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexPlay {
+    pub hand: (u8, u8),
+    pub dealer_up_card: u8,
+
+    /// The best decision when the true count is less than `index`. `Decision::Insurance` stands
+    /// for buying insurance, and `Decision::PlaceHolder` for not buying it.
+    pub decision_below: Decision,
+
+    /// The best decision when the true count is at least `index`.
+    pub decision_at_or_above: Decision,
+
+    pub index: i32,
+}
+
+/// The borderline situations to look for index plays, i.e., the situations of the well-known
+/// "Illustrious 18" except the splits, which the solver doesn't support yet. The first one is
+/// insurance. Hard 16 against 10 is represented by 9 and 7, which are neutral in most counting
+/// systems, because the index of this play is known to be sensitive to the hand composition.
+const CANDIDATES: [((u8, u8), u8); 16] = [
+    ((10, 6), 1),
+    ((9, 7), 10),
+    ((10, 5), 10),
+    ((6, 4), 10),
+    ((10, 2), 3),
+    ((10, 2), 2),
+    ((6, 5), 1),
+    ((5, 4), 2),
+    ((6, 4), 1),
+    ((5, 4), 7),
+    ((10, 6), 9),
+    ((10, 3), 2),
+    ((10, 2), 4),
+    ((10, 2), 5),
+    ((10, 2), 6),
+    ((10, 3), 3),
+];
+
+const MIN_TRUE_COUNT: i32 = -10;
+const MAX_TRUE_COUNT: i32 = 10;
+
+/// Finds the index plays for the given counting system, where `count_tags[0]` is the tag of Ace
+/// and `count_tags[9]` is the tag of 10. For each borderline situation, the shoe is perturbed to
+/// each true count from -10 to 10, and the situation is solved again to find the true count at
+/// which the best decision flips. Situations whose best decisions don't flip in this range, or which
+/// cannot be dealt from some of the searched shoes (e.g., of a small shoe), are not returned.
+pub fn index_deviations(
+    number_of_threads: usize,
+    rule: &Rule,
+    count_tags: &[i32; 10],
+) -> Vec<IndexPlay> {
+    let full_shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+    index_deviations_of_shoe(number_of_threads, rule, &full_shoe, count_tags)
+}
+
+/// Same as `index_deviations`, but half of the cards of the given shoe are dealt instead of a
+/// fresh one.
+pub(super) fn index_deviations_of_shoe(
+    number_of_threads: usize,
+    rule: &Rule,
+    full_shoe: &CardCount,
+    count_tags: &[i32; 10],
+) -> Vec<IndexPlay> {
+    let is_insurance = |i: usize| i == 0;
+    let mut index_plays = Vec::new();
+    for (i, &(hand, dealer_up_card)) in CANDIDATES.iter().enumerate() {
+        let decide = |true_count: i32| {
+            let shoe = get_shoe_with_true_count(full_shoe, count_tags, true_count);
+            get_best_decision(
+                number_of_threads,
+                rule,
+                &shoe,
+                hand,
+                dealer_up_card,
+                is_insurance(i),
+            )
+        };
+        let find_index_play = || {
+            let decision_below = decide(MIN_TRUE_COUNT)?;
+            let decision_at_or_above = decide(MAX_TRUE_COUNT)?;
+            if decision_below == decision_at_or_above {
+                return None;
+            }
+
+            // Assume that the best decision flips only once, and binary search the flipping point.
+            let (mut low, mut high) = (MIN_TRUE_COUNT, MAX_TRUE_COUNT);
+            while high - low > 1 {
+                let mid = (low + high) / 2;
+                if decide(mid)? == decision_at_or_above {
+                    high = mid;
+                } else {
+                    low = mid;
+                }
+            }
+            Some(IndexPlay {
+                hand,
+                dealer_up_card,
+                decision_below,
+                decision_at_or_above,
+                index: high,
+            })
+        };
+        index_plays.extend(find_index_play());
+    }
+    index_plays
+}
+
+/// Returns None if the situation cannot be dealt from the shoe.
+fn get_best_decision(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+    hand: (u8, u8),
+    dealer_up_card: u8,
+    is_insurance: bool,
+) -> Option<Decision> {
+    let (solution, hand_card_count) =
+        solve_situation(number_of_threads, rule, shoe, hand, dealer_up_card)?;
+    if is_insurance {
+        return Some(if solution.ex_extra_insurance > 0.0 {
+            Decision::Insurance
+        } else {
+            Decision::PlaceHolder
+        });
+    }
+    Some(solution.decision_for(&hand_card_count, rule, true, false, true))
+}
+
+/// Returns how much better `decision_at_or_above` of the index play is than `decision_below` (based
+/// on the original bet) when the true count of the given counting system is `true_count`, where
+/// half of the cards of `full_shoe` are dealt. Buying insurance costs half of the original bet.
+pub(super) fn get_index_play_advantage(
+    number_of_threads: usize,
+    rule: &Rule,
+    full_shoe: &CardCount,
+    count_tags: &[i32; 10],
+    index_play: &IndexPlay,
+    true_count: i32,
+) -> f64 {
+    let shoe = get_shoe_with_true_count(full_shoe, count_tags, true_count);
+    let (solution, hand_card_count) = solve_situation(
+        number_of_threads,
+        rule,
+        &shoe,
+        index_play.hand,
+        index_play.dealer_up_card,
+    )
+    .expect("The situation of an index play can be dealt around its index");
+    let get_ex = |decision| match decision {
+        Decision::Insurance => solution.ex_extra_insurance / 2.0,
+        Decision::PlaceHolder => 0.0,
+        _ => solution.decision_expectation(&hand_card_count, rule, decision),
+    };
+    get_ex(index_play.decision_at_or_above) - get_ex(index_play.decision_below)
+}
+
+/// Solves the given initial situation, whose cards are still in the shoe. Returns the solution and
+/// the hand, or None if the situation cannot be dealt from the shoe.
+fn solve_situation(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+    hand: (u8, u8),
+    dealer_up_card: u8,
+) -> Option<(SolutionForInitialSituation, CardCount)> {
+    let shoe = remove_initial_cards(shoe, hand, dealer_up_card)?;
+    let initial_situation = InitialSituation::new(shoe, hand, dealer_up_card);
+    let solution =
+        calculate_solution_with_initial_situation(number_of_threads, rule, &initial_situation);
+
+    let mut hand_card_count = CardCount::with_number_of_decks(0);
+    hand_card_count.add_card(hand.0);
+    hand_card_count.add_card(hand.1);
+    Some((solution, hand_card_count))
+}
+
+/// Returns a shoe with the given true count, where half of the cards of `full_shoe` are dealt. The
+/// dealt cards start as an even half of each card value, and then a dealt card with a tag of the
+/// opposite sign is swapped with an undealt card with a tag of the same sign as the true count, one
+/// by one, until the true count is reached.
+pub(super) fn get_shoe_with_true_count(
+    full_shoe: &CardCount,
+    count_tags: &[i32; 10],
+    true_count: i32,
+) -> CardCount {
+    let mut shoe = *full_shoe;
+    for card in 1..=10 {
+        for _ in 0..shoe[card] / 2 {
+            shoe.remove_card(card);
+        }
+    }
+    let tag = |card: u8| count_tags[(card - 1) as usize];
+    let mut running_count: i32 = (1..=10)
+        .map(|card| tag(card) * (full_shoe[card] - shoe[card]) as i32)
+        .sum();
+    let target = true_count as f64 * shoe.get_total() as f64 / 52.0;
+    let sign = if target >= running_count as f64 {
+        1
+    } else {
+        -1
+    };
+
+    while ((target - running_count as f64) * sign as f64) > 0.0 {
+        let to_deal = (1..=10)
+            .filter(|&card| tag(card) * sign > 0 && shoe[card] > 0)
+            .max_by_key(|&card| shoe[card]);
+        let to_return = (1..=10)
+            .filter(|&card| tag(card) * sign < 0 && shoe[card] < full_shoe[card])
+            .max_by_key(|&card| full_shoe[card] - shoe[card]);
+        let (Some(to_deal), Some(to_return)) = (to_deal, to_return) else {
+            break;
+        };
+        shoe.remove_card(to_deal);
+        shoe.add_card(to_return);
+        running_count += tag(to_deal) - tag(to_return);
+    }
+    shoe
+}
+
+/// Returns the true count of `shoe`, where the cards of `full_shoe` that are not in `shoe` have
+/// been dealt.
+pub(super) fn get_true_count(
+    full_shoe: &CardCount,
+    shoe: &CardCount,
+    count_tags: &[i32; 10],
+) -> f64 {
+    let running_count: i32 = (1..=10)
+        .map(|card| count_tags[(card - 1) as usize] * (full_shoe[card] - shoe[card]) as i32)
+        .sum();
+    running_count as f64 / (shoe.get_total() as f64 / 52.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HI_LO_TAGS: [i32; 10] = [-1, 1, 1, 1, 1, 1, 0, 0, 0, -1];
+
+    #[test]
+    fn shoe_with_true_count() {
+        let get_hi_lo_true_count = |shoe: &CardCount| {
+            let running_count = (shoe.low_cards() as i32 - 80) - (shoe.high_cards() as i32 - 80);
+            -running_count as f64 / (shoe.get_total() as f64 / 52.0)
+        };
+        let full_shoe = CardCount::with_number_of_decks(8);
+        for true_count in [-4, 0, 3] {
+            let shoe = get_shoe_with_true_count(&full_shoe, &HI_LO_TAGS, true_count);
+            assert_eq!(shoe.get_total(), 208);
+            let actual_true_count = get_hi_lo_true_count(&shoe);
+            assert!((actual_true_count - true_count as f64).abs() < 0.5);
+            assert!(
+                (get_true_count(&full_shoe, &shoe, &HI_LO_TAGS) - actual_true_count).abs() < 1e-12
+            );
+        }
+    }
+
+    #[test]
+    fn well_known_indices() {
+        // A single deck, so that each of the half-dealt shoes has only 26 cards left.
+        let rule = Rule {
+            number_of_decks: 1,
+            ..Default::default()
+        };
+        let index_plays = index_deviations(1, &rule, &HI_LO_TAGS);
+        let find = |hand, dealer_up_card| {
+            index_plays
+                .iter()
+                .find(|play| play.hand == hand && play.dealer_up_card == dealer_up_card)
+                .unwrap()
+        };
+
+        // The single-deck insurance index of Hi-Lo is about +1.4.
+        let insurance = find((10, 6), 1);
+        assert_eq!(insurance.decision_below, Decision::PlaceHolder);
+        assert_eq!(insurance.decision_at_or_above, Decision::Insurance);
+        assert!((insurance.index - 1).abs() <= 1);
+
+        let stand_16_vs_10 = find((9, 7), 10);
+        assert_eq!(stand_16_vs_10.decision_below, Decision::Hit);
+        assert_eq!(stand_16_vs_10.decision_at_or_above, Decision::Stand);
+        assert!(stand_16_vs_10.index > MIN_TRUE_COUNT && stand_16_vs_10.index < MAX_TRUE_COUNT);
+    }
+}
@@ -0,0 +1,203 @@
+use super::{
+    calculate_stand_odds, get_card_probability, get_impossible_dealer_hole_card, get_stand_moments,
+    memoization_calculate_stand_hit_expectation, Expectation,
+};
+use crate::{CardCount, Rule, StateArray};
+
+/// Calculates the expectation of the initial hand when the next few cards of the shoe are known,
+/// e.g., by shuffle tracking. The hits (and the double) of the player draw the known cards in
+/// order, and draw from the shoe probabilistically once the known cards run out. The known cards
+/// which are not drawn by the player are not assumed to go to the dealer.
+///
+/// Here `shoe` is the shoe after the initial cards are dealt, and the expectation is given when the
+/// round doesn't end early (i.e., the dealer doesn't have a natural). Stand, Hit, Double and
+/// Surrender are considered, but Split is not. Returns an error if the shoe doesn't contain the
+/// known cards.
+pub fn ev_with_known_next_cards(
+    rule: &Rule,
+    shoe: &CardCount,
+    hand: (u8, u8),
+    dealer_up_card: u8,
+    known_next: &[u8],
+) -> Result<f64, String> {
+    let mut remaining_shoe = *shoe;
+    for &card in known_next {
+        if !(1..=10).contains(&card) {
+            return Err(format!("Invalid known card: {}", card));
+        }
+        if remaining_shoe[card] == 0 {
+            return Err(format!("Not enough cards of value {} in shoe", card));
+        }
+        remaining_shoe.remove_card(card);
+    }
+
+    let mut shoe = *shoe;
+    let mut initial_hand = CardCount::with_number_of_decks(0);
+    initial_hand.add_card(hand.0);
+    initial_hand.add_card(hand.1);
+    let impossible_dealer_hole_card = get_impossible_dealer_hole_card(rule, dealer_up_card);
+
+    let mut ex = get_known_cards_expectation(
+        rule,
+        dealer_up_card,
+        impossible_dealer_hole_card,
+        known_next,
+        &mut shoe,
+        &mut initial_hand,
+    );
+    if initial_hand.is_natural() {
+        return Ok(ex);
+    }
+
+    // Double draws exactly one card, either the first known card or a random one.
+    if rule.double_policy.allows(&initial_hand) {
+        let ex_double = match known_next.first() {
+            Some(&card) => {
+                shoe.remove_card(card);
+                initial_hand.add_card(card);
+                let ex_stand = get_stand_expectation(rule, dealer_up_card, &shoe, &initial_hand);
+                initial_hand.remove_card(card);
+                shoe.add_card(card);
+                ex_stand * 2.0
+            }
+            None => {
+                let mut ex_double = 0.0;
+                for card in 1..=10 {
+                    if shoe[card] == 0 {
+                        continue;
+                    }
+                    let p = get_card_probability(&shoe, impossible_dealer_hole_card, card);
+                    shoe.remove_card(card);
+                    initial_hand.add_card(card);
+                    ex_double +=
+                        p * get_stand_expectation(rule, dealer_up_card, &shoe, &initial_hand);
+                    initial_hand.remove_card(card);
+                    shoe.add_card(card);
+                }
+                ex_double * 2.0
+            }
+        };
+        ex = ex.max(ex_double);
+    }
+    if rule.allow_late_surrender {
+        ex = ex.max(-0.5);
+    }
+    Ok(ex)
+}
+
+/// Returns the expectation of the best of Stand and Hit, where the hits draw the known cards
+/// first.
+fn get_known_cards_expectation(
+    rule: &Rule,
+    dealer_up_card: u8,
+    impossible_dealer_hole_card: u8,
+    known_next: &[u8],
+    shoe: &mut CardCount,
+    hand: &mut CardCount,
+) -> f64 {
+    if hand.bust() {
+        return -1.0;
+    }
+    if rule.reached_charlie(hand) {
+        return 1.0;
+    }
+    let ex_stand = get_stand_expectation(rule, dealer_up_card, shoe, hand);
+    if hand.get_actual_sum() == 21 {
+        return ex_stand;
+    }
+
+    let ex_hit = match known_next.split_first() {
+        Some((&card, rest)) => {
+            shoe.remove_card(card);
+            hand.add_card(card);
+            let ex = get_known_cards_expectation(
+                rule,
+                dealer_up_card,
+                impossible_dealer_hole_card,
+                rest,
+                shoe,
+                hand,
+            );
+            hand.remove_card(card);
+            shoe.add_card(card);
+            ex
+        }
+        None => {
+            let mut ex_stand_hit: StateArray<Expectation> = StateArray::new();
+            memoization_calculate_stand_hit_expectation(
+                rule,
+                &dealer_up_card,
+                &impossible_dealer_hole_card,
+                shoe,
+                hand,
+                &mut ex_stand_hit,
+            );
+            ex_stand_hit[hand].hit
+        }
+    };
+    ex_stand.max(ex_hit)
+}
+
+fn get_stand_expectation(
+    rule: &Rule,
+    dealer_up_card: u8,
+    shoe: &CardCount,
+    hand: &CardCount,
+) -> f64 {
+    if hand.bust() {
+        return -1.0;
+    }
+    if rule.reached_charlie(hand) {
+        return 1.0;
+    }
+    let stand_odds = calculate_stand_odds(rule, hand, &dealer_up_card, shoe);
+    get_stand_moments(rule, hand, &stand_odds).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculation::calculate_solution_with_initial_situation;
+    use crate::{Decision, InitialSituation};
+
+    #[test]
+    fn knowing_a_five_helps_hard_16() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for card in [10, 6, 10] {
+            shoe.remove_card(card);
+        }
+        let initial_situation = InitialSituation::new(shoe, (10, 6), 10);
+        let solution = calculate_solution_with_initial_situation(1, &rule, &initial_situation);
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(10);
+        hand.add_card(6);
+        let ex_unknown = [Decision::Stand, Decision::Hit, Decision::Double]
+            .map(|decision| solution.decision_expectation(&hand, &rule, decision))
+            .into_iter()
+            .fold(-f64::INFINITY, f64::max);
+
+        let ex = ev_with_known_next_cards(&rule, &shoe, (10, 6), 10, &[]).unwrap();
+        assert!((ex - ex_unknown).abs() < 1e-9);
+        let ex_five = ev_with_known_next_cards(&rule, &shoe, (10, 6), 10, &[5]).unwrap();
+        assert!(ex_five > ex_unknown + 1.0);
+        let ex_ten = ev_with_known_next_cards(&rule, &shoe, (10, 6), 10, &[10]).unwrap();
+        assert_eq!(
+            ex_ten,
+            solution.decision_expectation(&hand, &rule, Decision::Stand)
+        );
+    }
+
+    #[test]
+    fn known_cards_must_be_in_shoe() {
+        let rule = Rule::default();
+        let mut shoe = CardCount::new(&[1, 1, 1, 1, 1, 1, 1, 1, 1, 4]);
+        for card in [10, 6, 10] {
+            shoe.remove_card(card);
+        }
+        assert!(ev_with_known_next_cards(&rule, &shoe, (10, 6), 10, &[5]).is_ok());
+        assert!(ev_with_known_next_cards(&rule, &shoe, (10, 6), 10, &[6]).is_err());
+        assert!(ev_with_known_next_cards(&rule, &shoe, (10, 6), 10, &[5, 5]).is_err());
+        assert!(ev_with_known_next_cards(&rule, &shoe, (10, 6), 10, &[11]).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use super::{
+    calculate_solution_without_initial_situation, get_card_probability,
+    get_impossible_dealer_hole_card, Expectation,
+};
+use crate::{CardCount, Rule, StateArray};
+
+/// Calculates the expectation of a fresh shoe when the player mimics the dealer, i.e., hits until
+/// 17 and never doubles, splits, surrenders or buys insurance. If `hit_soft17` is true, the player
+/// also hits soft 17. This is a common "no strategy" baseline.
+pub fn mimic_dealer_ev(number_of_threads: usize, rule: &Rule, hit_soft17: bool) -> f64 {
+    let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+    mimic_dealer_ev_of_shoe(number_of_threads, rule, &shoe, hit_soft17)
+}
+
+fn mimic_dealer_ev_of_shoe(
+    number_of_threads: usize,
+    rule: &Rule,
+    shoe: &CardCount,
+    hit_soft17: bool,
+) -> f64 {
+    let solution = calculate_solution_without_initial_situation(number_of_threads, rule, shoe);
+    let mut shoe = *shoe;
+
+    let mut ex = 0.0;
+    for dealer_up_card in 1..=10 {
+        let p_up_card = get_card_probability(&shoe, 0, dealer_up_card);
+        if p_up_card == 0.0 {
+            continue;
+        }
+        shoe.remove_card(dealer_up_card);
+        let impossible_dealer_hole_card = get_impossible_dealer_hole_card(rule, dealer_up_card);
+        let ex_stand_hit = &solution.exs_stand_hit[(dealer_up_card - 1) as usize];
+        let mut hand = CardCount::with_number_of_decks(0);
+        for first_hand_card in 1..=10 {
+            let p_first_card = get_card_probability(&shoe, 0, first_hand_card);
+            if p_first_card == 0.0 {
+                continue;
+            }
+            shoe.remove_card(first_hand_card);
+            hand.add_card(first_hand_card);
+            for second_hand_card in 1..=10 {
+                let p_second_card = get_card_probability(&shoe, 0, second_hand_card);
+                if p_second_card == 0.0 {
+                    continue;
+                }
+                shoe.remove_card(second_hand_card);
+                hand.add_card(second_hand_card);
+
+                let p_early_end = {
+                    if impossible_dealer_hole_card == 0 {
+                        0.0
+                    } else {
+                        get_card_probability(&shoe, 0, impossible_dealer_hole_card)
+                    }
+                };
+                let ex_early_end = if hand.is_natural() { 0.0 } else { -1.0 };
+                let ex_no_early_end = get_mimic_expectation(
+                    rule,
+                    ex_stand_hit,
+                    impossible_dealer_hole_card,
+                    hit_soft17,
+                    &mut shoe,
+                    &mut hand,
+                );
+                ex += p_up_card
+                    * p_first_card
+                    * p_second_card
+                    * (p_early_end * ex_early_end + (1.0 - p_early_end) * ex_no_early_end);
+
+                hand.remove_card(second_hand_card);
+                shoe.add_card(second_hand_card);
+            }
+            hand.remove_card(first_hand_card);
+            shoe.add_card(first_hand_card);
+        }
+        shoe.add_card(dealer_up_card);
+    }
+    ex
+}
+
+/// Returns the expectation of playing the given hand like the dealer, where the expectations of
+/// standing (including bust and Charlie) are looked up in `ex_stand_hit`.
+fn get_mimic_expectation(
+    rule: &Rule,
+    ex_stand_hit: &StateArray<Expectation>,
+    impossible_dealer_hole_card: u8,
+    hit_soft17: bool,
+    shoe: &mut CardCount,
+    hand: &mut CardCount,
+) -> f64 {
+    let actual_sum = hand.get_actual_sum();
+    let should_stand = hand.bust()
+        || rule.reached_charlie(hand)
+        || actual_sum > 17
+        || (actual_sum == 17 && !(hit_soft17 && hand.can_be_soft_total(17)));
+    if should_stand {
+        return ex_stand_hit[hand].stand;
+    }
+
+    let mut ex = 0.0;
+    for card in 1..=10 {
+        if shoe[card] == 0 {
+            continue;
+        }
+        let p = get_card_probability(shoe, impossible_dealer_hole_card, card);
+        shoe.remove_card(card);
+        hand.add_card(card);
+        ex += p * get_mimic_expectation(
+            rule,
+            ex_stand_hit,
+            impossible_dealer_hole_card,
+            hit_soft17,
+            shoe,
+            hand,
+        );
+        hand.remove_card(card);
+        shoe.add_card(card);
+    }
+    ex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mimic_dealer_is_worse_than_optimal_strategy() {
+        let rule = Rule::default();
+        let shoe = CardCount::new(&[2, 1, 1, 1, 1, 1, 1, 1, 1, 6]);
+        let ex_optimal =
+            calculate_solution_without_initial_situation(0, &rule, &shoe).get_total_expectation();
+        let ex_mimic = mimic_dealer_ev_of_shoe(0, &rule, &shoe, false);
+        let ex_mimic_hit_soft17 = mimic_dealer_ev_of_shoe(0, &rule, &shoe, true);
+        assert!(ex_mimic < ex_optimal - 0.03);
+        assert!(ex_mimic_hit_soft17 < ex_optimal);
+        assert!(ex_mimic > -0.1);
+    }
+}
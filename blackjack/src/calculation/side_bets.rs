@@ -0,0 +1,433 @@
+use crate::simulation::{shoe::Shoe, Card};
+use crate::{CardCount, Rule, StateArray};
+
+/// The side bets that can be placed together with the main bet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SideBet {
+    /// Pays when the first two cards of the player are a pair. A perfect pair (same suit) pays
+    /// 25:1, a colored pair (same color) pays 12:1 and a mixed pair pays 6:1.
+    PerfectPairs,
+
+    /// Pays when the first two cards of the player and the dealer up card make a poker hand.
+    /// Suited trips pays 100:1, straight flush pays 40:1, three of a kind pays 30:1, straight
+    /// pays 10:1 and flush pays 5:1.
+    TwentyOnePlusThree,
+}
+
+const NUMBER_OF_SUITS: usize = 4;
+const NUMBER_OF_FACE_VALUES: usize = 13;
+
+/// Calculates the expectation of the given side bet. Note that this expectation is based on its
+/// own bet, not the main bet.
+///
+/// Note that CardCount doesn't know the face values and suits of the cards, so the cards of each
+/// blackjack value are assumed to be evenly distributed among suits (and among 10, J, Q and K for
+/// value 10).
+pub fn side_bet_expectation(side_bet: SideBet, shoe: &CardCount) -> f64 {
+    let weights = get_card_weights(shoe);
+    let total = shoe.get_total() as f64;
+    match side_bet {
+        SideBet::PerfectPairs => perfect_pairs_expectation(&weights, total),
+        SideBet::TwentyOnePlusThree => twenty_one_plus_three_expectation(&weights, total),
+    }
+}
+
+/// The payouts of the side bet "Match the Dealer", where each of the first two cards of the player
+/// pays if it has the same rank as the dealer up card.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchTheDealerPayouts {
+    /// The payout of a card with the same rank but a different suit.
+    pub unsuited: f64,
+
+    /// The payout of a card with the same rank and suit.
+    pub suited: f64,
+}
+
+impl Default for MatchTheDealerPayouts {
+    /// The common payouts of an 8-deck shoe, i.e., 4:1 for unsuited and 11:1 for suited.
+    fn default() -> Self {
+        MatchTheDealerPayouts {
+            unsuited: 4.0,
+            suited: 11.0,
+        }
+    }
+}
+
+/// Calculates the expectation of the side bet "Match the Dealer" given the dealer up card, where
+/// the first two cards of the player are drawn from the remaining cards of the shoe (i.e., the up
+/// card must have been dealt). If both cards match, both are paid. Note that this expectation is
+/// based on its own bet, not the main bet.
+pub fn match_the_dealer_expectation(
+    shoe: &Shoe,
+    dealer_up_card: Card,
+    payouts: MatchTheDealerPayouts,
+) -> f64 {
+    let remaining_cards = shoe.remaining_cards();
+    let total = remaining_cards.len() as f64;
+    let same_rank = |card: &&Card| card.face_value == dealer_up_card.face_value;
+    let suited = remaining_cards
+        .iter()
+        .filter(same_rank)
+        .filter(|card| card.suit == dealer_up_card.suit)
+        .count() as f64;
+    let unsuited = remaining_cards.iter().filter(same_rank).count() as f64 - suited;
+
+    // Each of the two cards is equally likely to be any remaining card.
+    let ex_payout_per_card = (suited * payouts.suited + unsuited * payouts.unsuited) / total;
+    let matches = suited + unsuited;
+    let p_no_match = (total - matches) / total * (total - 1.0 - matches) / (total - 1.0);
+    2.0 * ex_payout_per_card - p_no_match
+}
+
+/// The payouts of the side bet "Lucky Ladies", which pays when the first two cards of the player
+/// total 20. Only the best matching payout is paid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LuckyLadiesPayouts {
+    /// The payout of two cards with the same rank and suit.
+    pub matched: f64,
+
+    /// The payout of two cards with the same suit.
+    pub suited: f64,
+
+    /// The payout of any other two cards totaling 20.
+    pub any: f64,
+}
+
+impl Default for LuckyLadiesPayouts {
+    /// The common payouts, i.e., 25:1 for matched, 10:1 for suited and 4:1 for any 20.
+    fn default() -> Self {
+        LuckyLadiesPayouts {
+            matched: 25.0,
+            suited: 10.0,
+            any: 4.0,
+        }
+    }
+}
+
+/// Calculates the expectation of the side bet "Lucky Ladies", where the first two cards of the
+/// player are drawn from the remaining cards of the shoe. The bonus payouts which depend on the
+/// dealer (e.g. a pair of Queens of Hearts against a dealer natural) are not supported. Note that
+/// this expectation is based on its own bet, not the main bet.
+pub fn lucky_ladies_expectation(shoe: &Shoe, payouts: LuckyLadiesPayouts) -> f64 {
+    let remaining_cards = shoe.remaining_cards();
+    let mut counts = [[0.0; NUMBER_OF_SUITS]; NUMBER_OF_FACE_VALUES];
+    for card in remaining_cards {
+        counts[(card.face_value - 1) as usize][card.suit as usize] += 1.0;
+    }
+    let total = remaining_cards.len() as f64;
+
+    let mut ex = 0.0;
+    let mut p_win = 0.0;
+    for f1 in 0..NUMBER_OF_FACE_VALUES {
+        for s1 in 0..NUMBER_OF_SUITS {
+            for f2 in 0..NUMBER_OF_FACE_VALUES {
+                let value = |f: usize| std::cmp::min(f + 1, 10);
+                let is_twenty = match (f1, f2) {
+                    (0, 0) => false,
+                    (0, f) | (f, 0) => value(f) == 9,
+                    _ => value(f1) + value(f2) == 20,
+                };
+                if !is_twenty {
+                    continue;
+                }
+                for s2 in 0..NUMBER_OF_SUITS {
+                    let is_matched = f1 == f2 && s1 == s2;
+                    let w2 = counts[f2][s2] - is_matched as u8 as f64;
+                    let p = counts[f1][s1] / total * w2.max(0.0) / (total - 1.0);
+                    let payout = if is_matched {
+                        payouts.matched
+                    } else if s1 == s2 {
+                        payouts.suited
+                    } else {
+                        payouts.any
+                    };
+                    ex += p * payout;
+                    p_win += p;
+                }
+            }
+        }
+    }
+    ex - (1.0 - p_win)
+}
+
+/// The common payouts of the side bet "Bust It", from busting with 3 cards to busting with 8 or
+/// more cards.
+pub const BUST_IT_PAYOUTS: [f64; 6] = [1.0, 2.0, 9.0, 50.0, 100.0, 250.0];
+
+/// Calculates the expectation of the side bet "Bust It", which pays when the dealer busts, where
+/// `payouts_by_bust_cards[i]` is the payout of busting with `i + 3` cards, and the last payout
+/// is also used for busting with more cards (e.g. `BUST_IT_PAYOUTS`). The shoe should contain the
+/// cards remaining, i.e., the dealer up card must have been removed. A dealer natural loses the
+/// side bet, so the hole card is not conditioned on the dealer peek. Note that this expectation
+/// is based on its own bet, not the main bet.
+pub fn bust_it_expectation(
+    rule: &Rule,
+    shoe: &CardCount,
+    dealer_up_card: u8,
+    payouts_by_bust_cards: &[f64],
+) -> f64 {
+    if payouts_by_bust_cards.is_empty() {
+        return -1.0;
+    }
+    let mut shoe = *shoe;
+    let mut dealer_hand = CardCount::with_number_of_decks(0);
+    dealer_hand.add_card(dealer_up_card);
+    let mut memo = StateArray::new();
+    memoization_bust_it_expectation(
+        rule,
+        &mut shoe,
+        &mut dealer_hand,
+        payouts_by_bust_cards,
+        &mut memo,
+    )
+}
+
+/// Returns the expectation of "Bust It" (see `bust_it_expectation`) starting from the given
+/// dealer hand.
+fn memoization_bust_it_expectation(
+    rule: &Rule,
+    current_shoe: &mut CardCount,
+    dealer_hand: &mut CardCount,
+    payouts_by_bust_cards: &[f64],
+    memo: &mut StateArray<f64>,
+) -> f64 {
+    if memo.contains_state(dealer_hand) {
+        return memo[dealer_hand];
+    }
+    let ex = if dealer_hand.bust() {
+        let index = (dealer_hand.get_total() as usize - 3).min(payouts_by_bust_cards.len() - 1);
+        payouts_by_bust_cards[index]
+    } else if dealer_hand.get_total() >= 2 && rule.dealer_must_stand(dealer_hand) {
+        -1.0
+    } else {
+        let shoe_total = current_shoe.get_total() as f64;
+        let mut ex = 0.0;
+        for card in 1..=10 {
+            if current_shoe[card] == 0 {
+                continue;
+            }
+            let p = current_shoe[card] as f64 / shoe_total;
+            current_shoe.remove_card(card);
+            dealer_hand.add_card(card);
+            ex += p * memoization_bust_it_expectation(
+                rule,
+                current_shoe,
+                dealer_hand,
+                payouts_by_bust_cards,
+                memo,
+            );
+            dealer_hand.remove_card(card);
+            current_shoe.add_card(card);
+        }
+        ex
+    };
+    memo[dealer_hand] = ex;
+    ex
+}
+
+/// Returns the (possibly fractional) number of cards of each face value (from 1 to 13) and suit.
+fn get_card_weights(shoe: &CardCount) -> [[f64; NUMBER_OF_SUITS]; NUMBER_OF_FACE_VALUES] {
+    let mut weights = [[0.0; NUMBER_OF_SUITS]; NUMBER_OF_FACE_VALUES];
+    for (i, weights_of_face_value) in weights.iter_mut().enumerate() {
+        let blackjack_value = std::cmp::min(i + 1, 10) as u8;
+        let number_of_face_values = if blackjack_value == 10 { 4 } else { 1 };
+        let weight =
+            shoe[blackjack_value] as f64 / (NUMBER_OF_SUITS * number_of_face_values) as f64;
+        weights_of_face_value.fill(weight);
+    }
+    weights
+}
+
+fn is_red(suit: usize) -> bool {
+    // Same order as `Suit`: Diamond, Club, Heart, Spade.
+    suit == 0 || suit == 2
+}
+
+fn perfect_pairs_expectation(
+    weights: &[[f64; NUMBER_OF_SUITS]; NUMBER_OF_FACE_VALUES],
+    total: f64,
+) -> f64 {
+    let mut ex = 0.0;
+    let mut p_win = 0.0;
+    for weights_of_face_value in weights {
+        for (s1, &w1) in weights_of_face_value.iter().enumerate() {
+            for (s2, &w2) in weights_of_face_value.iter().enumerate() {
+                let w2 = if s1 == s2 { (w2 - 1.0).max(0.0) } else { w2 };
+                let p = w1 / total * w2 / (total - 1.0);
+                let payout = if s1 == s2 {
+                    25.0
+                } else if is_red(s1) == is_red(s2) {
+                    12.0
+                } else {
+                    6.0
+                };
+                ex += p * payout;
+                p_win += p;
+            }
+        }
+    }
+    ex - (1.0 - p_win)
+}
+
+fn twenty_one_plus_three_expectation(
+    weights: &[[f64; NUMBER_OF_SUITS]; NUMBER_OF_FACE_VALUES],
+    total: f64,
+) -> f64 {
+    let cards: Vec<(usize, usize, f64)> = (0..NUMBER_OF_FACE_VALUES)
+        .flat_map(|f| (0..NUMBER_OF_SUITS).map(move |s| (f, s, weights[f][s])))
+        .filter(|&(_, _, w)| w > 0.0)
+        .collect();
+
+    let mut ex = 0.0;
+    let mut p_win = 0.0;
+    for (i1, &(f1, s1, w1)) in cards.iter().enumerate() {
+        let p1 = w1 / total;
+        for (i2, &(f2, s2, w2)) in cards.iter().enumerate() {
+            let w2 = if i2 == i1 { (w2 - 1.0).max(0.0) } else { w2 };
+            let p2 = p1 * w2 / (total - 1.0);
+            if p2 == 0.0 {
+                continue;
+            }
+            for (i3, &(f3, s3, w3)) in cards.iter().enumerate() {
+                let already_taken = (i3 == i1) as u8 + (i3 == i2) as u8;
+                let w3 = (w3 - already_taken as f64).max(0.0);
+                let payout = get_poker_hand_payout([f1, f2, f3], [s1, s2, s3]);
+                if payout == 0.0 {
+                    continue;
+                }
+                let p = p2 * w3 / (total - 2.0);
+                ex += p * payout;
+                p_win += p;
+            }
+        }
+    }
+    ex - (1.0 - p_win)
+}
+
+/// Returns the 21+3 payout of a three-card poker hand, or 0.0 if it doesn't win. Face values are
+/// given from 0 (Ace) to 12 (King).
+fn get_poker_hand_payout(mut face_values: [usize; 3], suits: [usize; 3]) -> f64 {
+    face_values.sort_unstable();
+    let is_flush = suits[0] == suits[1] && suits[1] == suits[2];
+    let is_trips = face_values[0] == face_values[2];
+    let is_straight = (face_values[0] + 1 == face_values[1]
+        && face_values[1] + 1 == face_values[2])
+        || face_values == [0, 11, 12];
+
+    if is_trips && is_flush {
+        100.0
+    } else if is_straight && is_flush {
+        40.0
+    } else if is_trips {
+        30.0
+    } else if is_straight {
+        10.0
+    } else if is_flush {
+        5.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perfect_pairs_with_fresh_shoes() {
+        // With a single deck, there is no perfect pair. Whatever the first card is, 2 of the 51
+        // second cards make a mixed pair and 1 makes a colored pair.
+        let shoe = CardCount::with_number_of_decks(1);
+        let ex = side_bet_expectation(SideBet::PerfectPairs, &shoe);
+        let p_mixed = 2.0 / 51.0;
+        let p_colored = 1.0 / 51.0;
+        let gt = p_mixed * 6.0 + p_colored * 12.0 - (1.0 - p_mixed - p_colored);
+        assert!((ex - gt).abs() < 1e-12);
+
+        // With 8 decks, 7 perfect pairs, 8 colored pairs and 16 mixed pairs out of 415 second
+        // cards, i.e., the well-known house edge of 4.10%.
+        let shoe = CardCount::with_number_of_decks(8);
+        let ex = side_bet_expectation(SideBet::PerfectPairs, &shoe);
+        assert!((ex + 17.0 / 415.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn twenty_one_plus_three_with_fresh_shoe() {
+        // Counted by combinations of 3 out of 312 cards: 1040 suited trips, 10368 straight
+        // flushes, 25272 three of a kinds, 155520 straights and 292896 flushes.
+        let shoe = CardCount::with_number_of_decks(6);
+        let ex = side_bet_expectation(SideBet::TwentyOnePlusThree, &shoe);
+        let total = (312 * 311 * 310 / 6) as f64;
+        let win =
+            1040.0 * 100.0 + 10368.0 * 40.0 + 25272.0 * 30.0 + 155520.0 * 10.0 + 292896.0 * 5.0;
+        let p_win = (1040 + 10368 + 25272 + 155520 + 292896) as f64 / total;
+        assert!((ex - (win / total - (1.0 - p_win))).abs() < 1e-12);
+    }
+
+    #[test]
+    fn match_the_dealer_with_fresh_shoe() {
+        // The up card is the first card of an unshuffled shoe, i.e., Ace of Diamonds. 7 Aces of
+        // Diamonds and 24 other Aces are left out of 415 cards.
+        let mut shoe = Shoe::new(8, 0.5);
+        let dealer_up_card = shoe.deal_card().unwrap();
+        let ex =
+            match_the_dealer_expectation(&shoe, dealer_up_card, MatchTheDealerPayouts::default());
+        let gt = 2.0 * (7.0 * 11.0 + 24.0 * 4.0) / 415.0 - (384.0 * 383.0) / (415.0 * 414.0);
+        assert!((ex - gt).abs() < 1e-12);
+        assert!(ex < 0.0 && ex > -0.05);
+    }
+
+    #[test]
+    fn lucky_ladies_with_fresh_shoe() {
+        // Out of 416 * 415 ordered pairs: 128 * 7 matched 20s, 128 * 24 + 2 * 32 * 8 other suited
+        // 20s, and 128 * 96 + 2 * 32 * 24 other 20s.
+        let shoe = Shoe::new(8, 0.5);
+        let ex = lucky_ladies_expectation(&shoe, LuckyLadiesPayouts::default());
+        let total = 416.0 * 415.0;
+        let matched = 128.0 * 7.0;
+        let suited = 128.0 * 24.0 + 2.0 * 32.0 * 8.0;
+        let any = 128.0 * 96.0 + 2.0 * 32.0 * 24.0;
+        let gt =
+            (matched * 25.0 + suited * 10.0 + any * 4.0 - (total - matched - suited - any)) / total;
+        assert!((ex - gt).abs() < 1e-12);
+        assert!(ex < 0.0);
+    }
+
+    #[test]
+    fn bust_it_with_dealer_six() {
+        let rule = Rule::default();
+        let full_shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        let mut shoe = full_shoe;
+        shoe.remove_card(6);
+
+        // Paying even money for any bust gives the probability of the dealer busting.
+        let p_bust = crate::calculation::dealer_total_given_standing(&rule, &shoe, 6)[0];
+        let ex = bust_it_expectation(&rule, &shoe, 6, &[1.0]);
+        assert!((ex - (2.0 * p_bust - 1.0)).abs() < 1e-12);
+        assert!(ex < 0.0);
+
+        // With the common payouts, a known 6 is worth betting on, but the side bet is placed
+        // before the up card is dealt, which gives the house an edge.
+        let ex_six = bust_it_expectation(&rule, &shoe, 6, &BUST_IT_PAYOUTS);
+        assert!(ex_six > 0.0);
+        let ex: f64 = (1..=10)
+            .map(|up_card| {
+                let mut shoe = full_shoe;
+                shoe.remove_card(up_card);
+                let p = full_shoe[up_card] as f64 / full_shoe.get_total() as f64;
+                p * bust_it_expectation(&rule, &shoe, up_card, &BUST_IT_PAYOUTS)
+            })
+            .sum();
+        assert!(ex < 0.0 && ex > -0.1);
+    }
+
+    #[test]
+    fn poker_hand_payouts() {
+        assert_eq!(get_poker_hand_payout([3, 3, 3], [1, 1, 1]), 100.0);
+        assert_eq!(get_poker_hand_payout([12, 0, 11], [2, 2, 2]), 40.0);
+        assert_eq!(get_poker_hand_payout([3, 3, 3], [1, 2, 1]), 30.0);
+        assert_eq!(get_poker_hand_payout([0, 1, 2], [0, 1, 2]), 10.0);
+        assert_eq!(get_poker_hand_payout([0, 5, 9], [3, 3, 3]), 5.0);
+        assert_eq!(get_poker_hand_payout([11, 12, 1], [0, 0, 1]), 0.0);
+    }
+}
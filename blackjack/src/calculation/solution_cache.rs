@@ -0,0 +1,153 @@
+use super::{calculate_solution_without_initial_situation, SolutionForBettingPhase};
+use crate::{CardCount, DoublePolicy, PeekPolicy, PushOrLose, Rule};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Memoizes the solutions for a fresh shoe (i.e., before any card is dealt), which only depend on
+/// the rule. This avoids solving again when repeatedly creating strategies with the same rule.
+#[derive(Debug, Default)]
+pub struct SolutionCache {
+    solutions: HashMap<RuleKey, Arc<SolutionForBettingPhase>>,
+}
+
+impl SolutionCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the solution for a fresh shoe under the given rule. The solution is only calculated
+    /// the first time the rule is seen.
+    pub fn get_or_calculate(
+        &mut self,
+        number_of_threads: usize,
+        rule: &Rule,
+    ) -> Arc<SolutionForBettingPhase> {
+        self.get_or_insert_with(rule, || {
+            let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+            calculate_solution_without_initial_situation(number_of_threads, rule, &shoe)
+        })
+    }
+
+    /// Returns the cached solution for the given rule, calling `calculate` only if there is none.
+    fn get_or_insert_with(
+        &mut self,
+        rule: &Rule,
+        calculate: impl FnOnce() -> SolutionForBettingPhase,
+    ) -> Arc<SolutionForBettingPhase> {
+        self.solutions
+            .entry(RuleKey::new(rule))
+            .or_insert_with(|| Arc::new(calculate()))
+            .clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.solutions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.solutions.is_empty()
+    }
+}
+
+/// The fields of a rule that the solver reads. The simulator-only fields (e.g. `max_bet` and
+/// `suited_blackjack_payout`) are left out, so rules differing only in them share a solution.
+/// Floating point numbers are compared by their bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct RuleKey {
+    number_of_decks: u8,
+    split_all_limits: u8,
+    split_ace_limits: u8,
+    double_policy: DoublePolicy,
+    dealer_stand_value: u16,
+    dealer_hit_on_soft: bool,
+    dealer_soft_hit_up_to: Option<u16>,
+    allow_das: bool,
+    allow_late_surrender: bool,
+    allow_surrender_after_split: bool,
+    peek_policy: PeekPolicy,
+    offer_insurance_on_ace: bool,
+    charlie_number: u8,
+    payout_blackjack: u64,
+    payout_insurance: u64,
+    player_natural_vs_dealer_natural: PushOrLose,
+}
+
+impl RuleKey {
+    fn new(rule: &Rule) -> Self {
+        Self {
+            number_of_decks: rule.number_of_decks,
+            split_all_limits: rule.split_all_limits,
+            split_ace_limits: rule.split_ace_limits,
+            double_policy: rule.double_policy,
+            dealer_stand_value: rule.dealer_stand_value,
+            dealer_hit_on_soft: rule.dealer_hit_on_soft,
+            dealer_soft_hit_up_to: rule.dealer_soft_hit_up_to,
+            allow_das: rule.allow_das,
+            allow_late_surrender: rule.allow_late_surrender,
+            allow_surrender_after_split: rule.allow_surrender_after_split,
+            peek_policy: rule.peek_policy,
+            offer_insurance_on_ace: rule.offer_insurance_on_ace,
+            charlie_number: rule.charlie_number,
+            payout_blackjack: rule.payout_blackjack.to_bits(),
+            payout_insurance: rule.payout_insurance.to_bits(),
+            player_natural_vs_dealer_natural: rule.player_natural_vs_dealer_natural,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule_keys() {
+        let rule = Rule::default();
+        assert_eq!(RuleKey::new(&rule), RuleKey::new(&Rule::default()));
+        let other_rule = Rule {
+            payout_blackjack: 1.2,
+            ..Default::default()
+        };
+        assert_ne!(RuleKey::new(&rule), RuleKey::new(&other_rule));
+        let simulator_only_rule = Rule {
+            max_bet: 500,
+            suited_blackjack_payout: Some(2.0),
+            ..Default::default()
+        };
+        assert_eq!(RuleKey::new(&rule), RuleKey::new(&simulator_only_rule));
+    }
+
+    #[test]
+    fn equal_rules_share_solution() {
+        // Solving a full shoe is too slow for a test, so a small shoe is solved in its place.
+        let shoe = CardCount::new(&[1, 1, 1, 1, 1, 1, 1, 1, 1, 4]);
+        let mut number_of_calculations = 0;
+        let mut cache = SolutionCache::new();
+        let mut get = |cache: &mut SolutionCache, rule: &Rule| {
+            cache.get_or_insert_with(rule, || {
+                number_of_calculations += 1;
+                calculate_solution_without_initial_situation(0, rule, &shoe)
+            })
+        };
+
+        let rule = Rule::default();
+        let solution1 = get(&mut cache, &rule);
+        let solution2 = get(&mut cache, &rule);
+        assert!(Arc::ptr_eq(&solution1, &solution2));
+        let simulator_only_rule = Rule {
+            max_bet: 500,
+            ..rule
+        };
+        let solution3 = get(&mut cache, &simulator_only_rule);
+        assert!(Arc::ptr_eq(&solution1, &solution3));
+        assert_eq!(cache.len(), 1);
+
+        let other_rule = Rule {
+            payout_blackjack: 1.2,
+            ..rule
+        };
+        let solution4 = get(&mut cache, &other_rule);
+        assert!(!Arc::ptr_eq(&solution1, &solution4));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(number_of_calculations, 2);
+    }
+}
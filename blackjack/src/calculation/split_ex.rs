@@ -0,0 +1,315 @@
+use super::{
+    get_card_probability, get_impossible_dealer_hole_card, get_max_expectation,
+    memoization_calculate_stand_hit_expectation, memoization_find_win_lose_odds, Expectation,
+    WinLoseCasesOdds,
+};
+use crate::{CardCount, InitialSituation, Rule, StateArray};
+use std::collections::HashMap;
+
+/// Calculates the expectation of splitting the initial pair of the given initial situation, where
+/// at most `max_splits` splits (including the first one) are made, i.e., there are at most
+/// `max_splits + 1` groups. Returns -inf if the initial hand is not a pair or `max_splits` is 0.
+/// Since every split needs one more pair card, `max_splits` is capped by the number of pair cards
+/// left in the shoe, so a large limit like 255 means "split as long as cards allow". Aces are also
+/// capped by `split_ace_limits`.
+///
+/// The expectation is based on the original bet, so it takes all groups into account. Note that
+/// this is an approximation: each group draws from the shoe right after the initial cards are
+/// dealt, i.e., the cards drawn by the other groups are not removed. After splitting, a natural
+/// counts as a normal 21, Surrender is only allowed if `allow_surrender_after_split` is true,
+/// Double is only allowed if `allow_das` is true, and split Aces receive exactly one card each.
+pub fn calculate_split_expectation_with_limit(
+    rule: &Rule,
+    initial_situation: &InitialSituation,
+    max_splits: u8,
+) -> f64 {
+    let (pair_card, another_card) = initial_situation.hand_cards;
+    let max_splits = {
+        if pair_card == 1 {
+            max_splits.min(rule.split_ace_limits)
+        } else {
+            max_splits
+        }
+    };
+    if pair_card != another_card || max_splits == 0 {
+        return -f64::INFINITY;
+    }
+
+    let mut split_rule = *rule;
+    split_rule.allow_late_surrender = rule.allow_late_surrender && rule.allow_surrender_after_split;
+    let dealer_up_card = initial_situation.dealer_up_card;
+    let impossible_dealer_hole_card = get_impossible_dealer_hole_card(rule, dealer_up_card);
+    let shoe = initial_situation.shoe;
+
+    // The expectation of playing a single group which starts with the pair card and then gets
+    // the given second card. Each group has a bet of 1.
+    let mut ex_stand_hit = StateArray::new();
+    let mut ex_group = [-f64::INFINITY; 10];
+    for second_card in 1..=10 {
+        if shoe[second_card] == 0 {
+            continue;
+        }
+        let mut hand = CardCount::with_number_of_decks(0);
+        hand.add_card(pair_card);
+        hand.add_card(second_card);
+        let mut current_shoe = shoe;
+        current_shoe.remove_card(second_card);
+
+        memoization_calculate_stand_hit_expectation(
+            &split_rule,
+            &dealer_up_card,
+            &impossible_dealer_hole_card,
+            &mut current_shoe,
+            &mut hand,
+            &mut ex_stand_hit,
+        );
+        if hand.is_natural() {
+            // A natural after split is a normal 21.
+            let mut odds: StateArray<WinLoseCasesOdds> = StateArray::new();
+            let mut dealer_extra_hand = CardCount::with_number_of_decks(0);
+            memoization_find_win_lose_odds(
+                &split_rule,
+                &21,
+                &dealer_up_card,
+                &current_shoe,
+                &mut dealer_extra_hand,
+                &mut odds,
+            );
+            let odds = odds[&dealer_extra_hand];
+            ex_stand_hit[&hand].stand = odds.win - odds.lose;
+            ex_stand_hit[&hand].stand_second_moment = odds.win + odds.lose;
+        }
+
+        ex_group[(second_card - 1) as usize] = {
+            if pair_card == 1 {
+                // Split Aces receive only one card.
+                ex_stand_hit[&hand].stand
+            } else {
+                let (ex, _) = get_max_expectation(&ex_stand_hit, &hand, &split_rule);
+                // Doubling down on 21 is never better than standing, and the hands after it
+                // are not solved.
+                if rule.allow_das && rule.double_policy.allows(&hand) && hand.get_actual_sum() != 21
+                {
+                    let mut ex_double = 0.0;
+                    for third_card in 1..=10 {
+                        if current_shoe[third_card] == 0 {
+                            continue;
+                        }
+                        hand.add_card(third_card);
+                        let p = get_card_probability(
+                            &current_shoe,
+                            impossible_dealer_hole_card,
+                            third_card,
+                        );
+                        ex_double += p * get_stand_expectation(&ex_stand_hit, &hand, &split_rule);
+                        hand.remove_card(third_card);
+                    }
+                    ex.max(2.0 * ex_double)
+                } else {
+                    ex
+                }
+            }
+        };
+    }
+
+    let p_second_card: Vec<f64> = (1..=10)
+        .map(|card| get_card_probability(&shoe, impossible_dealer_hole_card, card))
+        .collect();
+    let max_splits = (max_splits as u16).min(shoe[pair_card] + 1);
+    let mut memo = HashMap::new();
+    get_expectation_of_groups(
+        pair_card,
+        &ex_group,
+        &p_second_card,
+        2,
+        max_splits - 1,
+        &mut memo,
+    )
+}
+
+/// Returns the expectation of standing on the given hand, taking bust and Charlie into account.
+fn get_stand_expectation(
+    ex_stand_hit: &StateArray<Expectation>,
+    hand: &CardCount,
+    rule: &Rule,
+) -> f64 {
+    if hand.bust() {
+        -1.0
+    } else if rule.reached_charlie(hand) {
+        1.0
+    } else {
+        ex_stand_hit[hand].stand
+    }
+}
+
+/// Returns the total expectation of `number_of_groups` unplayed groups (each of them only has the
+/// pair card), where `splits_left` more splits can be made.
+fn get_expectation_of_groups(
+    pair_card: u8,
+    ex_group: &[f64; 10],
+    p_second_card: &[f64],
+    number_of_groups: u16,
+    splits_left: u16,
+    memo: &mut HashMap<(u16, u16), f64>,
+) -> f64 {
+    if number_of_groups == 0 {
+        return 0.0;
+    }
+    if let Some(ex) = memo.get(&(number_of_groups, splits_left)) {
+        return *ex;
+    }
+
+    let mut ex = 0.0;
+    for second_card in 1..=10 {
+        let p = p_second_card[(second_card - 1) as usize];
+        if p == 0.0 {
+            continue;
+        }
+        let ex_play = ex_group[(second_card - 1) as usize]
+            + get_expectation_of_groups(
+                pair_card,
+                ex_group,
+                p_second_card,
+                number_of_groups - 1,
+                splits_left,
+                memo,
+            );
+        let ex_this_card = {
+            if second_card == pair_card && splits_left > 0 {
+                // Split again. Current group becomes two unplayed groups.
+                let ex_resplit = get_expectation_of_groups(
+                    pair_card,
+                    ex_group,
+                    p_second_card,
+                    number_of_groups + 1,
+                    splits_left - 1,
+                    memo,
+                );
+                ex_play.max(ex_resplit)
+            } else {
+                ex_play
+            }
+        };
+        ex += p * ex_this_card;
+    }
+
+    memo.insert((number_of_groups, splits_left), ex);
+    ex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_initial_situation(
+        rule: &Rule,
+        hand_cards: (u8, u8),
+        dealer_up_card: u8,
+    ) -> InitialSituation {
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        shoe.remove_card(hand_cards.0);
+        shoe.remove_card(hand_cards.1);
+        shoe.remove_card(dealer_up_card);
+        InitialSituation::new(shoe, hand_cards, dealer_up_card)
+    }
+
+    #[test]
+    fn more_splits_raise_expectation_of_eights_vs_six() {
+        let rule = Rule {
+            allow_das: true,
+            ..Default::default()
+        };
+        let initial_situation = get_initial_situation(&rule, (8, 8), 6);
+        let ex1 = calculate_split_expectation_with_limit(&rule, &initial_situation, 1);
+        let ex3 = calculate_split_expectation_with_limit(&rule, &initial_situation, 3);
+        assert!(ex1 > 0.0);
+        assert!(ex3 > ex1);
+    }
+
+    #[test]
+    fn unlimited_splits_are_capped_by_pair_cards_left() {
+        let mut rule = Rule {
+            allow_das: true,
+            ..Default::default()
+        };
+        rule.number_of_decks = 1;
+        rule.split_all_limits = 255;
+        // Only two more 8s are left in a single deck, so at most 3 splits can be made.
+        let initial_situation = get_initial_situation(&rule, (8, 8), 6);
+        let ex3 = calculate_split_expectation_with_limit(&rule, &initial_situation, 3);
+        let ex_unlimited = calculate_split_expectation_with_limit(
+            &rule,
+            &initial_situation,
+            rule.split_all_limits,
+        );
+        assert!(ex_unlimited.is_finite());
+        assert_eq!(ex_unlimited, ex3);
+
+        // Plenty of 10s are left in the shoe, but the number of groups must not overflow.
+        let mut shoe = CardCount::custom(8, &[(10, 200)]).unwrap();
+        for card in [10, 10, 6] {
+            shoe.remove_card(card);
+        }
+        let initial_situation = InitialSituation::new(shoe, (10, 10), 6);
+        let ex = calculate_split_expectation_with_limit(&rule, &initial_situation, 255);
+        assert!(ex.is_finite());
+    }
+
+    #[test]
+    fn aces_are_resplit_up_to_split_ace_limits() {
+        let mut rule = Rule {
+            allow_das: true,
+            ..Default::default()
+        };
+        let initial_situation = get_initial_situation(&rule, (1, 1), 6);
+        let ex1 = calculate_split_expectation_with_limit(&rule, &initial_situation, 1);
+        let ex3 = calculate_split_expectation_with_limit(&rule, &initial_situation, 3);
+        assert_eq!(ex3, ex1);
+
+        rule.split_ace_limits = 3;
+        let ex3 = calculate_split_expectation_with_limit(&rule, &initial_situation, 3);
+        assert!(ex3 > ex1);
+
+        rule.split_ace_limits = 0;
+        let ex = calculate_split_expectation_with_limit(&rule, &initial_situation, 3);
+        assert_eq!(ex, -f64::INFINITY);
+    }
+
+    #[test]
+    fn no_split_for_non_pair_or_zero_limit() {
+        let rule = Rule {
+            allow_das: true,
+            ..Default::default()
+        };
+        let initial_situation = get_initial_situation(&rule, (8, 7), 6);
+        let ex = calculate_split_expectation_with_limit(&rule, &initial_situation, 1);
+        assert_eq!(ex, -f64::INFINITY);
+        let initial_situation = get_initial_situation(&rule, (8, 8), 6);
+        let ex = calculate_split_expectation_with_limit(&rule, &initial_situation, 0);
+        assert_eq!(ex, -f64::INFINITY);
+    }
+
+    #[test]
+    fn surrender_after_split_raises_expectation_of_eights_vs_ten() {
+        let mut rule = Rule {
+            allow_das: true,
+            ..Default::default()
+        };
+        rule.allow_late_surrender = true;
+        let initial_situation = get_initial_situation(&rule, (8, 8), 10);
+        let ex = calculate_split_expectation_with_limit(&rule, &initial_situation, 1);
+        rule.allow_surrender_after_split = true;
+        let ex_surrender = calculate_split_expectation_with_limit(&rule, &initial_situation, 1);
+        assert!(ex_surrender > ex);
+    }
+
+    #[test]
+    fn split_limit_stops_spawning_groups() {
+        // With a single split, the expectation is exactly two groups each played once.
+        let ex_group = [0.5, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let p_second_card = [0.1; 10];
+        let mut memo = HashMap::new();
+        let ex = get_expectation_of_groups(1, &ex_group, &p_second_card, 2, 0, &mut memo);
+        assert!((ex - 2.0 * (0.05 - 0.1)).abs() < 1e-12);
+    }
+}
@@ -1,3 +1,4 @@
+pub mod analysis;
 pub mod calculation;
 pub mod simulation;
 mod statearray;
@@ -7,37 +8,157 @@ use serde_enum_str::{Deserialize_enum_str, Serialize_enum_str};
 pub use statearray::CardCount;
 pub use statearray::StateArray;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct Rule {
     pub number_of_decks: u8,
     pub cut_card_proportion: f64, // The proportion of cards before the cut card. // TODO: Use this.
-    pub split_all_limits: u8,     // Only supports 0 or 1 now. // TODO: Use this.
-    pub split_ace_limits: u8,     // Only supports 0 or 1 now. // TODO: Use this.
+    /// The maximum number of splits in a round, e.g., 3 for "split up to 4 hands". 255 means
+    /// splitting as long as there are pairs.
+    pub split_all_limits: u8,
+    /// The maximum number of splits of Aces in a round. Splitting stops once either this or
+    /// `split_all_limits` is reached.
+    pub split_ace_limits: u8,
     pub double_policy: DoublePolicy,
-    pub dealer_hit_on_soft17: bool,
+    /// The total at which the dealer stands, usually 17.
+    pub dealer_stand_value: u16,
+    /// Whether the dealer hits a soft total equal to `dealer_stand_value`, e.g. "dealer hits soft
+    /// 17".
+    pub dealer_hit_on_soft: bool,
+    /// The highest soft total the dealer hits, e.g., Some(18) for the rare "dealer hits soft 18"
+    /// games. If it is set, it replaces `dealer_stand_value` and `dealer_hit_on_soft` for soft
+    /// totals. None (the usual) means the dealer hits soft totals below `dealer_stand_value`, and
+    /// the soft total equal to it is decided by `dealer_hit_on_soft`.
+    pub dealer_soft_hit_up_to: Option<u16>,
     pub allow_das: bool, // TODO: Use this.
     pub allow_late_surrender: bool,
+    /// Whether late surrender is also allowed on the groups after splitting.
+    pub allow_surrender_after_split: bool,
     pub peek_policy: PeekPolicy,
-    pub charlie_number: u8, // TODO: Use this.
+    /// Whether insurance is offered when the dealer shows an Ace, regardless of the peek policy.
+    /// If the dealer doesn't peek, insurance is settled by the hole card, but the round goes on.
+    pub offer_insurance_on_ace: bool,
+    /// The number of cards with which a hand that doesn't bust wins immediately, e.g., 6 for
+    /// "six-card Charlie". 0 means there is no Charlie rule.
+    pub charlie_number: u8,
 
     pub payout_blackjack: f64,
     pub payout_insurance: f64, // TODO: Use this.
+    /// Whether a player natural pushes or loses against a dealer natural. Some casinos take the
+    /// whole bet, e.g., "dealer Blackjack beats player Blackjack".
+    pub player_natural_vs_dealer_natural: PushOrLose,
+    /// The payout of a natural Blackjack whose two cards share a suit, e.g. 2.0 for the promotion
+    /// "suited Blackjack pays 2:1". None means suited ones pay payout_blackjack as usual.
+    /// Only the simulator supports this. The solver always uses payout_blackjack.
+    pub suited_blackjack_payout: Option<f64>,
+    /// The table maximum bet. 0 means no limit.
+    pub max_bet: u32,
+    /// Whether the other players at the table (in the simulator) draw like the dealer, i.e., hit
+    /// until 17. Otherwise they stand immediately, which leaves more cards in the shoe.
+    pub other_players_mimic_dealer: bool,
+    /// Whether the dealer (in the simulator) still draws her cards when all the players bust. The
+    /// outcome is already decided, but the drawn cards leave the shoe as they do at a real table,
+    /// which matters for counting.
+    pub dealer_draws_when_all_players_bust: bool,
+}
+
+/// A typical rule: 8 decks, split once, double on any two cards, dealer stands on soft 17 and
+/// peeks only with an Ace up, no DAS, no surrender, six-card Charlie, and Blackjack pays 3:2.
+impl Default for Rule {
+    fn default() -> Self {
+        Rule {
+            number_of_decks: 8,
+            cut_card_proportion: 0.5,
+            split_all_limits: 1,
+            split_ace_limits: 1,
+            double_policy: DoublePolicy::AnyTwo,
+            dealer_stand_value: 17,
+            dealer_hit_on_soft: false,
+            dealer_soft_hit_up_to: None,
+            allow_das: false,
+            allow_late_surrender: false,
+            allow_surrender_after_split: false,
+            peek_policy: PeekPolicy::UpAce,
+            offer_insurance_on_ace: true,
+            charlie_number: 6,
+
+            payout_blackjack: 1.5,
+            payout_insurance: 2.0,
+            player_natural_vs_dealer_natural: PushOrLose::Push,
+            suited_blackjack_payout: None,
+            max_bet: 0,
+            other_players_mimic_dealer: false,
+            dealer_draws_when_all_players_bust: true,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize_enum_str, Deserialize_enum_str)]
+impl Rule {
+    /// Returns the table maximum bet, or u32::MAX if there is no limit.
+    pub fn get_max_bet(&self) -> u32 {
+        match self.max_bet {
+            0 => u32::MAX,
+            max_bet => max_bet,
+        }
+    }
+
+    /// Returns whether the given hand has reached the Charlie number, i.e., it wins immediately if
+    /// it doesn't bust. Always false if there is no Charlie rule.
+    pub fn reached_charlie(&self, hand: &CardCount) -> bool {
+        self.charlie_number != 0 && hand.get_total() >= self.charlie_number as u16
+    }
+
+    /// Returns whether the dealer must stand on the given hand.
+    pub fn dealer_must_stand(&self, dealer_hand: &CardCount) -> bool {
+        let total = dealer_hand.get_actual_sum();
+        if dealer_hand.can_be_soft_total(total) {
+            total >= self.dealer_soft_stand_value()
+        } else {
+            total >= self.dealer_stand_value
+        }
+    }
+
+    /// Returns the lowest soft total on which the dealer stands.
+    pub fn dealer_soft_stand_value(&self) -> u16 {
+        match self.dealer_soft_hit_up_to {
+            Some(dealer_soft_hit_up_to) => dealer_soft_hit_up_to + 1,
+            None => self.dealer_stand_value + self.dealer_hit_on_soft as u16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize_enum_str, Deserialize_enum_str)]
 pub enum DoublePolicy {
     AnyTwo,
     NineTenElevenOnly,
     TenElevenOnly,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize_enum_str, Deserialize_enum_str)]
+impl DoublePolicy {
+    /// Returns whether the policy allows doubling down on the given hand. Only the total of the
+    /// hand is checked, e.g. soft 19 (Ace and 8) is not allowed by `NineTenElevenOnly`.
+    pub fn allows(&self, hand: &CardCount) -> bool {
+        let total = hand.get_actual_sum();
+        match self {
+            DoublePolicy::AnyTwo => true,
+            DoublePolicy::NineTenElevenOnly => (9..=11).contains(&total),
+            DoublePolicy::TenElevenOnly => (10..=11).contains(&total),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize_enum_str, Deserialize_enum_str)]
 pub enum PeekPolicy {
     UpAceOrTen,
     UpAce,
     NoPeek,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize_enum_str, Deserialize_enum_str)]
+pub enum PushOrLose {
+    Push,
+    Lose,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct InitialSituation {
     shoe: CardCount,
@@ -61,8 +182,9 @@ impl InitialSituation {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub enum Decision {
+    #[default]
     PlaceHolder,
     Hit,
     Stand,
@@ -71,9 +193,3 @@ pub enum Decision {
     Split,
     Insurance,
 }
-
-impl Default for Decision {
-    fn default() -> Self {
-        Decision::PlaceHolder
-    }
-}
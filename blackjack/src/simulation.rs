@@ -1,8 +1,13 @@
 pub mod hand;
 pub mod shoe;
 
-use crate::{CardCount, InitialSituation, PeekPolicy, Rule};
-use blackjack_macros::allowed_phase;
+pub use crate::calculation::mimic_dealer_ev;
+use crate::calculation::SolutionForInitialSituation;
+use crate::strategy::{edge_based_bet, Strategy};
+use crate::{CardCount, Decision, InitialSituation, PeekPolicy, PushOrLose, Rule};
+use blackjack_macros::{allowed_phase, validate_hand_at_least_two_cards};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use strum_macros::EnumIter;
 
 static FACE_VALUE_TO_BLACKJACK_VALUE: [u8; 13] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 10, 10, 10];
@@ -27,26 +32,10 @@ impl Card {
     pub fn blackjack_value(&self) -> u8 {
         FACE_VALUE_TO_BLACKJACK_VALUE[(self.face_value - 1) as usize]
     }
-}
 
-impl Default for Card {
-    fn default() -> Self {
-        Card {
-            face_value: 1,
-            suit: Suit::Diamond,
-        }
-    }
-}
-
-impl std::fmt::Display for Card {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let suit = match self.suit {
-            Suit::Diamond => 'D',
-            Suit::Club => 'C',
-            Suit::Heart => 'H',
-            Suit::Spade => 'S',
-        };
-        let value = match self.face_value {
+    /// Returns the rank of the card as a character, i.e., one of A23456789TJQK.
+    pub fn rank_char(&self) -> char {
+        match self.face_value {
             1 => 'A',
             2 => '2',
             3 => '3',
@@ -61,14 +50,38 @@ impl std::fmt::Display for Card {
             12 => 'Q',
             13 => 'K',
             _ => panic!("Invalid card face value!"),
-        };
-        write!(f, "{}{}", suit, value)
+        }
+    }
+
+    /// Returns the suit of the card as a character, i.e., one of DCHS.
+    pub fn suit_char(&self) -> char {
+        match self.suit {
+            Suit::Diamond => 'D',
+            Suit::Club => 'C',
+            Suit::Heart => 'H',
+            Suit::Spade => 'S',
+        }
     }
 }
 
-impl Into<u8> for Card {
-    fn into(self) -> u8 {
-        self.suit as u8 * 13 + self.face_value - 1
+impl Default for Card {
+    fn default() -> Self {
+        Card {
+            face_value: 1,
+            suit: Suit::Diamond,
+        }
+    }
+}
+
+impl std::fmt::Display for Card {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.suit_char(), self.rank_char())
+    }
+}
+
+impl From<Card> for u8 {
+    fn from(val: Card) -> Self {
+        val.suit as u8 * 13 + val.face_value - 1
     }
 }
 
@@ -95,6 +108,93 @@ impl TryFrom<u8> for Card {
     }
 }
 
+/// The feedback of a decision submitted in practice mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecisionFeedback {
+    /// The best decision for the hand before the submitted decision is made.
+    pub optimal_decision: Decision,
+
+    /// Whether the submitted decision is the best decision.
+    pub is_correct: bool,
+
+    /// How much expectation (based on the original bet) is lost by the submitted decision. It is
+    /// 0 if the submitted decision is correct.
+    pub ex_loss: f64,
+
+    /// Whether the current group cannot be played any more after the submitted decision.
+    pub group_finished: bool,
+}
+
+/// The result of a group of your hand in a round.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GroupResult {
+    /// The bet of the group, including the doubled part.
+    pub bet: u32,
+
+    /// The money you win from the group, e.g., twice the bet if the group wins.
+    pub win: u32,
+}
+
+/// The result of a round, where all the money is what you win rather than your profit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundResult {
+    /// The money you win from the main game, i.e., the sum of the wins of all groups.
+    pub main_win: u32,
+
+    /// The money you win from insurance, which is 0 if you didn't buy it or it lost.
+    pub insurance_win: u32,
+
+    pub per_group: Vec<GroupResult>,
+}
+
+impl RoundResult {
+    /// Returns the total money you win including all side bets.
+    pub fn total_win(&self) -> u32 {
+        self.main_win + self.insurance_win
+    }
+}
+
+/// A histogram of integer samples with bins of equal width.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// The lower bound (inclusive) of the first bin.
+    pub start: i64,
+
+    pub bin_width: i64,
+
+    /// The number of samples in each bin.
+    pub counts: Vec<u64>,
+
+    /// The mean of all samples.
+    pub mean: f64,
+}
+
+impl Histogram {
+    /// Builds a histogram with the given number of bins, which cover all the samples.
+    pub fn from_samples(samples: &[i64], number_of_bins: usize) -> Histogram {
+        let (Some(&min), Some(&max)) = (samples.iter().min(), samples.iter().max()) else {
+            return Histogram {
+                start: 0,
+                bin_width: 1,
+                counts: vec![0; number_of_bins],
+                mean: 0.0,
+            };
+        };
+        let number_of_bins = number_of_bins.max(1);
+        let bin_width = ((max - min) / number_of_bins as i64 + 1).max(1);
+        let mut counts = vec![0; number_of_bins];
+        for &sample in samples {
+            counts[((sample - min) / bin_width) as usize] += 1;
+        }
+        Histogram {
+            start: min,
+            bin_width,
+            counts,
+            mean: samples.iter().sum::<i64>() as f64 / samples.len() as f64,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum GamePhase {
     WaitForPlayerSeat,
@@ -109,6 +209,123 @@ pub enum GamePhase {
     StartNewShoe,
 }
 
+impl GamePhase {
+    /// Returns the canonical name of the phase, which is stable for logging.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GamePhase::WaitForPlayerSeat => "WaitForPlayerSeat",
+            GamePhase::PlaceBets => "PlaceBets",
+            GamePhase::DealInitialCards => "DealInitialCards",
+            GamePhase::DealerPeek => "DealerPeek",
+            GamePhase::WaitForRightPlayers => "WaitForRightPlayers",
+            GamePhase::PlaySplit => "PlaySplit",
+            GamePhase::Play => "Play",
+            GamePhase::WaitForLeftPlayers => "WaitForLeftPlayers",
+            GamePhase::DealerPlayAndSummary => "DealerPlayAndSummary",
+            GamePhase::StartNewShoe => "StartNewShoe",
+        }
+    }
+}
+
+impl std::fmt::Display for GamePhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Returns the approximate expected number of cards dealt in a round with the given number of
+/// players (the dealer is counted additionally). See `cards_per_round_moments`.
+pub fn expected_cards_per_round(rule: &Rule, number_of_players: u8) -> f64 {
+    cards_per_round_moments(rule, number_of_players).0
+}
+
+/// Returns the mean and variance of the number of cards dealt in a round with the given number of
+/// players, assuming the hands are independent. The dealer and you are assumed to draw until
+/// `Rule::dealer_must_stand`, since your actual strategy is unknown here. The other players do the
+/// same if `other_players_mimic_dealer` is true, and stand on their first two cards otherwise, as
+/// they do in the simulator.
+fn cards_per_round_moments(rule: &Rule, number_of_players: u8) -> (f64, f64) {
+    let (mean, variance) = dealer_like_cards_moments(rule);
+    let (other_mean, other_variance) = if rule.other_players_mimic_dealer {
+        (mean, variance)
+    } else {
+        (2.0, 0.0)
+    };
+    let other_players = number_of_players.max(1) as f64 - 1.0;
+    (
+        2.0 * mean + other_players * other_mean,
+        2.0 * variance + other_players * other_variance,
+    )
+}
+
+/// Returns the mean and variance of the number of cards in a hand that draws until
+/// `Rule::dealer_must_stand`. The cards are drawn from an infinite shoe, i.e., each rank has
+/// probability 1/13 and the ten-valued cards have 4/13 together.
+fn dealer_like_cards_moments(rule: &Rule) -> (f64, f64) {
+    fn aux(rule: &Rule, hand: &mut CardCount, probability: f64, moments: &mut (f64, f64, f64)) {
+        if hand.get_total() >= 2 && rule.dealer_must_stand(hand) {
+            let number_of_cards = hand.get_total() as f64;
+            moments.0 += probability;
+            moments.1 += probability * number_of_cards;
+            moments.2 += probability * number_of_cards * number_of_cards;
+            return;
+        }
+        for card in 1..=10 {
+            let card_probability = if card == 10 { 4.0 / 13.0 } else { 1.0 / 13.0 };
+            hand.add_card(card);
+            aux(rule, hand, probability * card_probability, moments);
+            hand.remove_card(card);
+        }
+    }
+
+    // (Total probability, sum of p * n, sum of p * n^2)
+    let mut moments = (0.0, 0.0, 0.0);
+    aux(
+        rule,
+        &mut CardCount::with_number_of_decks(0),
+        1.0,
+        &mut moments,
+    );
+    let mean = moments.1 / moments.0;
+    (mean, moments.2 / moments.0 - mean * mean)
+}
+
+/// Returns the approximate expected number of rounds a single player plays before the cut card is
+/// reached and the shoe is reshuffled.
+pub fn expected_rounds_per_shoe(rule: &Rule) -> f64 {
+    let cut_card_index =
+        (rule.cut_card_proportion * (rule.number_of_decks as u16 * 52) as f64) as usize;
+    cut_card_index as f64 / expected_cards_per_round(rule, 1)
+}
+
+/// The number of bins of the histogram returned by `session_outcome_distribution`.
+const SESSION_HISTOGRAM_BINS: usize = 20;
+
+/// Samples `trials` sessions of `rounds` rounds each, where you play alone with the given strategy
+/// and a fresh shoe, and returns the distribution of the profit at the end of each session. The
+/// bet of each round is given by `bet_ramp` from the expectation before bet (e.g. with
+/// `strategy::edge_based_bet`), so the bets are correlated across rounds of the same shoe. Trial
+/// `i` uses the seed `seed + i`, so the sessions can be replayed.
+pub fn session_outcome_distribution(
+    rule: &Rule,
+    strategy: &mut impl Strategy,
+    rounds: usize,
+    bet_ramp: impl Fn(f64) -> u32,
+    trials: u64,
+    seed: u64,
+) -> Result<Histogram, String> {
+    let mut profits = Vec::with_capacity(trials as usize);
+    for trial in 0..trials {
+        let mut simulator = Simulator::with_seed(rule, seed.wrapping_add(trial));
+        let mut profit = 0;
+        for _ in 0..rounds {
+            profit += simulator.play_round_with_strategy(strategy, &bet_ramp)?;
+        }
+        profits.push(profit);
+    }
+    Ok(Histogram::from_samples(&profits, SESSION_HISTOGRAM_BINS))
+}
+
 /// Simulates a Blackjack table. Note that there are some differences:
 /// 1. Even when you place no bet, you can still play.
 pub struct Simulator {
@@ -118,9 +335,16 @@ pub struct Simulator {
 
     // Game state
     current_game_phase: GamePhase,
+    rng: StdRng,
     shoe: shoe::Shoe,
     dealer_hand: hand::Hand,
+    other_player_hands: Vec<CardCount>,
+    // The number of cards dealt from the shoe before this round.
+    round_first_card_index: usize,
     insurance_bet: u32,
+    // The money returned from insurance, i.e., the stake plus the winnings if the dealer has a
+    // natural, and 0 otherwise.
+    insurance_win: u32,
 
     // My playing state
     current_split_all_times: u8,
@@ -131,16 +355,30 @@ pub struct Simulator {
 
 impl Simulator {
     pub fn new(rule: &Rule) -> Self {
+        Self::with_rng(rule, StdRng::from_entropy())
+    }
+
+    /// Creates a simulator whose shoes are shuffled by a random number generator with the given
+    /// seed, so that the games can be replayed.
+    pub fn with_seed(rule: &Rule, seed: u64) -> Self {
+        Self::with_rng(rule, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rule: &Rule, mut rng: StdRng) -> Self {
         let mut shoe = shoe::Shoe::new(rule.number_of_decks, rule.cut_card_proportion);
-        shoe.shuffle(0);
+        shoe.shuffle_with(&mut rng, 0);
         Self {
             rule: *rule,
             number_of_players: 0,
             seat_order: 0,
             current_game_phase: GamePhase::WaitForPlayerSeat,
+            rng,
             shoe,
             dealer_hand: hand::Hand::new(),
+            other_player_hands: Vec::new(),
+            round_first_card_index: 0,
             insurance_bet: 0,
+            insurance_win: 0,
             current_split_all_times: 0,
             current_split_ace_times: 0,
             current_playing_group_index: 0,
@@ -149,20 +387,23 @@ impl Simulator {
     }
 
     /// This will seat the player. Can be called at WaitForPlayerSeat phase.
-    /// Call this with two zeros to indicate not changing.
+    /// Call this with two zeros to indicate not changing. A new simulator plays alone by default.
     #[allowed_phase(WaitForPlayerSeat)]
     pub fn seat_player(&mut self, number_of_players: u8, seat_order: u8) -> Result<(), String> {
         if number_of_players > MAX_PLAYER {
             return Err(format!("number_of_players cannot exceed {}", MAX_PLAYER));
         }
-        if seat_order >= number_of_players {
-            return Err(format!("seat_order should be less than number_of_players"));
+        let unchanged = number_of_players == 0 && seat_order == 0;
+        if !unchanged && seat_order >= number_of_players {
+            return Err(String::from(
+                "seat_order should be less than number_of_players",
+            ));
         }
 
         self.current_game_phase = GamePhase::PlaceBets;
         self.new_game();
 
-        if number_of_players == 0 && seat_order == 0 {
+        if unchanged {
             return Ok(());
         }
         self.number_of_players = number_of_players;
@@ -174,19 +415,29 @@ impl Simulator {
     /// Place 0 bet to indicate not to place any bet this time.
     #[allowed_phase(PlaceBets)]
     pub fn place_bets(&mut self, bet: u32) -> Result<(), String> {
+        if bet > self.rule.get_max_bet() {
+            return Err(format!("bet cannot exceed max_bet {}", self.rule.max_bet));
+        }
         if (bet as f64 * self.rule.payout_blackjack).fract() != 0.0 {
-            return Err(format!(
-                "bet multiplied by payout_blackjack must be an integer"
+            return Err(String::from(
+                "bet multiplied by payout_blackjack must be an integer",
             ));
         }
-        if bet % 2 != 0 {
-            return Err(format!(
-                "bet must be an even integer to possibly buy insurance"
+        if let Some(payout) = self.rule.suited_blackjack_payout {
+            if (bet as f64 * payout).fract() != 0.0 {
+                return Err(String::from(
+                    "bet multiplied by suited_blackjack_payout must be an integer",
+                ));
+            }
+        }
+        if !bet.is_multiple_of(2) {
+            return Err(String::from(
+                "bet must be an even integer to possibly buy insurance",
             ));
         }
         if ((bet / 2) as f64 * self.rule.payout_insurance).fract() != 0.0 {
-            return Err(format!(
-                "Half of bet multiplied by payout_insurance must be an integer"
+            return Err(String::from(
+                "Half of bet multiplied by payout_insurance must be an integer",
             ));
         }
         self.current_hand.set_original_bet(bet);
@@ -199,11 +450,18 @@ impl Simulator {
     /// Returns InitialSituation.
     #[allowed_phase(DealInitialCards)]
     pub fn deal_initial_cards(&mut self) -> Result<InitialSituation, String> {
+        self.round_first_card_index = self.shoe.dealt_cards().len();
+        // If the player is never seated, play alone.
+        let number_of_players = self.number_of_players.max(1);
+        self.other_player_hands =
+            vec![CardCount::with_number_of_decks(0); number_of_players as usize];
         for _ in 0..2 {
-            for i in 0..self.number_of_players {
+            for i in 0..number_of_players {
                 let card = self.shoe.deal_card().unwrap();
                 if i == self.seat_order {
                     self.receive_card_for_me(card);
+                } else {
+                    self.other_player_hands[i as usize].add_card(card.blackjack_value());
                 }
             }
             let card = self.shoe.deal_card().unwrap();
@@ -237,35 +495,59 @@ impl Simulator {
             PeekPolicy::UpAce => up == 1,
             PeekPolicy::NoPeek => false,
         };
-        if !dealer_will_peek {
-            if buy_insurance {
-                return Err(format!("Cannot buy insurance when dealer doesn't peek!"));
-            }
-            self.current_game_phase = GamePhase::WaitForRightPlayers;
-            return Ok(false);
-        }
-
         if buy_insurance {
+            if !self.offer_insurance() {
+                return Err(String::from("Insurance is not offered this round!"));
+            }
             self.insurance_bet = self.current_hand.get_bet(0) / 2;
         }
 
         let hole = dealer_cards[1].blackjack_value();
         let dealer_is_natural = up + hole == 11;
         if dealer_is_natural {
-            self.insurance_bet += ((self.insurance_bet as f64) * self.rule.payout_insurance) as u32;
+            let winnings = (self.insurance_bet as f64 * self.rule.payout_insurance).round() as u32;
+            self.insurance_win = self.insurance_bet + winnings;
+        } else {
+            self.insurance_win = 0;
+        }
+
+        // If the dealer doesn't peek, her natural is only revealed after the players play.
+        if dealer_will_peek && dealer_is_natural {
             self.current_game_phase = GamePhase::DealerPlayAndSummary;
+            Ok(true)
         } else {
-            self.insurance_bet = 0;
             self.current_game_phase = GamePhase::WaitForRightPlayers;
+            Ok(false)
+        }
+    }
+
+    /// Can be called at DealerPeek phase, before `dealer_peeks_if_necessary`.
+    /// Buys insurance of the given amount, which must not exceed half of your bet. Call
+    /// `dealer_peeks_if_necessary` with false afterwards to settle it.
+    #[allowed_phase(DealerPeek)]
+    pub fn buy_insurance(&mut self, amount: u32) -> Result<(), String> {
+        if !self.offer_insurance() {
+            return Err(String::from(
+                "Insurance is only offered when dealer shows an Ace!",
+            ));
+        }
+        if amount > self.current_hand.get_bet(0) / 2 {
+            return Err(String::from("Insurance cannot exceed half of your bet"));
+        }
+        if (amount as f64 * self.rule.payout_insurance).fract() != 0.0 {
+            return Err(String::from(
+                "amount multiplied by payout_insurance must be an integer",
+            ));
         }
-        Ok(dealer_is_natural)
+        self.insurance_bet = amount;
+        Ok(())
     }
 
     /// Can be called at WaitForRightPlayers phase.
     /// Call this to wait for players on your right.
     #[allowed_phase(WaitForRightPlayers)]
     pub fn wait_for_right_players(&mut self) -> Result<(), String> {
-        // Simply let them stand immediately.
+        self.let_other_players_play(0..self.seat_order);
         self.current_game_phase = GamePhase::PlaySplit;
         Ok(())
     }
@@ -278,11 +560,13 @@ impl Simulator {
     #[allowed_phase(PlaySplit)]
     pub fn play_split(&mut self, group_index: usize) -> Result<bool, String> {
         if self.reached_split_time_limits() {
-            return Err(format!("You reached split time limits!"));
+            return Err(String::from("You reached split time limits!"));
         }
         let cards = self.current_hand.get_cards(group_index);
         if cards[0].blackjack_value() != cards[1].blackjack_value() {
-            return Err(format!("You cannot split two cards with different values!"));
+            return Err(String::from(
+                "You cannot split two cards with different values!",
+            ));
         }
 
         self.current_split_all_times += 1;
@@ -319,6 +603,7 @@ impl Simulator {
 
     /// Can be called at Play phase.
     /// Returns true if cannot play current hand group any more.
+    #[validate_hand_at_least_two_cards]
     #[allowed_phase(Play)]
     pub fn play_stand(&mut self) -> Result<bool, String> {
         self.move_to_next_group();
@@ -326,7 +611,9 @@ impl Simulator {
     }
 
     /// Can be called at Play phase.
-    /// Returns true if cannot play current hand group any more.
+    /// Returns true if cannot play current hand group any more, i.e., it busts, reaches Charlie or
+    /// reaches 21.
+    #[validate_hand_at_least_two_cards]
     #[allowed_phase(Play)]
     pub fn play_hit(&mut self) -> Result<bool, String> {
         let card = self.shoe.deal_card().unwrap();
@@ -337,31 +624,50 @@ impl Simulator {
             self.move_to_next_group();
             return Ok(true);
         }
-        if my_card_count.get_total() == self.rule.charlie_number as u16 {
+        if self.rule.reached_charlie(my_card_count) {
             self.determine_winning(2.0);
             self.move_to_next_group();
             return Ok(true);
         }
+        if my_card_count.get_actual_sum() == 21 {
+            self.move_to_next_group();
+            return Ok(true);
+        }
 
         Ok(false)
     }
 
     /// Can be called at Play phase.
     /// Returns true if cannot play current hand group any more.
+    #[validate_hand_at_least_two_cards]
     #[allowed_phase(Play)]
     pub fn play_double(&mut self) -> Result<bool, String> {
-        let my_card_count = self.get_my_current_card_count();
-        if my_card_count.get_total() != 2 {
-            return Err(format!("You can only double down on initial 2 cards"));
-        }
-        if self.current_hand.get_number_of_groups() > 1 && !self.rule.allow_das {
-            return Err(format!("DAS is not allowed"));
+        let bet = self.current_hand.get_bet(self.current_playing_group_index);
+        self.play_double_for_less(bet)
+    }
+
+    /// Can be called at Play phase.
+    /// Doubles down but only adds the given amount to the bet of the current group. The amount
+    /// must be within the range returned by `double_bounds`.
+    /// Returns true if cannot play current hand group any more.
+    #[validate_hand_at_least_two_cards]
+    #[allowed_phase(Play)]
+    pub fn play_double_for_less(&mut self, amount: u32) -> Result<bool, String> {
+        self.check_double_allowed()?;
+        let (min_amount, max_amount) = self
+            .double_bounds()
+            .ok_or_else(|| String::from("Cannot double down without a bet"))?;
+        if amount < min_amount || amount > max_amount {
+            return Err(format!(
+                "Doubling amount must be in [{}, {}]",
+                min_amount, max_amount
+            ));
         }
 
         let card = self.shoe.deal_card().unwrap();
         self.receive_card_for_me(card);
         self.current_hand
-            .double_down(self.current_playing_group_index);
+            .double_down_for_less(self.current_playing_group_index, amount);
         let my_card_count = self.get_my_current_card_count();
         if my_card_count.bust() {
             self.determine_winning(0.0);
@@ -372,53 +678,80 @@ impl Simulator {
 
     /// Can be called at Play phase.
     /// Returns true if cannot play current hand group any more.
+    #[validate_hand_at_least_two_cards]
     #[allowed_phase(Play)]
     pub fn play_surrender(&mut self) -> Result<bool, String> {
         if !self.rule.allow_late_surrender {
-            return Err(format!("Surrender is not allowed!"));
+            return Err(String::from("Surrender is not allowed!"));
         }
-        self.determine_winning(0.5);
+        if self.current_hand.get_number_of_groups() > 1 && !self.rule.allow_surrender_after_split {
+            return Err(String::from("Surrender after split is not allowed"));
+        }
+        self.current_hand
+            .surrender(self.current_playing_group_index);
         self.move_to_next_group();
         Ok(true)
     }
 
+    /// Can be called at Play phase.
+    /// Practice mode: makes the given decision on the current group, and compares it to the best
+    /// decision according to the given solution of this initial situation (e.g. got from
+    /// `calculation::calculate_solution_with_initial_situation`).
+    #[validate_hand_at_least_two_cards]
+    #[allowed_phase(Play)]
+    pub fn submit_decision(
+        &mut self,
+        decision: Decision,
+        solution: &SolutionForInitialSituation,
+    ) -> Result<DecisionFeedback, String> {
+        let hand = *self.get_my_current_card_count();
+        let can_double = self.check_double_allowed().is_ok();
+        let optimal_decision = solution.decision_for(&hand, &self.rule, can_double, false, true);
+        let optimal_ex = solution.decision_expectation(&hand, &self.rule, optimal_decision);
+        let ex = solution.decision_expectation(&hand, &self.rule, decision);
+
+        let group_finished = match decision {
+            Decision::Stand => self.play_stand()?,
+            Decision::Hit => self.play_hit()?,
+            Decision::Double => self.play_double()?,
+            Decision::Surrender => self.play_surrender()?,
+            _ => return Err(format!("{:?} cannot be submitted in Play phase", decision)),
+        };
+        Ok(DecisionFeedback {
+            optimal_decision,
+            is_correct: decision == optimal_decision,
+            ex_loss: if decision == optimal_decision {
+                0.0
+            } else {
+                optimal_ex - ex
+            },
+            group_finished,
+        })
+    }
+
     /// Can be called at WaitForLeftPlayers phase.
     /// Call this to wait for players on your left.
     #[allowed_phase(WaitForLeftPlayers)]
     pub fn wait_for_left_players(&mut self) -> Result<(), String> {
-        // Simply let them stand immediately.
+        self.let_other_players_play(self.seat_order + 1..self.number_of_players);
         self.current_game_phase = GamePhase::DealerPlayAndSummary;
         Ok(())
     }
 
     /// Can be called at DealerPlayAndSummary phase.
     /// Call this to make dealer play according to game rule.
-    /// Returns the total money you win including all side bets.
+    /// Returns the money you win from each group and from insurance. Use
+    /// `RoundResult::total_win` for the total money you win including all side bets.
     /// Note that this is what you win, not your profit. For example,
     /// you wager 10 dollars. If you win, you win 20. If you lose,
     /// you win 0.
     #[allowed_phase(DealerPlayAndSummary)]
-    pub fn dealer_plays_and_summary(&mut self) -> Result<u32, String> {
-        let main_win = loop {
+    pub fn dealer_plays_and_summary(&mut self) -> Result<RoundResult, String> {
+        let dealer_draws = self.rule.dealer_draws_when_all_players_bust || !self.all_players_bust();
+        let per_group = loop {
             let dealer_card_count = self.get_dealer_card_count();
-            let must_stand = {
-                let actual_sum = dealer_card_count.get_actual_sum();
-                let is_soft = dealer_card_count.is_soft();
-                if actual_sum > 17 {
-                    true
-                } else if actual_sum < 17 {
-                    false
-                } else {
-                    if !is_soft {
-                        true
-                    } else {
-                        !self.rule.dealer_hit_on_soft17
-                    }
-                }
-            };
-
-            if must_stand {
-                let mut total_win = 0;
+            if !dealer_draws || self.rule.dealer_must_stand(dealer_card_count) {
+                let mut per_group = Vec::new();
                 for i in 0..self.current_hand.get_number_of_groups() {
                     let my_card_count = self.current_hand.get_card_counts(i);
                     let mut this_group_win = self.current_hand.get_bet(i);
@@ -429,22 +762,27 @@ impl Simulator {
                         && self.current_hand.get_number_of_groups() == 1
                     {
                         if !dealer_card_count.is_natural() {
-                            this_group_win +=
-                                (this_group_win as f64 * self.rule.payout_blackjack) as u32;
+                            let payout = self.get_blackjack_payout(i);
+                            this_group_win += (this_group_win as f64 * payout) as u32;
+                        } else if self.rule.player_natural_vs_dealer_natural == PushOrLose::Lose {
+                            this_group_win = 0;
                         }
                     } else if dealer_card_count.bust() {
                         this_group_win *= 2;
-                    } else if dealer_card_count.is_natural() {
-                        this_group_win = 0;
-                    } else if my_card_count.get_actual_sum() < dealer_card_count.get_actual_sum() {
+                    } else if dealer_card_count.is_natural()
+                        || my_card_count.get_actual_sum() < dealer_card_count.get_actual_sum()
+                    {
                         this_group_win = 0;
                     } else if my_card_count.get_actual_sum() > dealer_card_count.get_actual_sum() {
                         this_group_win *= 2;
                     }
-                    total_win += this_group_win;
+                    per_group.push(GroupResult {
+                        bet: self.current_hand.get_wager(i),
+                        win: this_group_win,
+                    });
                 }
 
-                break total_win;
+                break per_group;
             }
 
             let card = self.shoe.deal_card().unwrap();
@@ -452,8 +790,11 @@ impl Simulator {
         };
 
         self.current_game_phase = GamePhase::StartNewShoe;
-        let insurance_win = self.insurance_bet;
-        Ok(main_win + insurance_win)
+        Ok(RoundResult {
+            main_win: per_group.iter().map(|group| group.win).sum(),
+            insurance_win: self.insurance_win,
+            per_group,
+        })
     }
 
     /// Can be called at StartNewShoe phase.
@@ -461,19 +802,85 @@ impl Simulator {
     #[allowed_phase(StartNewShoe)]
     pub fn start_new_shoe_if_necessary(&mut self) -> Result<(), String> {
         if self.shoe.reached_cut_card() {
-            self.shoe.shuffle(0);
+            self.shoe.shuffle_with(&mut self.rng, 0);
         }
         self.current_game_phase = GamePhase::WaitForPlayerSeat;
         Ok(())
     }
 
+    /// Returns all the cards dealt in this round, including the cards of the other players and the
+    /// dealer, which are all revealed by the end of the round.
+    pub fn revealed_cards(&self) -> &[Card] {
+        let dealt_cards = self.shoe.dealt_cards();
+        &dealt_cards[self.round_first_card_index.min(dealt_cards.len())..]
+    }
+
+    /// Returns how each group of your hand is resolved, e.g., to report the outcome of each group
+    /// at DealerPlayAndSummary phase.
+    pub fn hand_states(&self) -> &[hand::HandState] {
+        self.current_hand.hand_states()
+    }
+
+    /// Estimates the probability that the cut card is reached by the end of this round, i.e., the
+    /// shoe will be reshuffled after this round. The number of cards dealt in a round is
+    /// approximated by a normal distribution with the mean and variance of
+    /// `cards_per_round_moments`.
+    pub fn will_likely_reshuffle_after_round(&self) -> f64 {
+        let cards_left = self.shoe.cards_before_cut_card();
+        if cards_left == 0 {
+            return 1.0;
+        }
+        let (mean, variance) = cards_per_round_moments(&self.rule, self.number_of_players);
+        // The logistic approximation of the standard normal CDF, whose error is below 0.01. See
+        // Bowling et al., "A logistic approximation to the cumulative normal distribution",
+        // Journal of Industrial Engineering and Management 2(1), 2009.
+        let z = (cards_left as f64 - mean) / variance.sqrt();
+        1.0 / (1.0 + (1.702 * z).exp())
+    }
+
+    /// Returns the name of the current game phase.
+    pub fn phase_name(&self) -> &'static str {
+        self.current_game_phase.name()
+    }
+
+    /// Returns the minimum and maximum amount you may add to the bet of the current group when
+    /// doubling down (the maximum is a full double), or None if doubling down is not allowed now or
+    /// there is no bet to double.
+    pub fn double_bounds(&self) -> Option<(u32, u32)> {
+        if self.current_game_phase != GamePhase::Play
+            || self
+                .current_hand
+                .get_cards(self.current_playing_group_index)
+                .len()
+                < 2
+            || self.check_double_allowed().is_err()
+        {
+            return None;
+        }
+        let bet = self.current_hand.get_bet(self.current_playing_group_index);
+        if bet == 0 {
+            return None;
+        }
+        Some((1, bet))
+    }
+
+    /// Returns whether insurance is available this round, i.e., it is DealerPeek phase, dealer
+    /// shows an Ace, and the rule offers insurance on an Ace.
+    pub fn offer_insurance(&self) -> bool {
+        if self.current_game_phase != GamePhase::DealerPeek {
+            return false;
+        }
+        let up = self.dealer_hand.get_cards(0)[0].blackjack_value();
+        up == 1 && self.rule.offer_insurance_on_ace
+    }
+
     pub fn reached_split_time_limits(&self) -> bool {
         self.current_split_all_times == self.rule.split_all_limits
             || self.current_split_ace_times == self.rule.split_ace_limits
     }
 
     pub fn get_shoe_card_count(&self) -> &CardCount {
-        &self.shoe.get_card_count()
+        self.shoe.get_card_count()
     }
 
     pub fn get_current_split_all_times(&self) -> u8 {
@@ -488,6 +895,19 @@ impl Simulator {
         self.current_hand.get_number_of_groups()
     }
 
+    /// Returns the total amount you wagered on the main bet in this game, across all groups.
+    pub fn get_my_total_bet(&self) -> u32 {
+        self.current_hand.total_bet()
+    }
+
+    /// Returns the amount wagered on the group being played, including the extra bet from double
+    /// down. Once all groups are played, returns that of the last group.
+    pub fn current_group_bet(&self) -> u32 {
+        let last_group_index = self.current_hand.get_number_of_groups() - 1;
+        self.current_hand
+            .get_wager(self.current_playing_group_index.min(last_group_index))
+    }
+
     pub fn get_my_current_card_count(&self) -> &CardCount {
         self.current_hand
             .get_card_counts(self.current_playing_group_index)
@@ -497,10 +917,85 @@ impl Simulator {
         self.dealer_hand.get_card_counts(0)
     }
 
+    /// Returns the hole card of the dealer, or None if it is not dealt yet. Note that this reveals
+    /// the card before the dealer does, so it is only meant for omniscient analyses, e.g., to
+    /// compare the expected value of a decision with the realized one.
+    pub fn dealer_hole_card(&self) -> Option<Card> {
+        self.dealer_hand.get_cards(0).get(1).copied()
+    }
+
+    /// Arranges the shoe so that the next dealt card has the given blackjack value, e.g., to test a
+    /// particular play line. Returns an error if no card of this value is left in the shoe.
+    pub fn force_next_card(&mut self, blackjack_value: u8) -> Result<(), String> {
+        self.shoe.force_next_card(blackjack_value)
+    }
+
     pub fn preview_next_few_cards_in_shoe(&self, number: usize) -> &[Card] {
         self.shoe.preview_next_few_cards(number)
     }
 
+    /// Plays the given number of rounds alone with the given strategy, starting from
+    /// WaitForPlayerSeat phase, where the main bet of each round is `main_bet`. Returns the total
+    /// profit.
+    pub fn automatic_simulate_with_fixed_main_bet(
+        &mut self,
+        strategy: &mut impl Strategy,
+        main_bet: u32,
+        rounds: usize,
+    ) -> Result<i64, String> {
+        let mut profit = 0;
+        for _ in 0..rounds {
+            profit += self.play_round_with_strategy(strategy, |_| main_bet)?;
+        }
+        Ok(profit)
+    }
+
+    /// Same as `automatic_simulate_with_fixed_main_bet`, but the main bet of each round is given
+    /// by `strategy::edge_based_bet` from the expectation before bet, i.e., it is `min_bet` unless
+    /// the expectation is positive.
+    pub fn automatic_simulate_with_edge_based_bet(
+        &mut self,
+        strategy: &mut impl Strategy,
+        min_bet: u32,
+        max_bet: u32,
+        rounds: usize,
+    ) -> Result<i64, String> {
+        let mut profit = 0;
+        for _ in 0..rounds {
+            profit +=
+                self.play_round_with_strategy(strategy, |ex| edge_based_bet(ex, min_bet, max_bet))?;
+        }
+        Ok(profit)
+    }
+
+    fn check_double_allowed(&self) -> Result<(), String> {
+        let my_card_count = self.get_my_current_card_count();
+        if my_card_count.get_total() != 2 {
+            return Err(String::from("You can only double down on initial 2 cards"));
+        }
+        if self.current_hand.get_number_of_groups() > 1 && !self.rule.allow_das {
+            return Err(String::from("DAS is not allowed"));
+        }
+        if !self.rule.double_policy.allows(my_card_count) {
+            return Err(format!(
+                "{:?} does not allow doubling down on {}",
+                self.rule.double_policy,
+                my_card_count.get_actual_sum()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the payout of the natural Blackjack in the given group, which depends on whether
+    /// its two cards share a suit.
+    fn get_blackjack_payout(&self, group_index: usize) -> f64 {
+        let cards = self.current_hand.get_cards(group_index);
+        match self.rule.suited_blackjack_payout {
+            Some(payout) if cards[0].suit == cards[1].suit => payout,
+            _ => self.rule.payout_blackjack,
+        }
+    }
+
     fn receive_card_for_me(&mut self, card: Card) {
         self.current_hand
             .receive_card(self.current_playing_group_index, card);
@@ -515,6 +1010,106 @@ impl Simulator {
             .determine_winning(self.current_playing_group_index, multiplier);
     }
 
+    /// Lets the other players of the given seats play. They simply stand immediately, unless
+    /// `other_players_mimic_dealer` is true, in which case they hit until 17.
+    fn let_other_players_play(&mut self, seats: std::ops::Range<u8>) {
+        if !self.rule.other_players_mimic_dealer {
+            return;
+        }
+        for i in seats {
+            while self.other_player_hands[i as usize].get_actual_sum() < 17 {
+                let card = self.shoe.deal_card().unwrap();
+                self.other_player_hands[i as usize].add_card(card.blackjack_value());
+            }
+        }
+    }
+
+    /// Plays a whole round alone with the given strategy, starting from WaitForPlayerSeat phase,
+    /// and returns the profit.
+    fn play_round_with_strategy(
+        &mut self,
+        strategy: &mut impl Strategy,
+        bet_ramp: impl Fn(f64) -> u32,
+    ) -> Result<i64, String> {
+        let rule = self.rule;
+        self.seat_player(1, 0)?;
+        let ex = strategy.calculate_expectation_before_bet(&rule, self.get_shoe_card_count());
+        self.place_bets(bet_ramp(ex))?;
+        let initial_situation = self.deal_initial_cards()?;
+        strategy.init_with_initial_situation(&rule, &initial_situation);
+        let buy_insurance =
+            self.offer_insurance() && strategy.should_buy_insurance(&rule, &initial_situation);
+        if buy_insurance {
+            self.buy_insurance(self.current_hand.get_bet(0) / 2)?;
+        }
+
+        if !self.dealer_peeks_if_necessary(false)? {
+            self.wait_for_right_players()?;
+            let mut group_index = 0;
+            while group_index < self.current_hand.get_number_of_groups() {
+                let hand = *self.current_hand.get_card_counts(group_index);
+                let is_pair = self.current_hand.get_cards(group_index)[0].blackjack_value()
+                    == self.current_hand.get_cards(group_index)[1].blackjack_value();
+                if is_pair
+                    && !self.reached_split_time_limits()
+                    && strategy.should_split(
+                        &rule,
+                        &hand,
+                        self.current_split_all_times,
+                        self.current_split_ace_times,
+                    )
+                {
+                    self.play_split(group_index)?;
+                } else {
+                    group_index += 1;
+                }
+            }
+            self.stop_split()?;
+
+            while self.current_game_phase == GamePhase::Play {
+                let hand = *self.get_my_current_card_count();
+                let decision = strategy.make_decision(
+                    &rule,
+                    &hand,
+                    self.current_split_all_times,
+                    self.current_split_ace_times,
+                );
+                match decision {
+                    Decision::Stand => self.play_stand(),
+                    Decision::Double if self.double_bounds().is_some() => self.play_double(),
+                    Decision::Surrender
+                        if hand.get_total() == 2
+                            && rule.allow_late_surrender
+                            && (self.current_hand.get_number_of_groups() == 1
+                                || rule.allow_surrender_after_split) =>
+                    {
+                        self.play_surrender()
+                    }
+                    _ => self.play_hit(),
+                }?;
+            }
+            self.wait_for_left_players()?;
+        }
+
+        let wagered = self.current_hand.total_bet() + self.insurance_bet;
+        let win = self.dealer_plays_and_summary()?.total_win();
+        strategy.observe_cards(self.revealed_cards());
+        self.start_new_shoe_if_necessary()?;
+        Ok(win as i64 - wagered as i64)
+    }
+
+    /// Returns whether all the groups of mine and all the hands of the other players bust.
+    fn all_players_bust(&self) -> bool {
+        let my_groups_bust = (0..self.current_hand.get_number_of_groups())
+            .all(|i| self.current_hand.get_card_counts(i).bust());
+        let other_players_bust = self
+            .other_player_hands
+            .iter()
+            .enumerate()
+            .all(|(i, hand)| i == self.seat_order as usize || hand.bust());
+        my_groups_bust && other_players_bust
+    }
+
     /// Move current playing group to the next group. If no more group, the game phase will proceed.
     fn move_to_next_group(&mut self) {
         self.current_playing_group_index += 1;
@@ -530,6 +1125,7 @@ impl Simulator {
         self.current_playing_group_index = 0;
         self.current_hand.clear();
         self.insurance_bet = 0;
+        self.insurance_win = 0;
     }
 }
 
@@ -537,228 +1133,1094 @@ impl Simulator {
 mod tests {
     use super::*;
 
-    fn get_typical_rule() -> Rule {
-        Rule {
-            number_of_decks: 8,
-            cut_card_proportion: 0.5,
-            split_all_limits: 1,
-            split_ace_limits: 1,
-            double_policy: crate::DoublePolicy::AnyTwo,
-            dealer_hit_on_soft17: false,
-            allow_das: false,
-            allow_late_surrender: false,
-            peek_policy: crate::PeekPolicy::UpAce,
-            charlie_number: 6,
+    #[test]
+    fn deeper_cut_card_yields_more_rounds() {
+        let mut rule = Rule::default();
+        let rounds = expected_rounds_per_shoe(&rule);
+        rule.cut_card_proportion = 0.75;
+        assert!(expected_rounds_per_shoe(&rule) > rounds);
+        assert!(rounds > 30.0 && rounds < 45.0);
+    }
 
-            payout_blackjack: 1.5,
-            payout_insurance: 2.0,
-        }
+    #[test]
+    fn card_chars() {
+        let card = Card {
+            face_value: 1,
+            suit: Suit::Spade,
+        };
+        assert_eq!((card.rank_char(), card.suit_char()), ('A', 'S'));
+        assert_eq!(card.to_string(), "SA");
     }
 
     #[test]
     fn test_allowed_phase() {
-        let rule = get_typical_rule();
+        let rule = Rule::default();
         let mut simulator = Simulator::new(&rule);
         assert_eq!(simulator.current_game_phase, GamePhase::WaitForPlayerSeat);
         assert!(simulator.seat_player(1, 0).is_ok());
         assert_eq!(simulator.current_game_phase, GamePhase::PlaceBets);
+        assert_eq!(simulator.phase_name(), "PlaceBets");
+        assert_eq!(GamePhase::PlaceBets.to_string(), "PlaceBets");
         assert!(simulator.seat_player(0, 0).is_err());
     }
-}
 
-// // Bet 100
-// fn play_a_round<T: Strategy>(rule: &Rule, strategy: &mut T, shoe: &mut Shoe) -> (i32, bool) {
-//     let (my_first_card, _) = shoe.deal_card();
-//     let (dealer_up_card, _) = shoe.deal_card();
-//     let (my_second_card, _) = shoe.deal_card();
-//     let mut counts = [0; 10];
-//     for i in shoe.current_index..shoe.cards.len() {
-//         counts[(shoe.cards[i] - 1) as usize] += 1;
-//     }
-//     let initial_shoe = CardCount::new(&counts);
-
-//     let initial_situation = InitialSituation::new(
-//         initial_shoe,
-//         (my_first_card, my_second_card),
-//         dealer_up_card,
-//     );
-//     strategy.init(rule, &initial_situation);
-//     let (dealer_hole_card, _) = shoe.deal_card();
-
-//     let mut current_hand = CardCount::new(&[0; 10]);
-//     current_hand.add_card(my_first_card);
-//     current_hand.add_card(my_second_card);
-
-//     let dealer_natural_blackjack =
-//         dealer_up_card + dealer_hole_card == 11 && (dealer_up_card == 1 || dealer_hole_card == 1);
-//     let me_natural_blackjack = current_hand.get_sum() == 11 && current_hand.is_soft();
-
-//     if dealer_natural_blackjack {
-//         if me_natural_blackjack {
-//             return (0, shoe.reached_cut_card());
-//         }
-//         return (-100, shoe.reached_cut_card());
-//     }
-
-//     let mut bet = 100;
-//     let mut has_surrendered = false;
-//     loop {
-//         if current_hand.get_sum() > 21 {
-//             break;
-//         }
-//         let my_decision = strategy.make_decision(&current_hand);
-//         print!("{:#?} ", my_decision);
-//         match my_decision {
-//             Decision::Hit => {
-//                 let (card, _) = shoe.deal_card();
-//                 current_hand.add_card(card);
-//             }
-//             Decision::Stand => {
-//                 break;
-//             }
-//             Decision::Double => {
-//                 let (card, _) = shoe.deal_card();
-//                 current_hand.add_card(card);
-//                 bet *= 2;
-//                 break;
-//             }
-//             Decision::Surrender => {
-//                 has_surrendered = true;
-//                 break;
-//             }
-//             _ => {
-//                 panic!("wtf??")
-//             }
-//         }
-//     }
-//     println!();
-
-//     let my_sum = {
-//         if current_hand.is_soft() && current_hand.get_sum() + 10 <= 21 {
-//             current_hand.get_sum() + 10
-//         } else {
-//             current_hand.get_sum()
-//         }
-//     };
-
-//     let mut dealer_sum = dealer_up_card + dealer_hole_card;
-//     let mut dealer_soft = dealer_up_card == 1 || dealer_hole_card == 1;
-//     while !(dealer_sum >= 17 || dealer_soft && dealer_sum + 10 > 17 && dealer_sum + 10 <= 21) {
-//         let (card, _) = shoe.deal_card();
-//         dealer_soft = dealer_soft || card == 1;
-//         dealer_sum += card;
-//     }
-//     if dealer_sum < 17 {
-//         dealer_sum += 10;
-//     }
-//     let dealer_sum = dealer_sum as u16;
-
-//     if has_surrendered {
-//         bet = -bet / 2;
-//     } else if my_sum > 21 {
-//         bet = -bet;
-//     } else if me_natural_blackjack {
-//         bet += bet / 2;
-//     } else if dealer_sum <= 21 {
-//         if my_sum < dealer_sum {
-//             bet = -bet;
-//         } else if my_sum == dealer_sum {
-//             bet = 0;
-//         }
-//     }
-
-//     (bet, shoe.reached_cut_card())
-// }
-
-// fn get_typical_rule() -> Rule {
-//     Rule {
-//         number_of_decks: 8,
-//         cut_card_proportion: 0.5,
-//         split_all_limits: 1,
-//         split_ace_limits: 1,
-//         double_policy: crate::DoublePolicy::AnyTwo,
-//         dealer_hit_on_soft17: true,
-//         allow_das: true,
-//         allow_late_surrender: true,
-//         peek_policy: crate::PeekPolicy::UpAceOrTen,
-
-//         payout_blackjack: 1.5,
-//         payout_insurance: 0.0,
-//     }
-// }
-
-// #[test]
-// fn test_strategy_on_new_shoe() {
-//     println!("Test begin!!!");
-//     let firsts = vec![1, 5, 2];
-//     let mut shoe = Shoe::new(8, 0.5, &firsts);
-//     let rule = get_typical_rule();
-//     let mut basic_strategy: BasicStrategy = Default::default();
-//     let mut my_strategy: MyStrategy = MyStrategy {
-//         rule: rule,
-//         sol: SolutionForInitialSituation {
-//             general_solution: StateArray::new(),
-//             split_expectation: 0.0,
-//         },
-//     };
-
-//     let mut acc_basic: i32 = 0;
-//     let mut acc_my: i32 = 0;
-//     let total_rounds = 1_000_000;
-
-//     let mut duration_max: u128 = 0;
-//     let mut duration_min: u128 = u128::MAX;
-//     let mut duration_total: u128 = 0;
-//     for round in 0..total_rounds {
-//         shoe.reinit(firsts.len());
-//         while shoe.cards[0] == 1 && shoe.cards[2] == 1 {
-//             shoe.reinit(firsts.len());
-//         }
-//         // shoe.cards[0] = 1;
-//         // shoe.cards[1] = 9;
-//         // shoe.cards[2] = 2;
-//         // shoe.cards[3] = 10;
-//         // shoe.cards[4] = 8;
-//         // shoe.cards[5] = 10;
-//         // shoe.cards[6] = 7;
-//         // shoe.cards[7] = 2;
-//         // shoe.cards[8] = 9;
-//         // shoe.cards[9] = 2;
-//         // shoe.cards[10] = 10;
-//         print!("Turn #{}: ", round);
-//         for i in 0..20 {
-//             print!("{} ", shoe.cards[i]);
-//         }
-//         println!();
-//         let (profit_basic, _) = play_a_round(&rule, &mut basic_strategy, &mut shoe);
-//         acc_basic += profit_basic;
-//         shoe.retry();
-//         let time_start = SystemTime::now();
-//         let (profit_my, _) = play_a_round(&rule, &mut my_strategy, &mut shoe);
-//         let duration = SystemTime::now()
-//             .duration_since(time_start)
-//             .unwrap()
-//             .as_millis();
-//         if duration_max < duration {
-//             duration_max = duration;
-//         }
-//         if duration_min > duration {
-//             duration_min = duration;
-//         }
-//         duration_total += duration;
-//         acc_my += profit_my;
-//         println!(
-//             "Turn #{}: {:#?}, {:#?} this({:.2}s) max({:.2}s) avg({:.2}s) min({:.2}s)",
-//             round,
-//             acc_basic,
-//             acc_my,
-//             duration as f64 / 1000.0,
-//             duration_max as f64 / 1000.0,
-//             duration_total as f64 / (round + 1) as f64 / 1000.0,
-//             duration_min as f64 / 1000.0,
-//         );
-//     }
-//     println!();
-//     println!("Acc: {}, {}", acc_basic, acc_my);
-//     println!("Total rounds: {}", total_rounds);
-// }
+    #[test]
+    fn default_seat_deals_to_me() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::with_seed(&rule, 1);
+        simulator.seat_player(0, 0).unwrap();
+        simulator.place_bets(10).unwrap();
+        simulator.deal_initial_cards().unwrap();
+        assert_eq!(simulator.current_hand.get_cards(0).len(), 2);
+        assert_eq!(simulator.dealer_hand.get_cards(0).len(), 2);
+    }
+
+    #[test]
+    fn play_is_rejected_on_single_card_group() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        simulator.current_game_phase = GamePhase::Play;
+        simulator.current_hand.receive_card(
+            0,
+            Card {
+                face_value: 8,
+                suit: Suit::Diamond,
+            },
+        );
+        let shoe_total = simulator.get_shoe_card_count().get_total();
+
+        assert!(simulator.play_hit().is_err());
+        assert!(simulator.play_stand().is_err());
+        assert!(simulator.play_double().is_err());
+        assert!(simulator.play_surrender().is_err());
+        assert_eq!(simulator.get_shoe_card_count().get_total(), shoe_total);
+        assert_eq!(simulator.current_game_phase, GamePhase::Play);
+    }
+
+    #[test]
+    fn double_bounds_of_current_group() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        simulator.current_game_phase = GamePhase::Play;
+        simulator.current_hand.set_original_bet(10);
+        for face_value in [5, 6] {
+            simulator.receive_card_for_me(Card {
+                face_value,
+                suit: Suit::Heart,
+            });
+        }
+        assert_eq!(simulator.double_bounds(), Some((1, 10)));
+
+        simulator.receive_card_for_me(Card {
+            face_value: 2,
+            suit: Suit::Heart,
+        });
+        assert_eq!(simulator.double_bounds(), None);
+        assert!(simulator.play_double_for_less(5).is_err());
+
+        // Nothing can be added to a group without a bet.
+        let mut simulator = Simulator::new(&rule);
+        simulator.current_game_phase = GamePhase::Play;
+        simulator.current_hand.set_original_bet(0);
+        for face_value in [5, 6] {
+            simulator.receive_card_for_me(Card {
+                face_value,
+                suit: Suit::Heart,
+            });
+        }
+        assert_eq!(simulator.double_bounds(), None);
+        assert!(simulator.play_double().is_err());
+    }
+
+    #[test]
+    fn double_for_less_adds_given_amount() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        simulator.current_game_phase = GamePhase::Play;
+        simulator.current_hand.set_original_bet(10);
+        for face_value in [5, 6] {
+            simulator.receive_card_for_me(Card {
+                face_value,
+                suit: Suit::Heart,
+            });
+        }
+        assert!(simulator.play_double_for_less(11).is_err());
+        assert_eq!(simulator.play_double_for_less(4), Ok(true));
+        assert_eq!(simulator.current_hand.get_bet(0), 14);
+        assert_eq!(simulator.double_bounds(), None);
+    }
+
+    #[test]
+    fn double_policy_is_honored_by_solver_and_simulator() {
+        let rule = Rule {
+            double_policy: crate::DoublePolicy::NineTenElevenOnly,
+            ..Default::default()
+        };
+        for (hand_face_values, can_double) in [([5, 3], false), ([6, 4], true)] {
+            let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+            for card in [hand_face_values[0], hand_face_values[1], 6] {
+                shoe.remove_card(card);
+            }
+            let initial_situation =
+                InitialSituation::new(shoe, (hand_face_values[0], hand_face_values[1]), 6);
+            let solution = crate::calculation::calculate_solution_with_initial_situation(
+                1,
+                &rule,
+                &initial_situation,
+            );
+
+            let mut simulator = Simulator::new(&rule);
+            simulator.current_game_phase = GamePhase::Play;
+            simulator.current_hand.set_original_bet(10);
+            for face_value in hand_face_values {
+                simulator.receive_card_for_me(Card {
+                    face_value,
+                    suit: Suit::Heart,
+                });
+            }
+            let hand = *simulator.get_my_current_card_count();
+            let decision = solution.decision_for(&hand, &rule, true, false, false);
+            assert_eq!(decision == Decision::Double, can_double);
+            assert_eq!(solution.ex_double == -f64::INFINITY, !can_double);
+            assert_eq!(simulator.double_bounds().is_some(), can_double);
+            assert_eq!(simulator.play_double().is_ok(), can_double);
+        }
+    }
+
+    #[test]
+    fn standing_on_hard_8_is_a_mistake() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        simulator.current_game_phase = GamePhase::Play;
+        let mut shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+        for face_value in [5, 3] {
+            simulator.receive_card_for_me(Card {
+                face_value,
+                suit: Suit::Heart,
+            });
+            shoe.remove_card(face_value);
+        }
+        shoe.remove_card(6);
+        let initial_situation = InitialSituation::new(shoe, (5, 3), 6);
+        let solution = crate::calculation::calculate_solution_with_initial_situation(
+            1,
+            &rule,
+            &initial_situation,
+        );
+
+        let feedback = simulator
+            .submit_decision(Decision::Stand, &solution)
+            .unwrap();
+        assert!(!feedback.is_correct);
+        assert_eq!(feedback.optimal_decision, Decision::Hit);
+        assert!(feedback.ex_loss > 0.0);
+        assert!(feedback.group_finished);
+        assert_eq!(simulator.current_game_phase, GamePhase::WaitForLeftPlayers);
+        assert!(simulator.submit_decision(Decision::Hit, &solution).is_err());
+    }
+
+    #[test]
+    fn insurance_is_only_offered_against_ace() {
+        let rule = Rule::default();
+        for (up_face_value, offered) in [(10, false), (1, true)] {
+            let mut simulator = Simulator::new(&rule);
+            simulator.current_hand.set_original_bet(10);
+            assert!(simulator.buy_insurance(5).is_err());
+            simulator.current_game_phase = GamePhase::DealerPeek;
+            for face_value in [up_face_value, 9] {
+                simulator.receive_card_for_dealer(Card {
+                    face_value,
+                    suit: Suit::Club,
+                });
+            }
+            assert_eq!(simulator.offer_insurance(), offered);
+            assert_eq!(simulator.buy_insurance(5).is_ok(), offered);
+        }
+
+        let mut simulator = Simulator::new(&rule);
+        simulator.current_hand.set_original_bet(10);
+        simulator.current_game_phase = GamePhase::DealerPeek;
+        for face_value in [1, 13] {
+            simulator.receive_card_for_dealer(Card {
+                face_value,
+                suit: Suit::Club,
+            });
+        }
+        assert!(simulator.buy_insurance(6).is_err());
+        simulator.buy_insurance(4).unwrap();
+        assert_eq!(simulator.dealer_peeks_if_necessary(false), Ok(true));
+        let result = simulator.dealer_plays_and_summary().unwrap();
+        assert_eq!((result.main_win, result.insurance_win), (0, 12));
+        assert_eq!(result.total_win(), 12);
+    }
+
+    #[test]
+    fn insurance_pays_stake_plus_winnings() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        simulator.current_hand.set_original_bet(100);
+        simulator.current_game_phase = GamePhase::DealerPeek;
+        for face_value in [1, 13] {
+            simulator.receive_card_for_dealer(Card {
+                face_value,
+                suit: Suit::Club,
+            });
+        }
+        simulator.buy_insurance(50).unwrap();
+        assert_eq!(simulator.dealer_peeks_if_necessary(false), Ok(true));
+        assert_eq!(simulator.insurance_bet, 50);
+        let result = simulator.dealer_plays_and_summary().unwrap();
+        assert_eq!(result.insurance_win, 150);
+        assert_eq!(result.total_win(), 150);
+    }
+
+    #[test]
+    fn reshuffle_is_likely_when_few_cards_remain() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        simulator.seat_player(1, 0).unwrap();
+        assert!(simulator.will_likely_reshuffle_after_round() < 0.01);
+        while simulator.shoe.cards_before_cut_card() > 2 {
+            simulator.shoe.deal_card();
+        }
+        assert!(simulator.will_likely_reshuffle_after_round() > 0.95);
+        while simulator.shoe.cards_before_cut_card() > 0 {
+            simulator.shoe.deal_card();
+        }
+        assert_eq!(simulator.will_likely_reshuffle_after_round(), 1.0);
+    }
+
+    #[test]
+    fn cards_per_round_follow_rule() {
+        let mut rule = Rule::default();
+        let (mean, variance) = dealer_like_cards_moments(&rule);
+        assert!(mean > 2.5 && mean < 3.5);
+        assert!(variance > 0.0);
+        assert_eq!(cards_per_round_moments(&rule, 3).0, 2.0 * mean + 4.0);
+        rule.other_players_mimic_dealer = true;
+        assert_eq!(cards_per_round_moments(&rule, 3).0, 4.0 * mean);
+
+        // Every two cards add up to at least 4, so the hand always stands on them.
+        rule.dealer_stand_value = 4;
+        assert_eq!(dealer_like_cards_moments(&rule), (2.0, 0.0));
+    }
+
+    #[test]
+    fn mimicking_other_players_deplete_shoe_faster() {
+        let mut rule = Rule::default();
+        let mut cards_dealt = [0, 0];
+        for (other_players_mimic_dealer, cards_dealt) in
+            [false, true].into_iter().zip(&mut cards_dealt)
+        {
+            rule.other_players_mimic_dealer = other_players_mimic_dealer;
+            let mut simulator = Simulator::with_seed(&rule, 7);
+            for _ in 0..10 {
+                let total_before = simulator.get_shoe_card_count().get_total();
+                simulator.seat_player(5, 2).unwrap();
+                simulator.place_bets(10).unwrap();
+                simulator.deal_initial_cards().unwrap();
+                if !simulator.dealer_peeks_if_necessary(false).unwrap() {
+                    simulator.wait_for_right_players().unwrap();
+                    simulator.stop_split().unwrap();
+                    simulator.play_stand().unwrap();
+                    simulator.wait_for_left_players().unwrap();
+                }
+                simulator.dealer_plays_and_summary().unwrap();
+                *cards_dealt += total_before - simulator.get_shoe_card_count().get_total();
+                simulator.start_new_shoe_if_necessary().unwrap();
+            }
+        }
+        assert!(cards_dealt[1] > cards_dealt[0]);
+    }
+
+    #[test]
+    fn bet_above_max_bet_is_rejected() {
+        let rule = Rule {
+            max_bet: 100,
+            ..Default::default()
+        };
+        let mut simulator = Simulator::new(&rule);
+        simulator.seat_player(1, 0).unwrap();
+        assert!(simulator.place_bets(102).is_err());
+        let bet = crate::strategy::edge_based_bet(0.2, 10, rule.get_max_bet());
+        assert_eq!(bet, 100);
+        assert!(simulator.place_bets(bet).is_ok());
+    }
+
+    #[test]
+    fn suited_blackjack_pays_promotional_rate() {
+        let rule = Rule {
+            suited_blackjack_payout: Some(2.0),
+            ..Default::default()
+        };
+        for (king_suit, gt_win) in [(Suit::Spade, 30), (Suit::Heart, 25)] {
+            let mut simulator = Simulator::new(&rule);
+            simulator.current_game_phase = GamePhase::DealerPlayAndSummary;
+            simulator.current_hand.set_original_bet(10);
+            for (face_value, suit) in [(1, Suit::Spade), (13, king_suit)] {
+                simulator.receive_card_for_me(Card { face_value, suit });
+            }
+            for face_value in [10, 7] {
+                simulator.receive_card_for_dealer(Card {
+                    face_value,
+                    suit: Suit::Club,
+                });
+            }
+            assert_eq!(
+                simulator
+                    .dealer_plays_and_summary()
+                    .map(|result| result.total_win()),
+                Ok(gt_win)
+            );
+        }
+    }
+
+    #[test]
+    fn player_natural_pushes_or_loses_against_dealer_natural() {
+        let mut rule = Rule::default();
+        for (setting, gt_win) in [(PushOrLose::Push, 10), (PushOrLose::Lose, 0)] {
+            rule.player_natural_vs_dealer_natural = setting;
+            let mut simulator = Simulator::new(&rule);
+            simulator.current_game_phase = GamePhase::DealerPlayAndSummary;
+            simulator.current_hand.set_original_bet(10);
+            for face_value in [1, 13] {
+                simulator.receive_card_for_me(Card {
+                    face_value,
+                    suit: Suit::Spade,
+                });
+                simulator.receive_card_for_dealer(Card {
+                    face_value,
+                    suit: Suit::Club,
+                });
+            }
+            assert_eq!(
+                simulator
+                    .dealer_plays_and_summary()
+                    .map(|result| result.total_win()),
+                Ok(gt_win)
+            );
+        }
+    }
+
+    #[test]
+    fn blackjack_beats_dealer_three_card_21() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        simulator.current_game_phase = GamePhase::DealerPlayAndSummary;
+        simulator.current_hand.set_original_bet(10);
+        for face_value in [1, 13] {
+            simulator.receive_card_for_me(Card {
+                face_value,
+                suit: Suit::Spade,
+            });
+        }
+        for face_value in [6, 5] {
+            simulator.receive_card_for_dealer(Card {
+                face_value,
+                suit: Suit::Club,
+            });
+        }
+
+        // The dealer draws a 10 and makes 21, which is not a natural.
+        simulator.force_next_card(10).unwrap();
+        let result = simulator.dealer_plays_and_summary().unwrap();
+        assert_eq!(simulator.get_dealer_card_count().get_total(), 3);
+        assert_eq!(simulator.get_dealer_card_count().get_actual_sum(), 21);
+        assert_eq!(result.per_group, vec![GroupResult { bet: 10, win: 25 }]);
+    }
+
+    #[test]
+    fn hole_card_is_known_from_deal_to_summary() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        // I get 10 and 8 against dealer 9 and 7.
+        simulator.shoe.shuffle_with_firsts(&[10, 9, 8, 7]);
+        simulator.seat_player(1, 0).unwrap();
+        simulator.place_bets(10).unwrap();
+        assert_eq!(simulator.dealer_hole_card(), None);
+        simulator.deal_initial_cards().unwrap();
+        let hole_card = simulator.dealer_hole_card().unwrap();
+        assert_eq!(hole_card.blackjack_value(), 7);
+
+        assert!(!simulator.dealer_peeks_if_necessary(false).unwrap());
+        simulator.wait_for_right_players().unwrap();
+        simulator.stop_split().unwrap();
+        assert_eq!(simulator.current_game_phase, GamePhase::Play);
+        assert_eq!(simulator.dealer_hole_card(), Some(hole_card));
+        simulator.play_stand().unwrap();
+        simulator.wait_for_left_players().unwrap();
+        simulator.dealer_plays_and_summary().unwrap();
+        assert_eq!(simulator.dealer_hand.get_cards(0)[1], hole_card);
+        assert_eq!(simulator.revealed_cards()[3], hole_card);
+    }
+
+    #[test]
+    fn scripted_hit_to_21_wins_against_dealer_20() {
+        use crate::strategy::{ScriptedStrategy, Strategy};
+
+        let rule = Rule::default();
+        let mut strategy = ScriptedStrategy::new(&[Decision::Hit, Decision::Hit]);
+        let mut simulator = Simulator::new(&rule);
+        // I get 5 and 6, then hit 4 and 6. The dealer gets 10 and 10.
+        simulator.shoe.shuffle_with_firsts(&[5, 10, 6, 10, 4, 6]);
+        simulator.seat_player(1, 0).unwrap();
+        simulator.place_bets(10).unwrap();
+        simulator.deal_initial_cards().unwrap();
+        assert!(!simulator.dealer_peeks_if_necessary(false).unwrap());
+        simulator.wait_for_right_players().unwrap();
+        simulator.stop_split().unwrap();
+        let mut decisions = Vec::new();
+        while simulator.current_game_phase == GamePhase::Play {
+            let hand = *simulator.get_my_current_card_count();
+            let decision = strategy.make_decision(&rule, &hand, 0, 0);
+            decisions.push(decision);
+            match decision {
+                Decision::Hit => simulator.play_hit(),
+                _ => simulator.play_stand(),
+            }
+            .unwrap();
+        }
+        // Reaching 21 ends the group without asking for a Stand.
+        assert_eq!(decisions, vec![Decision::Hit, Decision::Hit]);
+        assert_eq!(
+            simulator.current_hand.get_card_counts(0).get_actual_sum(),
+            21
+        );
+        simulator.wait_for_left_players().unwrap();
+
+        let result = simulator.dealer_plays_and_summary().unwrap();
+        assert_eq!(simulator.get_dealer_card_count().get_actual_sum(), 20);
+        assert_eq!(result.per_group, vec![GroupResult { bet: 10, win: 20 }]);
+    }
+
+    #[test]
+    fn scripted_strategy_is_not_asked_to_split_with_its_other_decisions() {
+        use crate::strategy::ScriptedStrategy;
+
+        let rule = Rule::default();
+        let mut strategy = ScriptedStrategy::new(&[Decision::Hit]);
+        let mut simulator = Simulator::new(&rule);
+        // I get a pair of 8 against dealer 10 and 7, and hit a 3 to 19 without splitting.
+        simulator.shoe.shuffle_with_firsts(&[8, 10, 8, 7, 3]);
+        let profit = simulator
+            .play_round_with_strategy(&mut strategy, |_| 10)
+            .unwrap();
+        assert_eq!(simulator.current_hand.get_number_of_groups(), 1);
+        assert_eq!(
+            simulator.current_hand.get_card_counts(0).get_actual_sum(),
+            19
+        );
+        assert_eq!(profit, 10);
+    }
+
+    #[test]
+    fn hand_states_of_surrendered_and_doubled_groups() {
+        use hand::HandState;
+
+        let rule = Rule {
+            allow_das: true,
+            allow_late_surrender: true,
+            allow_surrender_after_split: true,
+            ..Default::default()
+        };
+        let mut simulator = Simulator::new(&rule);
+        // I get a pair of 8 against dealer 10 and 7. After splitting, the first group gets 6 and
+        // the second group gets 3, then doubles with a 9.
+        simulator.shoe.shuffle_with_firsts(&[8, 10, 8, 7, 6, 3, 9]);
+        simulator.seat_player(1, 0).unwrap();
+        simulator.place_bets(10).unwrap();
+        simulator.deal_initial_cards().unwrap();
+        assert!(!simulator.dealer_peeks_if_necessary(false).unwrap());
+        simulator.wait_for_right_players().unwrap();
+        simulator.play_split(0).unwrap();
+        simulator.stop_split().unwrap();
+        assert_eq!(
+            simulator.hand_states(),
+            &[HandState::Normal, HandState::Normal]
+        );
+        simulator.play_surrender().unwrap();
+        simulator.play_double().unwrap();
+        simulator.wait_for_left_players().unwrap();
+        assert_eq!(
+            simulator.hand_states(),
+            &[HandState::Surrender, HandState::Double]
+        );
+
+        let result = simulator.dealer_plays_and_summary().unwrap();
+        assert_eq!(
+            result.per_group,
+            vec![
+                GroupResult { bet: 10, win: 5 },
+                GroupResult { bet: 20, win: 40 }
+            ]
+        );
+    }
+
+    #[test]
+    fn split_nines_are_both_playable() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        // I get a pair of 9 against dealer 10 and 7. After splitting, the groups get 2 and 3, and
+        // the first group hits a 4.
+        simulator.shoe.shuffle_with_firsts(&[9, 10, 9, 7, 2, 3, 4]);
+        simulator.seat_player(1, 0).unwrap();
+        simulator.place_bets(10).unwrap();
+        simulator.deal_initial_cards().unwrap();
+        assert!(!simulator.dealer_peeks_if_necessary(false).unwrap());
+        simulator.wait_for_right_players().unwrap();
+        simulator.play_split(0).unwrap();
+        simulator.stop_split().unwrap();
+        assert_eq!(simulator.current_game_phase, GamePhase::Play);
+        for group_index in 0..2 {
+            assert_eq!(simulator.current_hand.get_cards(group_index).len(), 2);
+        }
+
+        // Both groups are played, one after another.
+        assert!(!simulator.play_hit().unwrap());
+        simulator.play_stand().unwrap();
+        assert_eq!(simulator.current_game_phase, GamePhase::Play);
+        assert_eq!(simulator.current_playing_group_index, 1);
+        simulator.play_stand().unwrap();
+        assert_eq!(simulator.current_game_phase, GamePhase::WaitForLeftPlayers);
+    }
+
+    #[test]
+    fn unlimited_splits_stop_when_no_pair_is_left() {
+        let rule = Rule {
+            number_of_decks: 1,
+            split_all_limits: 255,
+            ..Default::default()
+        };
+        let mut simulator = Simulator::new(&rule);
+        // I get a pair of 8 against dealer 10 and 7. All the 4 eights of the deck come to me.
+        simulator
+            .shoe
+            .shuffle_with_firsts(&[8, 10, 8, 7, 8, 8, 2, 3, 4, 5]);
+        simulator.seat_player(1, 0).unwrap();
+        simulator.place_bets(10).unwrap();
+        simulator.deal_initial_cards().unwrap();
+        assert!(!simulator.dealer_peeks_if_necessary(false).unwrap());
+        simulator.wait_for_right_players().unwrap();
+        assert!(!simulator.play_split(0).unwrap());
+        assert!(!simulator.play_split(0).unwrap());
+        assert!(!simulator.play_split(1).unwrap());
+        assert_eq!(simulator.get_number_of_groups(), 4);
+        assert_eq!(simulator.get_current_split_all_times(), 3);
+
+        // No pair is left, so no more splits can be made although the limit is not reached.
+        for group_index in 0..4 {
+            assert!(simulator.play_split(group_index).is_err());
+        }
+        simulator.stop_split().unwrap();
+        assert_eq!(simulator.current_game_phase, GamePhase::Play);
+    }
+
+    #[test]
+    fn split_aces_receive_one_card_each() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        // I get a pair of Aces against dealer 10 and 7. After splitting, the groups get 5 and 6.
+        simulator.shoe.shuffle_with_firsts(&[1, 10, 1, 7, 5, 6]);
+        simulator.seat_player(1, 0).unwrap();
+        simulator.place_bets(10).unwrap();
+        simulator.deal_initial_cards().unwrap();
+        assert!(!simulator.dealer_peeks_if_necessary(false).unwrap());
+        simulator.wait_for_right_players().unwrap();
+        assert!(simulator.play_split(0).unwrap());
+        simulator.stop_split().unwrap();
+        assert_eq!(simulator.current_game_phase, GamePhase::WaitForLeftPlayers);
+        for group_index in 0..2 {
+            assert_eq!(simulator.current_hand.get_cards(group_index).len(), 2);
+        }
+        assert!(simulator.play_hit().is_err());
+    }
+
+    #[test]
+    fn split_reports_per_group_results() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        simulator.current_game_phase = GamePhase::DealerPlayAndSummary;
+        simulator.current_hand.set_original_bet(10);
+        for face_value in [8, 8] {
+            simulator.receive_card_for_me(Card {
+                face_value,
+                suit: Suit::Club,
+            });
+        }
+        simulator.current_hand.split_group(0);
+        // The first group gets 8 + A = 19 and the second group gets 8 + 7 = 15.
+        for (group_index, face_value) in [(0, 1), (1, 7)] {
+            simulator.current_hand.receive_card(
+                group_index,
+                Card {
+                    face_value,
+                    suit: Suit::Club,
+                },
+            );
+        }
+        for face_value in [10, 8] {
+            simulator.receive_card_for_dealer(Card {
+                face_value,
+                suit: Suit::Club,
+            });
+        }
+
+        let result = simulator.dealer_plays_and_summary().unwrap();
+        assert_eq!(
+            result.per_group,
+            vec![
+                GroupResult { bet: 10, win: 20 },
+                GroupResult { bet: 10, win: 0 }
+            ]
+        );
+        assert_eq!(result.main_win, 20);
+        assert_eq!(result.insurance_win, 0);
+        assert_eq!(result.total_win(), 20);
+    }
+
+    #[test]
+    fn surrender_after_split_follows_rule() {
+        let mut rule = Rule {
+            allow_late_surrender: true,
+            ..Default::default()
+        };
+        let get_split_simulator = |rule: &Rule| {
+            let mut simulator = Simulator::new(rule);
+            simulator.current_game_phase = GamePhase::Play;
+            simulator.current_hand.set_original_bet(10);
+            for face_value in [8, 8] {
+                simulator.receive_card_for_me(Card {
+                    face_value,
+                    suit: Suit::Club,
+                });
+            }
+            simulator.current_hand.split_group(0);
+            simulator.receive_card_for_me(Card {
+                face_value: 8,
+                suit: Suit::Heart,
+            });
+            simulator
+        };
+
+        let mut simulator = get_split_simulator(&rule);
+        assert!(simulator.play_surrender().is_err());
+        assert_eq!(simulator.current_hand.get_bet(0), 10);
+
+        rule.allow_surrender_after_split = true;
+        let mut simulator = get_split_simulator(&rule);
+        assert_eq!(simulator.play_surrender(), Ok(true));
+        assert_eq!(simulator.current_hand.get_bet(0), 5);
+    }
+
+    #[test]
+    fn current_group_bet_after_double() {
+        let rule = Rule {
+            allow_das: true,
+            ..Default::default()
+        };
+        let mut simulator = Simulator::new(&rule);
+        // I get a pair of 8 against dealer 10 and 7. After splitting, the first group gets 3 and
+        // doubles with a 9.
+        simulator.shoe.shuffle_with_firsts(&[8, 10, 8, 7, 3, 9, 10]);
+        simulator.seat_player(1, 0).unwrap();
+        simulator.place_bets(10).unwrap();
+        simulator.deal_initial_cards().unwrap();
+        assert!(!simulator.dealer_peeks_if_necessary(false).unwrap());
+        simulator.wait_for_right_players().unwrap();
+        assert_eq!(simulator.current_group_bet(), 10);
+        simulator.play_split(0).unwrap();
+        simulator.stop_split().unwrap();
+        assert_eq!(simulator.current_group_bet(), 10);
+
+        simulator.play_double().unwrap();
+        assert_eq!(simulator.current_hand.get_wager(0), 20);
+        // The second group is being played now.
+        assert_eq!(simulator.current_group_bet(), 10);
+        simulator.play_double().unwrap();
+        assert_eq!(simulator.current_group_bet(), 20);
+    }
+
+    #[test]
+    fn histogram_covers_all_samples() {
+        let histogram = Histogram::from_samples(&[-30, -10, 0, 5, 49], 4);
+        assert_eq!(histogram.start, -30);
+        assert_eq!(histogram.bin_width, 20);
+        assert_eq!(histogram.counts, vec![1, 3, 0, 1]);
+        assert!((histogram.mean - 2.8).abs() < 1e-12);
+    }
+
+    #[test]
+    fn higher_edge_shifts_session_outcomes_up() {
+        const SEED: u64 = 20240101;
+        let rule = Rule::default();
+        let mut strategy = crate::strategy::BasicStrategy::new(&rule);
+        let histogram =
+            session_outcome_distribution(&rule, &mut strategy, 100, |_| 100, 100, SEED).unwrap();
+        assert_eq!(histogram.counts.iter().sum::<u64>(), 100);
+
+        // Blackjack pays 3:1, which gives the player an edge of about 7%.
+        let generous_rule = Rule {
+            payout_blackjack: 3.0,
+            ..rule
+        };
+        let mut strategy = crate::strategy::BasicStrategy::new(&generous_rule);
+        let generous_histogram =
+            session_outcome_distribution(&generous_rule, &mut strategy, 100, |_| 100, 100, SEED)
+                .unwrap();
+        assert!(generous_histogram.mean > 0.0);
+        assert!(generous_histogram.mean > histogram.mean);
+    }
+
+    #[test]
+    fn forced_card_makes_dealer_21() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        // I get 10 and 9 against dealer 10 and 6.
+        simulator.shoe.shuffle_with_firsts(&[10, 10, 9, 6]);
+        simulator.seat_player(1, 0).unwrap();
+        simulator.place_bets(10).unwrap();
+        simulator.deal_initial_cards().unwrap();
+        assert!(!simulator.dealer_peeks_if_necessary(false).unwrap());
+        simulator.wait_for_right_players().unwrap();
+        simulator.stop_split().unwrap();
+        simulator.play_stand().unwrap();
+        simulator.wait_for_left_players().unwrap();
+
+        simulator.force_next_card(5).unwrap();
+        let result = simulator.dealer_plays_and_summary().unwrap();
+        assert_eq!(simulator.get_dealer_card_count().get_actual_sum(), 21);
+        assert_eq!(result.total_win(), 0);
+    }
+
+    #[test]
+    fn six_card_twenty_without_charlie() {
+        for (charlie_number, win) in [(6, 20), (0, 10)] {
+            let rule = Rule {
+                charlie_number,
+                ..Default::default()
+            };
+            let mut simulator = Simulator::new(&rule);
+            // I get 2 and 3 against dealer 10 and 10, and then hit 2, 3, 4 and 6 to 20.
+            simulator
+                .shoe
+                .shuffle_with_firsts(&[2, 10, 3, 10, 2, 3, 4, 6]);
+            simulator.seat_player(1, 0).unwrap();
+            simulator.place_bets(10).unwrap();
+            simulator.deal_initial_cards().unwrap();
+            assert!(!simulator.dealer_peeks_if_necessary(false).unwrap());
+            simulator.wait_for_right_players().unwrap();
+            simulator.stop_split().unwrap();
+            for _ in 0..3 {
+                assert!(!simulator.play_hit().unwrap());
+            }
+            let finished = simulator.play_hit().unwrap();
+            assert_eq!(finished, charlie_number != 0);
+            if !finished {
+                simulator.play_stand().unwrap();
+            }
+            simulator.wait_for_left_players().unwrap();
+            let result = simulator.dealer_plays_and_summary().unwrap();
+            assert_eq!(result.total_win(), win);
+        }
+    }
+
+    #[test]
+    fn insurance_without_peek() {
+        let mut rule = Rule {
+            peek_policy: PeekPolicy::NoPeek,
+            ..Default::default()
+        };
+        for offer_insurance_on_ace in [false, true] {
+            rule.offer_insurance_on_ace = offer_insurance_on_ace;
+            let mut simulator = Simulator::new(&rule);
+            // I get 10 and 9 against dealer Ace and 10.
+            simulator.shoe.shuffle_with_firsts(&[10, 1, 9, 10]);
+            simulator.seat_player(1, 0).unwrap();
+            simulator.place_bets(10).unwrap();
+            simulator.deal_initial_cards().unwrap();
+            assert_eq!(simulator.offer_insurance(), offer_insurance_on_ace);
+            if !offer_insurance_on_ace {
+                assert!(simulator.dealer_peeks_if_necessary(true).is_err());
+                continue;
+            }
+
+            // The dealer doesn't peek, so the round goes on.
+            assert!(!simulator.dealer_peeks_if_necessary(true).unwrap());
+            simulator.wait_for_right_players().unwrap();
+            simulator.stop_split().unwrap();
+            simulator.play_stand().unwrap();
+            simulator.wait_for_left_players().unwrap();
+            let result = simulator.dealer_plays_and_summary().unwrap();
+            assert_eq!(result.main_win, 0);
+            assert_eq!(result.insurance_win, 5 + 10);
+        }
+    }
+
+    #[test]
+    fn dealer_skips_drawing_when_all_players_bust() {
+        for dealer_draws_when_all_players_bust in [true, false] {
+            let rule = Rule {
+                dealer_draws_when_all_players_bust,
+                ..Default::default()
+            };
+            let mut simulator = Simulator::new(&rule);
+            // I get 10 and 6 against dealer 10 and 6, and bust with another 10.
+            simulator.shoe.shuffle_with_firsts(&[10, 10, 6, 6, 10]);
+            simulator.seat_player(1, 0).unwrap();
+            simulator.place_bets(10).unwrap();
+            simulator.deal_initial_cards().unwrap();
+            assert!(!simulator.dealer_peeks_if_necessary(false).unwrap());
+            simulator.wait_for_right_players().unwrap();
+            simulator.stop_split().unwrap();
+            assert!(simulator.play_hit().unwrap());
+            simulator.wait_for_left_players().unwrap();
+
+            let result = simulator.dealer_plays_and_summary().unwrap();
+            assert_eq!(result.total_win(), 0);
+            let dealer_cards = simulator.get_dealer_card_count().get_total();
+            assert_eq!(dealer_cards > 2, dealer_draws_when_all_players_bust);
+        }
+    }
+
+    /// A strategy that stands on everything and keeps a Hi-Lo running count of the observed cards.
+    #[derive(Default)]
+    struct CountingStrategy {
+        running_count: i32,
+    }
+
+    impl Strategy for CountingStrategy {
+        fn calculate_expectation_before_bet(&mut self, _: &Rule, _: &CardCount) -> f64 {
+            0.0
+        }
+
+        fn init_with_initial_situation(&mut self, _: &Rule, _: &InitialSituation) {}
+
+        fn should_buy_insurance(&mut self, _: &Rule, _: &InitialSituation) -> bool {
+            false
+        }
+
+        fn make_decision(&mut self, _: &Rule, _: &CardCount, _: u8, _: u8) -> Decision {
+            Decision::Stand
+        }
+
+        fn observe_cards(&mut self, cards: &[Card]) {
+            for card in cards {
+                self.running_count += match card.blackjack_value() {
+                    2..=6 => 1,
+                    7..=9 => 0,
+                    _ => -1,
+                };
+            }
+        }
+    }
+
+    #[test]
+    fn strategy_observes_all_revealed_cards() {
+        let rule = Rule::default();
+        let mut simulator = Simulator::new(&rule);
+        // I stand on 10 and 9, and the dealer draws to 5, 6 and 6.
+        simulator.shoe.shuffle_with_firsts(&[10, 5, 9, 6, 6]);
+        let mut strategy = CountingStrategy::default();
+        simulator
+            .play_round_with_strategy(&mut strategy, |_| 10)
+            .unwrap();
+        // My cards alone would give -1.
+        assert_eq!(strategy.running_count, 2);
+        assert_eq!(simulator.revealed_cards().len(), 5);
+    }
+
+    /// Plays like `BasicStrategy`, but always gives the same expectation before bet.
+    struct FixedExpectationStrategy {
+        basic_strategy: crate::strategy::BasicStrategy,
+        expectation: f64,
+    }
+
+    impl Strategy for FixedExpectationStrategy {
+        fn calculate_expectation_before_bet(&mut self, _: &Rule, _: &CardCount) -> f64 {
+            self.expectation
+        }
+
+        fn init_with_initial_situation(
+            &mut self,
+            rule: &Rule,
+            initial_situation: &InitialSituation,
+        ) {
+            self.basic_strategy
+                .init_with_initial_situation(rule, initial_situation);
+        }
+
+        fn should_buy_insurance(
+            &mut self,
+            rule: &Rule,
+            initial_situation: &InitialSituation,
+        ) -> bool {
+            self.basic_strategy
+                .should_buy_insurance(rule, initial_situation)
+        }
+
+        fn make_decision(
+            &mut self,
+            rule: &Rule,
+            current_hand: &CardCount,
+            current_split_all_times: u8,
+            current_split_ace_times: u8,
+        ) -> Decision {
+            self.basic_strategy.make_decision(
+                rule,
+                current_hand,
+                current_split_all_times,
+                current_split_ace_times,
+            )
+        }
+    }
+
+    #[test]
+    fn edge_based_bet_replays_flat_bet() {
+        const SEED: u64 = 20230501;
+        const ROUNDS: usize = 1000;
+        let rule = Rule::default();
+        let mut strategy = FixedExpectationStrategy {
+            basic_strategy: crate::strategy::BasicStrategy::new(&rule),
+            expectation: -0.01,
+        };
+        let flat_profit = Simulator::with_seed(&rule, SEED)
+            .automatic_simulate_with_fixed_main_bet(&mut strategy, 10, ROUNDS)
+            .unwrap();
+        assert_ne!(flat_profit, 0);
+
+        // Without an advantage, the minimum bet is placed in every round.
+        let edge_based_profit = Simulator::with_seed(&rule, SEED)
+            .automatic_simulate_with_edge_based_bet(&mut strategy, 10, 100, ROUNDS)
+            .unwrap();
+        assert_eq!(edge_based_profit, flat_profit);
+
+        // With an advantage of 1.1%, 3 times the minimum bet is placed in every round, and the
+        // same games are played.
+        strategy.expectation = 0.011;
+        let edge_based_profit = Simulator::with_seed(&rule, SEED)
+            .automatic_simulate_with_edge_based_bet(&mut strategy, 10, 100, ROUNDS)
+            .unwrap();
+        assert_eq!(edge_based_profit, 3 * flat_profit);
+    }
+
+    /// Plays a round with a bet of 100, and returns the profit.
+    fn play_a_round<T: crate::strategy::Strategy>(
+        rule: &Rule,
+        strategy: &mut T,
+        simulator: &mut Simulator,
+    ) -> i64 {
+        const BET: u32 = 100;
+        let mut wagered = BET;
+        simulator.seat_player(1, 0).unwrap();
+        strategy.calculate_expectation_before_bet(rule, simulator.get_shoe_card_count());
+        simulator.place_bets(BET).unwrap();
+        let initial_situation = simulator.deal_initial_cards().unwrap();
+        strategy.init_with_initial_situation(rule, &initial_situation);
+        let buy_insurance = strategy.should_buy_insurance(rule, &initial_situation);
+        if buy_insurance {
+            wagered += BET / 2;
+        }
+
+        if !simulator.dealer_peeks_if_necessary(buy_insurance).unwrap() {
+            simulator.wait_for_right_players().unwrap();
+            let hand = *simulator.get_my_current_card_count();
+            if strategy.should_split(rule, &hand, 0, 0) {
+                simulator.play_split(0).unwrap();
+                wagered += BET;
+            }
+            simulator.stop_split().unwrap();
+            while simulator.current_game_phase == GamePhase::Play {
+                let hand = *simulator.get_my_current_card_count();
+                let decision = strategy.make_decision(
+                    rule,
+                    &hand,
+                    simulator.get_current_split_all_times(),
+                    simulator.get_current_split_ace_times(),
+                );
+                match decision {
+                    Decision::Stand => simulator.play_stand(),
+                    Decision::Double if simulator.double_bounds().is_some() => {
+                        wagered += simulator
+                            .current_hand
+                            .get_bet(simulator.current_playing_group_index);
+                        simulator.play_double()
+                    }
+                    Decision::Surrender if hand.get_total() == 2 => simulator.play_surrender(),
+                    _ => simulator.play_hit(),
+                }
+                .unwrap();
+            }
+            simulator.wait_for_left_players().unwrap();
+        }
+
+        let win = simulator.dealer_plays_and_summary().unwrap().total_win();
+        simulator.start_new_shoe_if_necessary().unwrap();
+        win as i64 - wagered as i64
+    }
+
+    #[test]
+    fn same_seed_replays_same_rounds() {
+        const TOTAL_ROUNDS: usize = 1000;
+        let rule = Rule::default();
+        let mut strategy = crate::strategy::BasicStrategy::new(&rule);
+        let mut play_rounds = |seed: u64| {
+            let mut simulator = Simulator::with_seed(&rule, seed);
+            let mut results = Vec::with_capacity(TOTAL_ROUNDS);
+            for _ in 0..TOTAL_ROUNDS {
+                let profit = play_a_round(&rule, &mut strategy, &mut simulator);
+                // The next card tells where the shoe is, including after reshuffling.
+                let next_card = simulator.preview_next_few_cards_in_shoe(1).to_vec();
+                results.push((profit, next_card));
+            }
+            results
+        };
+
+        let results = play_rounds(20230501);
+        assert_eq!(play_rounds(20230501), results);
+        assert_ne!(play_rounds(20230502), results);
+    }
+
+    #[test]
+    fn test_strategy_on_new_shoe() {
+        const SEED: u64 = 20230501;
+        const TOTAL_SHOES: usize = 40;
+        // DpStrategySinglePlayer solves the whole shoe before each round, which is too slow for a
+        // test unless few cards are left. So each round is played on the last cards of a deck.
+        const CARDS_LEFT: usize = 14;
+        let rule = Rule {
+            number_of_decks: 1,
+            cut_card_proportion: 1.0,
+            dealer_hit_on_soft: true,
+            allow_das: true,
+            allow_late_surrender: true,
+            peek_policy: crate::PeekPolicy::UpAceOrTen,
+            ..Default::default()
+        };
+
+        // Both strategies play the same shoes. Their cards differ only after their decisions
+        // differ.
+        let mut basic_strategy = crate::strategy::BasicStrategy::new(&rule);
+        let mut basic_simulator = Simulator::with_seed(&rule, SEED);
+        let mut dp_strategy = crate::strategy::DpStrategySinglePlayer::new(1);
+        let mut dp_simulator = Simulator::with_seed(&rule, SEED);
+        let mut acc_basic = 0;
+        let mut acc_dp = 0;
+        for _ in 0..TOTAL_SHOES {
+            for simulator in [&mut basic_simulator, &mut dp_simulator] {
+                simulator.shoe.shuffle_with(&mut simulator.rng, 0);
+                for _ in 0..52 - CARDS_LEFT {
+                    simulator.shoe.deal_card();
+                }
+            }
+            assert_eq!(
+                basic_simulator.preview_next_few_cards_in_shoe(CARDS_LEFT),
+                dp_simulator.preview_next_few_cards_in_shoe(CARDS_LEFT)
+            );
+            acc_basic += play_a_round(&rule, &mut basic_strategy, &mut basic_simulator);
+            acc_dp += play_a_round(&rule, &mut dp_strategy, &mut dp_simulator);
+        }
+
+        // The games are replayed exactly, so no tolerance is needed.
+        assert!(acc_dp >= acc_basic, "{} vs {}", acc_dp, acc_basic);
+    }
+}
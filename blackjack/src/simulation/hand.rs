@@ -2,10 +2,26 @@ use crate::CardCount;
 
 use super::Card;
 
+/// How a group of cards is resolved by the player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandState {
+    /// The group is played with Stand and Hit only, or not played yet.
+    Normal,
+    Double,
+    Surrender,
+}
+
 /// Represents all hand cards. May contain more than 1 group of cards because of split.
 #[derive(Debug)]
 pub struct Hand {
     group_bet_pairs: Vec<GroupBetPair>,
+    hand_states: Vec<HandState>,
+}
+
+impl Default for Hand {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Hand {
@@ -13,10 +29,12 @@ impl Hand {
         let group_bet_pair = GroupBetPair {
             group: Group::new(),
             bet: 0,
+            wager: 0,
             win_already_determined: false,
         };
         Hand {
             group_bet_pairs: vec![group_bet_pair],
+            hand_states: vec![HandState::Normal],
         }
     }
 
@@ -33,13 +51,35 @@ impl Hand {
         self.group_bet_pairs.push(GroupBetPair {
             group: new_group,
             bet: self.group_bet_pairs[group_index].bet,
+            wager: self.group_bet_pairs[group_index].wager,
             win_already_determined: false,
         });
+        self.hand_states.push(HandState::Normal);
     }
 
     /// Doubles down the given group.
     pub fn double_down(&mut self, group_index: usize) {
         self.group_bet_pairs[group_index].bet *= 2;
+        self.group_bet_pairs[group_index].wager *= 2;
+        self.hand_states[group_index] = HandState::Double;
+    }
+
+    /// Doubles down the given group, but only adds the given amount to its bet.
+    pub fn double_down_for_less(&mut self, group_index: usize, amount: u32) {
+        self.group_bet_pairs[group_index].bet += amount;
+        self.group_bet_pairs[group_index].wager += amount;
+        self.hand_states[group_index] = HandState::Double;
+    }
+
+    /// Surrenders the given group, i.e., half of its bet is returned.
+    pub fn surrender(&mut self, group_index: usize) {
+        self.determine_winning(group_index, 0.5);
+        self.hand_states[group_index] = HandState::Surrender;
+    }
+
+    /// Returns how each group is resolved by the player.
+    pub fn hand_states(&self) -> &[HandState] {
+        &self.hand_states
     }
 
     pub fn get_number_of_groups(&self) -> usize {
@@ -50,8 +90,21 @@ impl Hand {
         self.group_bet_pairs[group_index].bet
     }
 
+    /// Returns the amount wagered on the given group, including the extra bet from double down.
+    /// Unlike `get_bet`, this is not affected by the winning already determined.
+    pub fn get_wager(&self, group_index: usize) -> u32 {
+        self.group_bet_pairs[group_index].wager
+    }
+
     pub fn set_original_bet(&mut self, bet: u32) {
         self.group_bet_pairs[0].bet = bet;
+        self.group_bet_pairs[0].wager = bet;
+    }
+
+    /// Returns the total amount wagered across all groups, including the extra bets from split and
+    /// double down. Unlike `get_bet`, this is not affected by the winning already determined.
+    pub fn total_bet(&self) -> u32 {
+        self.group_bet_pairs.iter().map(|pair| pair.wager).sum()
     }
 
     pub fn determine_winning(&mut self, group_index: usize, multiplier: f64) {
@@ -78,8 +131,11 @@ impl Hand {
         while self.group_bet_pairs.len() > 1 {
             self.group_bet_pairs.pop();
         }
+        self.hand_states.truncate(1);
+        self.hand_states[0] = HandState::Normal;
         self.group_bet_pairs[0].group.clear();
         self.group_bet_pairs[0].bet = 0;
+        self.group_bet_pairs[0].wager = 0;
         self.group_bet_pairs[0].win_already_determined = false;
     }
 }
@@ -118,7 +174,10 @@ impl Group {
 #[derive(Debug)]
 struct GroupBetPair {
     group: Group,
+    /// The bet of this group, or the winning money once it has been determined.
     bet: u32,
+    /// The amount wagered on this group.
+    wager: u32,
     /// Indicate whether the winning money of this group has already been determined. This happens
     /// when you bust, surrender or reach Charlie number.
     win_already_determined: bool,
@@ -164,4 +223,34 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn total_bet_after_split_and_double() {
+        let mut hand = Hand::new();
+        hand.set_original_bet(10);
+        hand.receive_card(
+            0,
+            Card {
+                face_value: 8,
+                suit: Suit::Diamond,
+            },
+        );
+        hand.receive_card(
+            0,
+            Card {
+                face_value: 8,
+                suit: Suit::Club,
+            },
+        );
+        hand.split_group(0);
+        hand.double_down(1);
+        assert_eq!(hand.total_bet(), 30);
+
+        hand.double_down(0);
+        hand.determine_winning(0, 0.0);
+        assert_eq!(hand.total_bet(), 40);
+
+        hand.clear();
+        assert_eq!(hand.total_bet(), 0);
+    }
 }
@@ -5,7 +5,14 @@ use super::{Card, Suit};
 use strum::IntoEnumIterator;
 
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
+
+/// Keeps a running count incrementally while cards are dealt by `Shoe::deal_card_counted`, so that
+/// strategies don't need to diff full shoes for each decision.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CountContext {
+    pub running: i32,
+}
 
 /// Represents a shoe in the real world.
 #[derive(Debug, Clone)]
@@ -40,7 +47,13 @@ impl Shoe {
     /// Returns the dealt cards back into the shoe, and shuffles. This method makes sure the given first few cards
     /// will be at the frontmost positions of the shoe. Panics if requirement cannot be met.
     /// Note that the cards are given in blackjack values (i.e., 1 stands for A. 10 stands for 10 and J, Q, K).
-    pub fn shuffle_with_firsts(&mut self, firsts: &Vec<u8>) {
+    pub fn shuffle_with_firsts(&mut self, firsts: &[u8]) {
+        self.shuffle_with_firsts_using(&mut thread_rng(), firsts);
+    }
+
+    /// Same as `shuffle_with_firsts`, but uses the given random number generator to shuffle the
+    /// cards after the given first few cards.
+    pub fn shuffle_with_firsts_using<R: Rng + ?Sized>(&mut self, rng: &mut R, firsts: &[u8]) {
         let mut counts = [self.number_of_decks; 52];
         self.current_index = 0;
         self.card_count = CardCount::with_number_of_decks(self.number_of_decks);
@@ -65,12 +78,18 @@ impl Shoe {
             }
         }
 
-        self.cards[firsts.len()..].shuffle(&mut thread_rng());
+        self.cards[firsts.len()..].shuffle(rng);
     }
 
     /// Returns the dealt cards back into the shoe and shuffles. Panics if start_index out of bound.
     pub fn shuffle(&mut self, start_index: usize) {
-        self.cards[start_index..].shuffle(&mut thread_rng());
+        self.shuffle_with(&mut thread_rng(), start_index);
+    }
+
+    /// Same as `shuffle`, but uses the given random number generator, e.g. a seeded one for
+    /// reproducible games.
+    pub fn shuffle_with<R: Rng + ?Sized>(&mut self, rng: &mut R, start_index: usize) {
+        self.cards[start_index..].shuffle(rng);
         self.current_index = 0;
         self.card_count = CardCount::with_number_of_decks(self.number_of_decks);
     }
@@ -81,6 +100,19 @@ impl Shoe {
         self.card_count = CardCount::with_number_of_decks(self.number_of_decks);
     }
 
+    /// Arranges the shoe so that the next dealt card has the given blackjack value, by swapping the
+    /// first remaining card of this value to the front. The remaining cards stay the same, except
+    /// for their order. Returns an error if no remaining card has this value.
+    pub fn force_next_card(&mut self, blackjack_value: u8) -> Result<(), String> {
+        let start = self.current_index.min(self.cards.len());
+        let offset = self.cards[start..]
+            .iter()
+            .position(|card| card.blackjack_value() == blackjack_value)
+            .ok_or(format!("No card of value {} left in shoe", blackjack_value))?;
+        self.cards.swap(start, start + offset);
+        Ok(())
+    }
+
     /// Deals a card if the shoe is not empty. Returns None if empty.
     pub fn deal_card(&mut self) -> Option<Card> {
         self.current_index += 1;
@@ -93,6 +125,20 @@ impl Shoe {
         }
     }
 
+    /// Same as `deal_card`, but also adds the tag of the dealt card to the running count of the
+    /// given context. The tags are given by blackjack values, i.e., `tags[0]` is for Ace and
+    /// `tags[9]` is for 10, J, Q and K.
+    pub fn deal_card_counted(&mut self, ctx: &mut CountContext, tags: &[i32; 10]) -> Option<Card> {
+        let card = self.deal_card()?;
+        ctx.running += tags[(card.blackjack_value() - 1) as usize];
+        Some(card)
+    }
+
+    /// Returns the number of cards that can be dealt before the cut card is reached.
+    pub fn cards_before_cut_card(&self) -> usize {
+        self.cut_card_index.saturating_sub(self.current_index)
+    }
+
     /// Checks if the cut card has been reached.
     pub fn reached_cut_card(&self) -> bool {
         self.current_index >= self.cut_card_index
@@ -106,6 +152,16 @@ impl Shoe {
         let rear = std::cmp::min(self.current_index + number, self.cards.len());
         &self.cards[self.current_index..rear]
     }
+
+    /// Returns all the cards not dealt yet, in the order they will be dealt.
+    pub fn remaining_cards(&self) -> &[Card] {
+        &self.cards[self.current_index.min(self.cards.len())..]
+    }
+
+    /// Returns the cards dealt since the shoe was shuffled, in the order they were dealt.
+    pub fn dealt_cards(&self) -> &[Card] {
+        &self.cards[..self.current_index.min(self.cards.len())]
+    }
 }
 
 fn find_suitable_card(counts: &[u8; 52], blackjack_value: u8) -> Result<u8, ()> {
@@ -137,18 +193,13 @@ mod tests {
     use super::*;
 
     fn number_of_cards_is_correct(shoe: &Shoe) -> bool {
-        let mut counts = [0 as u8; 52];
+        let mut counts = [0_u8; 52];
         for card in &shoe.cards {
             let card_integer: u8 = (*card).into();
             counts[card_integer as usize] += 1;
         }
 
-        for i in 0..52 {
-            if counts[i] != shoe.number_of_decks as u8 {
-                return false;
-            }
-        }
-        true
+        counts.iter().all(|&count| count == shoe.number_of_decks)
     }
 
     #[test]
@@ -170,6 +221,62 @@ mod tests {
         }
     }
 
+    #[test]
+    fn counted_dealing_matches_shoe_diff() {
+        const HI_LO_TAGS: [i32; 10] = [-1, 1, 1, 1, 1, 1, 0, 0, 0, -1];
+        let number_of_decks = 6;
+        let mut shoe = Shoe::new(number_of_decks, 0.5);
+        shoe.shuffle(0);
+        let mut ctx = CountContext::default();
+        for _ in 0..200 {
+            shoe.deal_card_counted(&mut ctx, &HI_LO_TAGS).unwrap();
+        }
+
+        let full_shoe = CardCount::with_number_of_decks(number_of_decks);
+        let running: i32 = (1..=10)
+            .map(|card| {
+                let dealt = (full_shoe[card] - shoe.get_card_count()[card]) as i32;
+                dealt * HI_LO_TAGS[(card - 1) as usize]
+            })
+            .sum();
+        assert_eq!(ctx.running, running);
+    }
+
+    #[test]
+    fn remaining_cards_exclude_dealt_ones() {
+        let mut shoe = Shoe::new(1, 0.5);
+        shoe.shuffle(0);
+        let dealt: Vec<Card> = (0..5).map(|_| shoe.deal_card().unwrap()).collect();
+        let remaining = shoe.remaining_cards();
+        assert_eq!(remaining.len(), 52 - 5);
+        assert!(dealt.iter().all(|card| !remaining.contains(card)));
+        assert_eq!(remaining, shoe.preview_next_few_cards(52));
+
+        while shoe.deal_card().is_some() {}
+        assert!(shoe.remaining_cards().is_empty());
+    }
+
+    #[test]
+    fn forcing_next_card_keeps_remaining_cards() {
+        let mut shoe = Shoe::new(1, 0.5);
+        shoe.shuffle(0);
+        shoe.deal_card();
+        let mut remaining_cards = shoe.remaining_cards().to_vec();
+        shoe.force_next_card(7).unwrap();
+        let mut forced_remaining_cards = shoe.remaining_cards().to_vec();
+        let key = |card: &Card| u8::from(*card);
+        remaining_cards.sort_by_key(key);
+        forced_remaining_cards.sort_by_key(key);
+        assert_eq!(forced_remaining_cards, remaining_cards);
+        assert_eq!(shoe.deal_card().unwrap().blackjack_value(), 7);
+
+        while shoe.get_card_count()[1] > 0 {
+            shoe.force_next_card(1).unwrap();
+            shoe.deal_card();
+        }
+        assert!(shoe.force_next_card(1).is_err());
+    }
+
     #[test]
     fn test_shuffle_with_firsts() {
         let number_of_decks = 1;
@@ -189,6 +296,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn shuffling_with_deterministic_rng_is_reproducible() {
+        use rand::rngs::mock::StepRng;
+
+        let mut shoes = [Shoe::new(2, 0.5), Shoe::new(2, 0.5)];
+        for shoe in &mut shoes {
+            shoe.shuffle_with(&mut StepRng::new(7, 11), 0);
+            assert!(number_of_cards_is_correct(shoe));
+        }
+        assert_eq!(shoes[0].cards, shoes[1].cards);
+        assert_ne!(shoes[0].cards, Shoe::new(2, 0.5).cards);
+
+        for shoe in &mut shoes {
+            shoe.shuffle_with_firsts_using(&mut StepRng::new(3, 5), &[1, 10]);
+            assert!(number_of_cards_is_correct(shoe));
+        }
+        assert_eq!(shoes[0].cards, shoes[1].cards);
+        assert_eq!(shoes[0].cards[0].blackjack_value(), 1);
+        assert_eq!(shoes[0].cards[1].blackjack_value(), 10);
+    }
+
     #[test]
     #[should_panic]
     fn invalid_firsts_should_panic() {
@@ -222,7 +350,7 @@ mod tests {
     fn card_count_is_correctly_synced() {
         let number_of_decks = 2;
         let mut shoe = Shoe::new(number_of_decks, 0.3333333);
-        shoe.shuffle_with_firsts(&vec![1, 4, 4, 10]);
+        shoe.shuffle_with_firsts(&[1, 4, 4, 10]);
         _ = shoe.deal_card();
         assert_eq!(shoe.card_count[1], 7);
         _ = shoe.deal_card();
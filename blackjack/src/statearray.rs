@@ -21,37 +21,62 @@ const fn get_powers_of_base() -> [u64; 10] {
 
 /// This struct provide a convenient way to use CardCount as the index of the
 /// array.
+///
+/// States are keyed by their hash values only, so two different states with the same hash value
+/// would share an entry. In debug builds, the counts of each state are stored as well, and
+/// accessing an entry with a different state panics.
 #[derive(Debug, Default, Clone)]
 pub struct StateArray<T: Copy + Default> {
     data: HashMap<u64, T>,
+    #[cfg(debug_assertions)]
+    counts: HashMap<u64, [u16; 10]>,
 }
 
 impl<T: Copy + Default> StateArray<T> {
     pub fn new() -> StateArray<T> {
         StateArray {
             data: HashMap::new(),
+            #[cfg(debug_assertions)]
+            counts: HashMap::new(),
         }
     }
 
     pub fn contains_state(&self, index: &CardCount) -> bool {
-        self.data.contains_key(&index.hash_value)
+        let contains = self.data.contains_key(&index.hash_value);
+        if contains {
+            self.check_collision(index);
+        }
+        contains
+    }
+
+    #[cfg(debug_assertions)]
+    fn check_collision(&self, index: &CardCount) {
+        if let Some(counts) = self.counts.get(&index.hash_value) {
+            assert_eq!(
+                counts, &index.counts,
+                "Hash collision between two different states"
+            );
+        }
     }
+
+    #[cfg(not(debug_assertions))]
+    fn check_collision(&self, _: &CardCount) {}
 }
 
 impl<T: Copy + Default> Index<&CardCount> for StateArray<T> {
     type Output = T;
     fn index(&self, index: &CardCount) -> &Self::Output {
+        self.check_collision(index);
         &self.data[&index.hash_value]
     }
 }
 
 impl<T: Copy + Default> IndexMut<&CardCount> for StateArray<T> {
     fn index_mut(&mut self, index: &CardCount) -> &mut Self::Output {
-        if !self.data.contains_key(&index.hash_value) {
-            self.data.insert(index.hash_value, Default::default());
-        }
-
-        self.data.get_mut(&index.hash_value).unwrap()
+        #[cfg(debug_assertions)]
+        self.counts.entry(index.hash_value).or_insert(index.counts);
+        self.check_collision(index);
+        self.data.entry(index.hash_value).or_default()
     }
 }
 
@@ -85,6 +110,31 @@ impl CardCount {
         Self::new(&counts)
     }
 
+    /// Starts from a shoe of `number_of_decks` standard decks and applies the given adjustments,
+    /// each of which is a card value and the number of cards of that value to add (or to remove,
+    /// if negative). This can be used for promotional games that add or remove specific ranks.
+    pub fn custom(number_of_decks: u8, adjustments: &[(u8, i16)]) -> Result<CardCount, String> {
+        let mut counts = Self::with_number_of_decks(number_of_decks)
+            .counts
+            .map(|count| count as i32);
+        for &(card_value, delta) in adjustments {
+            if !(1..=10).contains(&card_value) {
+                return Err(format!("Invalid card value {}", card_value));
+            }
+            counts[(card_value - 1) as usize] += delta as i32;
+        }
+        if let Some(index) = counts.iter().position(|&count| count < 0) {
+            return Err(format!(
+                "The number of card value {} cannot be negative",
+                index + 1
+            ));
+        }
+        if counts.iter().any(|&count| count > u16::MAX as i32) {
+            return Err(String::from("Too many cards in the shoe"));
+        }
+        Ok(Self::new(&counts.map(|count| count as u16)))
+    }
+
     /// Add a card of given card value.
     ///
     /// Note that this method won't check if the card value is valid.
@@ -137,13 +187,60 @@ impl CardCount {
         }
     }
 
+    /// Returns the highest total that doesn't bust, where one Ace counts as 11 if possible, or None
+    /// if the hand busts.
+    pub fn best_total(&self) -> Option<u16> {
+        if self.bust() {
+            None
+        } else {
+            Some(self.get_actual_sum())
+        }
+    }
+
+    /// Returns the total where all Aces count as 1.
+    pub fn hard_total(&self) -> u16 {
+        self.sum
+    }
+
+    /// Returns whether the hand can stand on the given soft total, i.e., the total where one Ace
+    /// counts as 11 without busting.
+    pub fn can_be_soft_total(&self, target: u16) -> bool {
+        self.is_soft() && target <= 21 && self.sum + 10 == target
+    }
+
+    /// Returns the number of low cards, i.e., cards from 2 to 6.
+    pub fn low_cards(&self) -> u16 {
+        self.counts[1..6].iter().sum()
+    }
+
+    /// Returns the number of neutral cards, i.e., cards from 7 to 9.
+    pub fn neutral_cards(&self) -> u16 {
+        self.counts[6..9].iter().sum()
+    }
+
+    /// Returns the number of high cards, i.e., Aces and cards of value 10.
+    pub fn high_cards(&self) -> u16 {
+        self.counts[0] + self.counts[9]
+    }
+
+    /// Returns whether the running count of the cards dealt from `full_shoe` (the shoe being
+    /// `self` now) has reached the given pivot, where `tags` are the tags of the counting system
+    /// from Ace to 10. This is for unbalanced counts like KO, whose running count starts from 0
+    /// here, so any initial running count should be subtracted from the pivot.
+    pub fn pivot_reached(&self, full_shoe: &CardCount, tags: &[i32; 10], pivot: i32) -> bool {
+        let running_count: i32 = (0..10)
+            .map(|i| tags[i] * (full_shoe.counts[i] as i32 - self.counts[i] as i32))
+            .sum();
+        running_count >= pivot
+    }
+
     fn propagate_counts(&mut self) {
         self.hash_value = 0;
         self.sum = 0;
-        for i in 0..self.counts.len() {
-            self.hash_value += (self.counts[i] as u64) * POW_BASE[i];
-            self.sum += ((i + 1) as u16) * self.counts[i];
-            self.total += self.counts[i] as u16;
+        for (i, &count) in self.counts.iter().enumerate() {
+            self.hash_value += (count as u64) * POW_BASE[i];
+            self.sum += ((i + 1) as u16) * count;
+            self.total += count;
         }
         self.hash_value %= MOD;
     }
@@ -180,8 +277,8 @@ mod tests {
     fn generate_random_counts(number_of_decks: u8) -> [u16; 10] {
         let mut rng = rand::thread_rng();
         let mut counts: [u16; 10] = [0; 10];
-        for i in 0..9 {
-            counts[i] = rng.gen_range(0..=(number_of_decks as u16) * 4);
+        for count in counts.iter_mut().take(9) {
+            *count = rng.gen_range(0..=(number_of_decks as u16) * 4);
         }
         counts[9] = rng.gen_range(0..=(number_of_decks as u16) * 16);
 
@@ -227,6 +324,142 @@ mod tests {
         }
     }
 
+    #[test]
+    fn random_states_do_not_collide() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut state_array = StateArray::new();
+        let mut states = Vec::new();
+        for i in 0..1000 {
+            let mut counts = [0; 10];
+            for count in counts.iter_mut() {
+                *count = rng.gen_range(0..=32);
+            }
+            let state = CardCount::new(&counts);
+            state_array[&state] = i;
+            states.push((state, i));
+        }
+        for (state, i) in &states {
+            assert!(state_array.contains_state(state));
+            assert_eq!(state_array[state], *i);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Hash collision")]
+    fn hash_collision_is_detected() {
+        // 211 Aces and a single 2 have the same hash value.
+        let mut counts = [0; 10];
+        counts[0] = BASE as u16;
+        let aces = CardCount::new(&counts);
+        let mut counts = [0; 10];
+        counts[1] = 1;
+        let two = CardCount::new(&counts);
+        assert_eq!(aces.hash_value, two.hash_value);
+
+        let mut state_array = StateArray::new();
+        state_array[&aces] = 1;
+        let _ = state_array[&two];
+    }
+
+    #[test]
+    fn custom_shoe_with_two_fewer_fives() {
+        let shoe = CardCount::custom(6, &[(5, -2)]).unwrap();
+        let mut expected = CardCount::with_number_of_decks(6);
+        expected.remove_card(5);
+        expected.remove_card(5);
+        assert_eq!(shoe[5], 22);
+        assert_eq!(shoe.get_total(), 6 * 52 - 2);
+        assert_eq!(shoe.get_sum(), expected.get_sum());
+        assert_eq!(shoe.hash_value, expected.hash_value);
+        for card in (1..=10).filter(|&card| card != 5) {
+            assert_eq!(shoe[card], expected[card]);
+        }
+
+        assert!(CardCount::custom(1, &[(5, -5)]).is_err());
+        assert!(CardCount::custom(1, &[(11, 1)]).is_err());
+    }
+
+    #[test]
+    fn actual_sum_with_multiple_aces() {
+        // At most one Ace can count as 11.
+        let mut card_count = CardCount::with_number_of_decks(0);
+        card_count.add_card(1);
+        card_count.add_card(1);
+        assert_eq!(card_count.get_actual_sum(), 12);
+        card_count.add_card(1);
+        card_count.add_card(1);
+        assert_eq!(card_count.get_actual_sum(), 14);
+        card_count.add_card(7);
+        assert_eq!(card_count.get_actual_sum(), 21);
+        card_count.add_card(10);
+        assert_eq!(card_count.get_actual_sum(), 21);
+        assert!(!card_count.bust());
+    }
+
+    #[test]
+    fn best_totals() {
+        let best_total = |cards: &[u8]| {
+            let mut card_count = CardCount::with_number_of_decks(0);
+            for &card in cards {
+                card_count.add_card(card);
+            }
+            card_count.best_total()
+        };
+        assert_eq!(best_total(&[1, 6, 10]), Some(17));
+        assert_eq!(best_total(&[10, 10, 5]), None);
+        assert_eq!(best_total(&[1, 10]), Some(21));
+    }
+
+    #[test]
+    fn soft_and_hard_totals() {
+        let mut card_count = CardCount::with_number_of_decks(0);
+        card_count.add_card(1);
+        card_count.add_card(7);
+        assert!(card_count.can_be_soft_total(18));
+        assert_eq!(card_count.hard_total(), 8);
+
+        let mut card_count = CardCount::with_number_of_decks(0);
+        card_count.add_card(10);
+        card_count.add_card(7);
+        assert!(!card_count.can_be_soft_total(17));
+        assert!(!card_count.can_be_soft_total(27));
+        assert_eq!(card_count.hard_total(), 17);
+
+        // Soft 22 is not a soft total.
+        let mut card_count = CardCount::with_number_of_decks(0);
+        card_count.add_card(1);
+        card_count.add_card(1);
+        card_count.add_card(10);
+        assert!(!card_count.can_be_soft_total(22));
+        assert_eq!(card_count.hard_total(), 12);
+    }
+
+    #[test]
+    fn low_neutral_and_high_cards() {
+        let card_count = CardCount::with_number_of_decks(1);
+        assert_eq!(card_count.low_cards(), 20);
+        assert_eq!(card_count.neutral_cards(), 12);
+        assert_eq!(card_count.high_cards(), 20);
+    }
+
+    #[test]
+    fn pivot_reached_after_low_cards() {
+        const KO_TAGS: [i32; 10] = [-1, 1, 1, 1, 1, 1, 1, 0, 0, -1];
+        let full_shoe = CardCount::with_number_of_decks(1);
+        let mut shoe = full_shoe;
+        assert!(!shoe.pivot_reached(&full_shoe, &KO_TAGS, 4));
+        for card in [2, 3, 4] {
+            shoe.remove_card(card);
+        }
+        assert!(!shoe.pivot_reached(&full_shoe, &KO_TAGS, 4));
+        shoe.remove_card(7);
+        assert!(shoe.pivot_reached(&full_shoe, &KO_TAGS, 4));
+        shoe.remove_card(10);
+        assert!(!shoe.pivot_reached(&full_shoe, &KO_TAGS, 4));
+    }
+
     #[test]
     fn test_state_array() {
         for _turn in 0..10 {
@@ -1,10 +1,12 @@
 use crate::{
     calculation::{
-        calculate_solution_without_initial_situation, get_max_expectation, SolutionForBettingPhase,
+        calculate_solution_without_initial_situation, SolutionForBettingPhase,
         SolutionForInitialSituation,
     },
+    simulation::Card,
     CardCount, Decision, InitialSituation, Rule,
 };
+use std::collections::VecDeque;
 
 pub trait Strategy {
     fn calculate_expectation_before_bet(&mut self, rule: &Rule, shoe: &CardCount) -> f64;
@@ -17,6 +19,45 @@ pub trait Strategy {
         current_split_all_times: u8,
         current_split_ace_times: u8,
     ) -> Decision;
+
+    /// Returns whether to split the current pair before playing it. Asks `make_decision` by
+    /// default.
+    fn should_split(
+        &mut self,
+        rule: &Rule,
+        current_hand: &CardCount,
+        current_split_all_times: u8,
+        current_split_ace_times: u8,
+    ) -> bool {
+        self.make_decision(
+            rule,
+            current_hand,
+            current_split_all_times,
+            current_split_ace_times,
+        ) == Decision::Split
+    }
+
+    /// Observes all the cards revealed in a round, including the cards of the other players and
+    /// the dealer, e.g., to keep a running count. Does nothing by default.
+    fn observe_cards(&mut self, _cards: &[Card]) {}
+}
+
+/// The player advantage needed for each extra unit of bet.
+const ADVANTAGE_PER_BET_UNIT: f64 = 0.005;
+
+/// Returns the bet to place given the expectation before bet (e.g. from
+/// `Strategy::calculate_expectation_before_bet`). Bets `min_bet` when the expectation is not
+/// positive, and adds one more `min_bet` for every 0.5% of advantage, up to `max_bet` (usually
+/// `Rule::get_max_bet`).
+///
+/// The bet is always a multiple of `min_bet` unless capped, so it meets the same requirements of
+/// `Simulator::place_bets` as `min_bet` does.
+pub fn edge_based_bet(expectation: f64, min_bet: u32, max_bet: u32) -> u32 {
+    if expectation <= 0.0 {
+        return min_bet;
+    }
+    let units = 1 + (expectation / ADVANTAGE_PER_BET_UNIT) as u32;
+    units.saturating_mul(min_bet).min(max_bet)
 }
 
 #[derive(Debug, Default)]
@@ -24,6 +65,10 @@ pub struct DpStrategySinglePlayer {
     solution_large: SolutionForBettingPhase,
     solution_small: SolutionForInitialSituation,
     number_of_threads: usize,
+
+    /// The dealer up card whose expectations of Stand and Hit are moved from `solution_large` to
+    /// `solution_small`, or 0 if none.
+    dealer_up_card: u8,
 }
 
 impl DpStrategySinglePlayer {
@@ -50,18 +95,26 @@ impl Strategy for DpStrategySinglePlayer {
     fn calculate_expectation_before_bet(&mut self, rule: &Rule, shoe: &CardCount) -> f64 {
         self.solution_large =
             calculate_solution_without_initial_situation(self.number_of_threads, rule, shoe);
+        self.dealer_up_card = 0;
         self.solution_large.get_total_expectation()
     }
 
     fn init_with_initial_situation(&mut self, _: &Rule, initial_situation: &InitialSituation) {
-        let solution_large = std::mem::take(&mut self.solution_large);
-        self.solution_small = solution_large.into_solution_for_initial_situation(
+        // Move the expectations back and forth instead of cloning them, so that other initial
+        // situations of the same shoe can still be played without solving again.
+        if self.dealer_up_card != 0 {
+            let solution_small = std::mem::take(&mut self.solution_small);
+            self.solution_large
+                .return_solution_for_initial_situation(solution_small, self.dealer_up_card);
+        }
+        self.dealer_up_card = initial_situation.dealer_up_card;
+        self.solution_small = self.solution_large.take_solution_for_initial_situation(
             initial_situation.hand_cards,
             initial_situation.dealer_up_card,
         );
     }
 
-    fn should_buy_insurance(&mut self, rule: &Rule, _: &InitialSituation) -> bool {
+    fn should_buy_insurance(&mut self, _rule: &Rule, _: &InitialSituation) -> bool {
         self.solution_small.ex_extra_insurance > 0.0
     }
 
@@ -70,21 +123,13 @@ impl Strategy for DpStrategySinglePlayer {
         rule: &Rule,
         current_hand: &CardCount,
         current_split_all_times: u8,
-        current_split_ace_times: u8,
+        _current_split_ace_times: u8,
     ) -> Decision {
-        let (mut mx_ex, mut decision) =
-            get_max_expectation(&self.solution_small.ex_stand_hit, current_hand, rule);
-        if current_hand.get_total() == 2 {
-            if mx_ex < self.solution_small.ex_double {
-                mx_ex = self.solution_small.ex_double;
-                decision = Decision::Double;
-            }
-            if mx_ex < self.solution_small.ex_split {
-                mx_ex = self.solution_small.ex_split;
-                decision = Decision::Split;
-            }
-        }
-        decision
+        let is_initial_hand = current_hand.get_total() == 2;
+        // Only the initial pair is solved, so the groups after splitting are not split again.
+        let can_split = is_initial_hand && current_split_all_times == 0;
+        self.solution_small
+            .decision_for(current_hand, rule, is_initial_hand, can_split, true)
     }
 }
 
@@ -96,7 +141,7 @@ pub struct BasicStrategy {
 }
 
 impl BasicStrategy {
-    pub fn new(_rule: &Rule) -> BasicStrategy {
+    pub fn new(rule: &Rule) -> BasicStrategy {
         // TODO: Improve this by calculating, instead of hard-coding.
 
         let mut strategy = BasicStrategy {
@@ -115,6 +160,8 @@ impl BasicStrategy {
         const RH: (Decision, Decision) = (Decision::Surrender, Decision::Hit);
         const RS: (Decision, Decision) = (Decision::Surrender, Decision::Stand);
         const RP: (Decision, Decision) = (Decision::Surrender, Decision::Split);
+        // Soft 18 doubles against 2 only if the dealer hits soft 17.
+        let ds_if_h17 = if rule.dealer_hit_on_soft { DS } else { S };
 
         strategy.hard_charts = [
             [H, H, H, H, H, H, H, H, H, H], // 5
@@ -138,7 +185,7 @@ impl BasicStrategy {
             [H, H, H, DH, DH, DH, H, H, H, H],
             [H, H, H, DH, DH, DH, H, H, H, H],
             [H, H, DH, DH, DH, DH, H, H, H, H],
-            [H, DS, DS, DS, DS, DS, S, S, H, H],
+            [H, ds_if_h17, DS, DS, DS, DS, S, S, H, H], // Ace + 7
             [S, S, S, S, S, DS, S, S, S, S],
             [S, S, S, S, S, S, S, S, S, S], // Ace + 9
             [S, S, S, S, S, S, S, S, S, S], // Ace + 10
@@ -169,7 +216,11 @@ impl Strategy for BasicStrategy {
         self.dealer_up_card = initial_situation.dealer_up_card;
     }
 
-    fn should_buy_insurance(&mut self, rule: &Rule, initial_situation: &InitialSituation) -> bool {
+    fn should_buy_insurance(
+        &mut self,
+        _rule: &Rule,
+        _initial_situation: &InitialSituation,
+    ) -> bool {
         false
     }
 
@@ -178,7 +229,7 @@ impl Strategy for BasicStrategy {
         rule: &Rule,
         current_hand: &CardCount,
         current_split_all_times: u8,
-        current_split_ace_times: u8,
+        _current_split_ace_times: u8,
     ) -> Decision {
         let col = (self.dealer_up_card - 1) as usize;
 
@@ -187,7 +238,7 @@ impl Strategy for BasicStrategy {
                 && current_hand[(current_hand.get_sum() / 2) as u8] == 2
             {
                 // Pair
-                let row = (current_hand.get_sum() / 2) as usize;
+                let row = (current_hand.get_sum() / 2 - 1) as usize;
                 self.pair_charts[row][col]
             } else if current_hand.is_soft() && current_hand.get_sum() + 10 <= 21 {
                 // Soft hand
@@ -215,14 +266,18 @@ impl Strategy for BasicStrategy {
 
         match decision.0 {
             Decision::Double => {
-                if current_split_all_times == 0 || rule.allow_das {
+                if (current_split_all_times == 0 || rule.allow_das)
+                    && rule.double_policy.allows(current_hand)
+                {
                     Decision::Double
                 } else {
                     decision.1
                 }
             }
             Decision::Surrender => {
-                if rule.allow_late_surrender {
+                if rule.allow_late_surrender
+                    && (current_split_all_times == 0 || rule.allow_surrender_after_split)
+                {
                     Decision::Surrender
                 } else {
                     decision.1
@@ -232,3 +287,120 @@ impl Strategy for BasicStrategy {
         }
     }
 }
+
+/// A strategy that makes a pre-programmed sequence of decisions regardless of the state, which is
+/// useful for testing specific play lines. It stands once all the decisions are made, and never
+/// buys insurance. It splits a pair only if its next decision is Split, so asking whether to split
+/// does not use up any other decision.
+#[derive(Debug, Default, Clone)]
+pub struct ScriptedStrategy {
+    decisions: VecDeque<Decision>,
+}
+
+impl ScriptedStrategy {
+    pub fn new(decisions: &[Decision]) -> Self {
+        ScriptedStrategy {
+            decisions: decisions.iter().copied().collect(),
+        }
+    }
+}
+
+impl Strategy for ScriptedStrategy {
+    fn calculate_expectation_before_bet(&mut self, _: &Rule, _: &CardCount) -> f64 {
+        0.0
+    }
+
+    fn init_with_initial_situation(&mut self, _: &Rule, _: &InitialSituation) {}
+
+    fn should_buy_insurance(&mut self, _: &Rule, _: &InitialSituation) -> bool {
+        false
+    }
+
+    fn make_decision(&mut self, _: &Rule, _: &CardCount, _: u8, _: u8) -> Decision {
+        self.decisions.pop_front().unwrap_or(Decision::Stand)
+    }
+
+    fn should_split(&mut self, _: &Rule, _: &CardCount, _: u8, _: u8) -> bool {
+        let should_split = self.decisions.front() == Some(&Decision::Split);
+        if should_split {
+            self.decisions.pop_front();
+        }
+        should_split
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edge_based_bet_scales_with_advantage() {
+        assert_eq!(edge_based_bet(-0.02, 10, 100), 10);
+        assert_eq!(edge_based_bet(0.0, 10, 100), 10);
+        assert_eq!(edge_based_bet(0.004, 10, 100), 10);
+        assert_eq!(edge_based_bet(0.011, 10, 100), 30);
+        assert_eq!(edge_based_bet(0.2, 10, 100), 100);
+    }
+
+    #[test]
+    fn edge_based_betting_wagers_more_than_flat_betting() {
+        // Expectations before bet of some rounds of a shoe.
+        let expectations = [-0.006, -0.004, 0.002, 0.008, 0.013, -0.001, 0.021];
+        let flat: u32 = expectations.iter().map(|_| 10).sum();
+        let edge_based: u32 = expectations
+            .iter()
+            .map(|&ex| edge_based_bet(ex, 10, 40))
+            .sum();
+        assert_eq!(flat, 70);
+        assert_eq!(edge_based, 10 + 10 + 10 + 20 + 30 + 10 + 40);
+    }
+
+    #[test]
+    fn basic_strategy_on_pairs() {
+        let rule = Rule::default();
+        let mut strategy = BasicStrategy::new(&rule);
+        let decide = |strategy: &mut BasicStrategy, card: u8, dealer_up_card: u8| {
+            let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+            let initial_situation = InitialSituation::new(shoe, (card, card), dealer_up_card);
+            strategy.init_with_initial_situation(&rule, &initial_situation);
+            let mut hand = CardCount::with_number_of_decks(0);
+            hand.add_card(card);
+            hand.add_card(card);
+            strategy.make_decision(&rule, &hand, 0, 0)
+        };
+        assert_eq!(decide(&mut strategy, 8, 10), Decision::Split);
+        assert_eq!(decide(&mut strategy, 1, 10), Decision::Split);
+        assert_eq!(decide(&mut strategy, 10, 6), Decision::Stand);
+        assert_eq!(decide(&mut strategy, 5, 6), Decision::Double);
+    }
+
+    #[test]
+    fn basic_strategy_on_soft_18() {
+        let decide = |rule: &Rule, dealer_up_card: u8| {
+            let mut strategy = BasicStrategy::new(rule);
+            let shoe = CardCount::with_number_of_decks(rule.number_of_decks);
+            let initial_situation = InitialSituation::new(shoe, (1, 7), dealer_up_card);
+            strategy.init_with_initial_situation(rule, &initial_situation);
+            let mut hand = CardCount::with_number_of_decks(0);
+            hand.add_card(1);
+            hand.add_card(7);
+            strategy.make_decision(rule, &hand, 0, 0)
+        };
+        let rule = Rule::default();
+        assert_eq!(decide(&rule, 9), Decision::Hit);
+        assert_eq!(decide(&rule, 10), Decision::Hit);
+        assert_eq!(decide(&rule, 1), Decision::Hit);
+        assert_eq!(decide(&rule, 2), Decision::Stand);
+        for dealer_up_card in 3..=6 {
+            assert_eq!(decide(&rule, dealer_up_card), Decision::Double);
+        }
+        assert_eq!(decide(&rule, 7), Decision::Stand);
+
+        let h17_rule = Rule {
+            dealer_hit_on_soft: true,
+            ..rule
+        };
+        assert_eq!(decide(&h17_rule, 2), Decision::Double);
+        assert_eq!(decide(&h17_rule, 7), Decision::Stand);
+    }
+}
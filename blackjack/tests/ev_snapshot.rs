@@ -0,0 +1,43 @@
+use blackjack::calculation::{
+    calculate_solution_without_initial_situation, read_ev_snapshot, write_ev_snapshot,
+};
+use blackjack::{CardCount, Rule};
+
+/// The snapshot of `SolutionForBettingPhase::ev_snapshot` for `Rule::default()` on `SHOE`. Run
+/// this test with the environment variable `UPDATE_EV_SNAPSHOT` set to regenerate it after an
+/// intended change of the solver.
+const SNAPSHOT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ev_snapshot.tsv");
+
+/// A 20-card shoe, which solves in a few seconds while still covering Stand, Hit, Double, Split,
+/// surrender, peeking and insurance. A full shoe is too slow to solve in a test.
+const SHOE: [u16; 10] = [2, 1, 1, 1, 2, 2, 1, 1, 1, 8];
+
+#[test]
+fn solver_matches_ev_snapshot() {
+    let rule = Rule {
+        allow_late_surrender: true,
+        ..Default::default()
+    };
+    let shoe = CardCount::new(&SHOE);
+    let snapshot = calculate_solution_without_initial_situation(1, &rule, &shoe).ev_snapshot(&rule);
+    if std::env::var_os("UPDATE_EV_SNAPSHOT").is_some() {
+        write_ev_snapshot(&snapshot, SNAPSHOT_PATH).unwrap();
+        return;
+    }
+
+    let expected = read_ev_snapshot(SNAPSHOT_PATH).unwrap();
+    assert_eq!(
+        snapshot.keys().collect::<Vec<_>>(),
+        expected.keys().collect::<Vec<_>>()
+    );
+    for (key, ex) in &snapshot {
+        let expected_ex = expected[key];
+        assert!(
+            (ex - expected_ex).abs() < 1e-9,
+            "{}: {} drifted from {}",
+            key,
+            ex,
+            expected_ex
+        );
+    }
+}
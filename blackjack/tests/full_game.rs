@@ -0,0 +1,113 @@
+use blackjack::simulation::{Card, Simulator};
+use blackjack::strategy::Strategy;
+use blackjack::{CardCount, Decision, InitialSituation, Rule};
+
+const NUMBER_OF_GAMES: usize = 500;
+const BET: u32 = 100;
+const SEED: u64 = 20230501;
+
+/// Plays like a dealer: hits until 17, never doubles, splits, surrenders or buys insurance. It
+/// remembers the cards revealed in the last round.
+struct MimicDealerStrategy {
+    revealed_cards: CardCount,
+}
+
+impl MimicDealerStrategy {
+    fn new() -> Self {
+        MimicDealerStrategy {
+            revealed_cards: CardCount::with_number_of_decks(0),
+        }
+    }
+}
+
+impl Strategy for MimicDealerStrategy {
+    fn calculate_expectation_before_bet(&mut self, _: &Rule, _: &CardCount) -> f64 {
+        0.0
+    }
+
+    fn init_with_initial_situation(&mut self, _: &Rule, _: &InitialSituation) {}
+
+    fn should_buy_insurance(&mut self, _: &Rule, _: &InitialSituation) -> bool {
+        false
+    }
+
+    fn make_decision(&mut self, _: &Rule, current_hand: &CardCount, _: u8, _: u8) -> Decision {
+        if current_hand.get_actual_sum() < 17 {
+            Decision::Hit
+        } else {
+            Decision::Stand
+        }
+    }
+
+    fn observe_cards(&mut self, cards: &[Card]) {
+        self.revealed_cards = CardCount::with_number_of_decks(0);
+        for card in cards {
+            self.revealed_cards.add_card(card.blackjack_value());
+        }
+    }
+}
+
+/// Returns what the player should win given the final cards of the player and dealer.
+fn get_expected_winning(rule: &Rule, player: &CardCount, dealer: &CardCount) -> u32 {
+    if player.bust() {
+        0
+    } else if rule.reached_charlie(player) {
+        BET * 2
+    } else if player.is_natural() {
+        if dealer.is_natural() {
+            BET
+        } else {
+            BET + (BET as f64 * rule.payout_blackjack) as u32
+        }
+    } else if dealer.is_natural() {
+        0
+    } else if dealer.bust() || player.get_actual_sum() > dealer.get_actual_sum() {
+        BET * 2
+    } else if player.get_actual_sum() == dealer.get_actual_sum() {
+        BET
+    } else {
+        0
+    }
+}
+
+#[test]
+fn automatic_simulation_conserves_money() {
+    let rule = Rule::default();
+    let mut strategy = MimicDealerStrategy::new();
+    let mut simulator = Simulator::with_seed(&rule, SEED);
+    let mut total_profit = 0;
+    let mut number_of_winning_games = 0;
+    for _ in 0..NUMBER_OF_GAMES {
+        let profit = simulator
+            .automatic_simulate_with_fixed_main_bet(&mut strategy, BET, 1)
+            .unwrap();
+        // Each round ends ready for the next one.
+        assert_eq!(simulator.phase_name(), "WaitForPlayerSeat");
+        assert!(simulator.place_bets(BET).is_err());
+
+        // The only player holds every revealed card the dealer doesn't.
+        let dealer = *simulator.get_dealer_card_count();
+        let mut player = strategy.revealed_cards;
+        for card in 1..=10 {
+            for _ in 0..dealer[card] {
+                player.remove_card(card);
+            }
+        }
+        assert!(player.get_total() >= 2);
+        let win = get_expected_winning(&rule, &player, &dealer);
+        assert_eq!(profit, win as i64 - BET as i64);
+        total_profit += profit;
+        if win > BET {
+            number_of_winning_games += 1;
+        }
+    }
+    assert!(number_of_winning_games > 0);
+
+    // The same seed replays the same rounds in a single run.
+    let mut strategy = MimicDealerStrategy::new();
+    let mut simulator = Simulator::with_seed(&rule, SEED);
+    let profit = simulator
+        .automatic_simulate_with_fixed_main_bet(&mut strategy, BET, NUMBER_OF_GAMES)
+        .unwrap();
+    assert_eq!(profit, total_profit);
+}
@@ -0,0 +1,76 @@
+use blackjack::calculation::calculate_solution_with_initial_situation;
+use blackjack::{CardCount, Decision, InitialSituation, Rule};
+
+/// Parses the scenario and returns the expectations of all decisions followed by the recommended
+/// decision, one per line.
+pub fn analyze(number_of_threads: usize, rule: &Rule, input: &str) -> Result<String, String> {
+    let mut lines = input.lines().filter(|line| !line.trim().is_empty());
+    let mut next_numbers = |name: &str| -> Result<Vec<u16>, String> {
+        let line = lines.next().ok_or(format!("Missing {}", name))?;
+        line.split_whitespace()
+            .map(|number| {
+                number
+                    .parse()
+                    .map_err(|_| format!("Invalid {}: {}", name, number))
+            })
+            .collect()
+    };
+
+    let shoe_numbers = next_numbers("shoe")?;
+    let hand_cards = next_numbers("player cards")?;
+    let dealer_up_card = next_numbers("dealer up card")?;
+    if hand_cards.len() != 2 {
+        return Err(String::from("There must be exactly 2 player cards"));
+    }
+    if dealer_up_card.len() != 1 {
+        return Err(String::from("There must be exactly 1 dealer up card"));
+    }
+    let cards = [hand_cards[0], hand_cards[1], dealer_up_card[0]];
+    if cards.iter().any(|&card| card == 0 || card > 10) {
+        return Err(String::from("Cards must be in [1, 10]"));
+    }
+    let cards = cards.map(|card| card as u8);
+
+    let shoe = match shoe_numbers.len() {
+        1 => {
+            let mut shoe = CardCount::with_number_of_decks(shoe_numbers[0] as u8);
+            for card in cards {
+                if shoe[card] == 0 {
+                    return Err(String::from("Not enough cards in the shoe"));
+                }
+                shoe.remove_card(card);
+            }
+            shoe
+        }
+        10 => CardCount::new(&shoe_numbers.try_into().unwrap()),
+        _ => {
+            return Err(String::from(
+                "The shoe must be either the number of decks or 10 card numbers",
+            ))
+        }
+    };
+
+    let initial_situation = InitialSituation::new(shoe, (cards[0], cards[1]), cards[2]);
+    let solution =
+        calculate_solution_with_initial_situation(number_of_threads, rule, &initial_situation);
+    let mut hand = CardCount::with_number_of_decks(0);
+    hand.add_card(cards[0]);
+    hand.add_card(cards[1]);
+
+    let mut output = String::new();
+    for decision in [
+        Decision::Stand,
+        Decision::Hit,
+        Decision::Double,
+        Decision::Split,
+        Decision::Surrender,
+    ] {
+        let ex = solution.decision_expectation(&hand, rule, decision);
+        output += &format!("{:?}: {:+.4}\n", decision, ex);
+    }
+    output += &format!("Insurance: {:+.4}\n", solution.ex_extra_insurance);
+    output += &format!("Summary: {:+.4}\n", solution.ex_summary);
+    let decision = solution.decision_for(&hand, rule, true, true, true);
+    output += &format!("Recommended: {:?}\n", decision);
+    Ok(output)
+}
@@ -0,0 +1,47 @@
+mod analysis;
+
+use blackjack_drivers::parse_config_from_file;
+use clap::Parser;
+use std::io::Read;
+
+const DEFAULT_CONFIG_PATH: &str = "~/.blackjack.yml";
+
+/// Reads a scenario from stdin and prints the expectations of all decisions. The scenario has
+/// three lines:
+/// 1. The shoe, either the number of decks of a fresh shoe, or the numbers of cards (from Ace to
+///    10) left in the shoe after the initial cards are dealt.
+/// 2. The two player cards.
+/// 3. The dealer up card.
+#[derive(Debug, Parser)]
+#[command(author, about, long_about = None)]
+struct CommandLineArgs {
+    /// The path of the config file
+    #[arg(short, long, default_value_t = String::from(DEFAULT_CONFIG_PATH))]
+    config: String,
+}
+
+fn main() {
+    let mut args = CommandLineArgs::parse();
+    if args.config == DEFAULT_CONFIG_PATH {
+        let home_dir = home::home_dir().expect("Cannot find home directory");
+        let config_file_path = home_dir.join(".blackjack.yml");
+        if !config_file_path.exists() {
+            panic!("Config file not exists");
+        }
+        if config_file_path.is_dir() {
+            panic!("This should be a path rather than a directory");
+        }
+        args.config = String::from(config_file_path.to_str().unwrap());
+    }
+    let args = args;
+
+    let config = parse_config_from_file(&args.config);
+    let rule: blackjack::Rule = config.rule.try_into().unwrap();
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input).unwrap();
+    let result = analysis::analyze(config.blackjack_simulator.number_of_threads, &rule, &input);
+    match result {
+        Ok(output) => print!("{}", output),
+        Err(err_msg) => panic!("{}", err_msg),
+    };
+}
@@ -112,7 +112,8 @@ pub fn simulate_playing_forever(
         let initial_situation = simulator.deal_initial_cards()?;
         dp_strategy.init_with_initial_situation(rule, &initial_situation);
 
-        let buy_insurance = dp_strategy.should_buy_insurance(rule, &initial_situation);
+        let buy_insurance = simulator.offer_insurance()
+            && dp_strategy.should_buy_insurance(rule, &initial_situation);
         if buy_insurance {
             println!("########## Should buy insurance! ###############");
             stat_virtual.bet_money(BASIC_BET / 2);
@@ -122,8 +123,26 @@ pub fn simulate_playing_forever(
 
         if !dealer_does_peek_and_natural {
             simulator.wait_for_right_players()?;
+            let hand_card_count = *simulator.get_my_current_card_count();
+            if !simulator.reached_split_time_limits()
+                && dp_strategy.make_decision(rule, &hand_card_count, 0, 0)
+                    == blackjack::Decision::Split
+            {
+                println!("Split!");
+                simulator.play_split(0)?;
+                stat_virtual.bet_money(BASIC_BET);
+                stat_real.bet_money(bet);
+            }
             simulator.stop_split()?;
-            for group_id in 0..simulator.get_number_of_groups() {
+            // After splitting Aces, the groups receive no more cards.
+            let number_of_groups_to_play = {
+                if simulator.get_current_split_ace_times() > 0 {
+                    0
+                } else {
+                    simulator.get_number_of_groups()
+                }
+            };
+            for group_id in 0..number_of_groups_to_play {
                 print!("Decisions for Group {}:", group_id);
                 loop {
                     let hand_card_count = simulator.get_my_current_card_count();
@@ -150,7 +169,8 @@ pub fn simulate_playing_forever(
             simulator.wait_for_left_players()?;
         }
 
-        let winning_money = simulator.dealer_plays_and_summary()?;
+        let winning_money = simulator.dealer_plays_and_summary()?.total_win();
+        dp_strategy.observe_cards(simulator.revealed_cards());
         stat_virtual.receive_money(winning_money);
         stat_real.receive_money((winning_money as u64 * bet as u64 / BASIC_BET as u64) as u32);
 
@@ -183,12 +203,11 @@ pub fn simulate_playing_forever(
         let period_money = stat_real.get_current_money() - prev_period_end_money;
         let period_bet = stat_real.get_total_bet() - prev_period_end_bet;
         if period_percentage == 0 {
-            period_percentage = simulator_config.games_in_period as u64;
+            period_percentage = simulator_config.games_in_period;
             prev_period_end_money = stat_real.get_current_money();
             prev_period_end_bet = stat_real.get_total_bet();
         }
-        let period_percentage =
-            (period_percentage * 100 as u64) / simulator_config.games_in_period as u64;
+        let period_percentage = (period_percentage * 100_u64) / simulator_config.games_in_period;
         print!("This period: ");
         println!(
             "Money: {}, Total bet: {}, Rate: {:.2}%. Process: {:.2}%",
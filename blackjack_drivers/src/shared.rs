@@ -1,4 +1,3 @@
-use blackjack;
 use serde::{Deserialize, Serialize};
 use std::fs;
 
@@ -15,14 +14,49 @@ pub struct ConfigRule {
     pub split_all_limits: u8,
     pub split_ace_limits: u8,
     pub double_policy: String,
-    pub dealer_hit_on_soft17: bool,
+    #[serde(default = "default_dealer_stand_value")]
+    pub dealer_stand_value: u16,
+    #[serde(alias = "dealer_hit_on_soft17")]
+    pub dealer_hit_on_soft: bool,
+    #[serde(default)]
+    pub dealer_soft_hit_up_to: Option<u16>,
     pub allow_das: bool,
     pub allow_late_surrender: bool,
+    #[serde(default)]
+    pub allow_surrender_after_split: bool,
     pub peek_policy: String,
+    #[serde(default = "default_offer_insurance_on_ace")]
+    pub offer_insurance_on_ace: bool,
     pub charlie_number: u8,
 
     pub payout_blackjack: f64,
     pub payout_insurance: f64,
+    #[serde(default = "default_player_natural_vs_dealer_natural")]
+    pub player_natural_vs_dealer_natural: String,
+    #[serde(default)]
+    pub suited_blackjack_payout: Option<f64>,
+    #[serde(default)]
+    pub max_bet: u32,
+    #[serde(default)]
+    pub other_players_mimic_dealer: bool,
+    #[serde(default = "default_dealer_draws_when_all_players_bust")]
+    pub dealer_draws_when_all_players_bust: bool,
+}
+
+fn default_dealer_stand_value() -> u16 {
+    17
+}
+
+fn default_offer_insurance_on_ace() -> bool {
+    true
+}
+
+fn default_player_natural_vs_dealer_natural() -> String {
+    String::from("Push")
+}
+
+fn default_dealer_draws_when_all_players_bust() -> bool {
+    true
 }
 
 impl TryInto<blackjack::Rule> for ConfigRule {
@@ -35,13 +69,22 @@ impl TryInto<blackjack::Rule> for ConfigRule {
             split_all_limits: self.split_all_limits,
             split_ace_limits: self.split_ace_limits,
             double_policy: self.double_policy.parse()?,
-            dealer_hit_on_soft17: self.dealer_hit_on_soft17,
+            dealer_stand_value: self.dealer_stand_value,
+            dealer_hit_on_soft: self.dealer_hit_on_soft,
+            dealer_soft_hit_up_to: self.dealer_soft_hit_up_to,
             allow_das: self.allow_das,
             allow_late_surrender: self.allow_late_surrender,
+            allow_surrender_after_split: self.allow_surrender_after_split,
             peek_policy: self.peek_policy.parse()?,
+            offer_insurance_on_ace: self.offer_insurance_on_ace,
             charlie_number: self.charlie_number,
             payout_blackjack: self.payout_blackjack,
             payout_insurance: self.payout_insurance,
+            player_natural_vs_dealer_natural: self.player_natural_vs_dealer_natural.parse()?,
+            suited_blackjack_payout: self.suited_blackjack_payout,
+            max_bet: self.max_bet,
+            other_players_mimic_dealer: self.other_players_mimic_dealer,
+            dealer_draws_when_all_players_bust: self.dealer_draws_when_all_players_bust,
         };
 
         Ok(blackjack_rule)
@@ -73,13 +116,22 @@ mod tests {
             split_all_limits: 1,
             split_ace_limits: 1,
             double_policy: String::from("AnyTwo"),
-            dealer_hit_on_soft17: false,
+            dealer_stand_value: 17,
+            dealer_hit_on_soft: false,
+            dealer_soft_hit_up_to: None,
             allow_das: false,
             allow_late_surrender: false,
+            allow_surrender_after_split: false,
             peek_policy: String::from("UpAce"),
+            offer_insurance_on_ace: true,
             charlie_number: 6,
             payout_blackjack: 1.5,
             payout_insurance: 2.0,
+            player_natural_vs_dealer_natural: String::from("Push"),
+            suited_blackjack_payout: None,
+            max_bet: 0,
+            other_players_mimic_dealer: false,
+            dealer_draws_when_all_players_bust: true,
         }
     }
 
@@ -96,6 +148,17 @@ mod tests {
         assert_eq!(converted_rule.peek_policy, blackjack::PeekPolicy::UpAce);
     }
 
+    #[test]
+    fn can_parse_old_dealer_hit_on_soft17_key() {
+        let yaml = serde_yaml::to_string(&get_typical_config_rule())
+            .unwrap()
+            .replace("dealer_hit_on_soft: false", "dealer_hit_on_soft17: true")
+            .replace("dealer_stand_value: 17\n", "");
+        let config_rule: ConfigRule = serde_yaml::from_str(&yaml).unwrap();
+        assert!(config_rule.dealer_hit_on_soft);
+        assert_eq!(config_rule.dealer_stand_value, 17);
+    }
+
     #[test]
     fn should_return_error_when_converting_rule() {
         let mut config_rule = get_typical_config_rule();
@@ -0,0 +1,55 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const CONFIG: &str = "rule:
+  number_of_decks: 8
+  cut_card_proportion: 0.5
+  split_all_limits: 1
+  split_ace_limits: 1
+  double_policy: AnyTwo
+  dealer_hit_on_soft17: false
+  allow_das: false
+  allow_late_surrender: false
+  peek_policy: UpAce
+  charlie_number: 6
+
+  payout_blackjack: 1.5
+  payout_insurance: 2.0
+
+blackjack_simulator:
+  number_of_threads: 1
+  games_in_period: 1000
+";
+
+fn analyze(scenario: &str) -> String {
+    let config_path = std::env::temp_dir().join(format!(
+        "blackjack_analyzer_test_{}.yml",
+        std::process::id()
+    ));
+    std::fs::write(&config_path, CONFIG).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_blackjack_analyzer"))
+        .arg("--config")
+        .arg(&config_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(scenario.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&config_path).unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn eleven_against_six_doubles() {
+    let output = analyze("8\n6 5\n6\n");
+    assert!(output.lines().any(|line| line.starts_with("Stand: ")));
+    assert!(output.contains("Split: -inf"));
+    assert_eq!(output.lines().last(), Some("Recommended: Double"));
+}
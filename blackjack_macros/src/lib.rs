@@ -1,6 +1,7 @@
 use proc_macro::TokenStream;
-use quote::ToTokens;
-use syn;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
 
 /// This macro is added before a method of `Simulator` struct in the impl block.
 /// Use this macro to first check if current game phase is exactly the phase in
@@ -9,22 +10,195 @@ use syn;
 /// For example, `#[allowed_phase(PlaceBets)]` will make a method first check
 /// if current game phase is `PlaceBets`. If not, the method will return an
 /// error message.
+///
+/// Only methods taking `self` are supported. Anything else (e.g. a struct or a
+/// free function) is reported as a compile error instead of a panic.
 #[proc_macro_attribute]
 pub fn allowed_phase(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let mut ast: syn::ImplItemFn = syn::parse(item).unwrap();
-    let phase = attr.to_string();
-    let function_name = ast.sig.ident.to_string();
-    let err_msg = format!("{} is only allowed in {} phase", function_name, phase);
-    let code = format!(
-        r#"
-    if self.current_game_phase != GamePhase::{} {{
-        return Err(String::from("{}"));
-    }}
-"#,
-        phase, err_msg
+    allowed_phase_impl(attr.into(), item.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn allowed_phase_impl(attr: TokenStream2, item: TokenStream2) -> syn::Result<TokenStream2> {
+    let phase: syn::Ident = syn::parse2(attr.clone()).map_err(|_| {
+        syn::Error::new(
+            attr.span(),
+            "allowed_phase expects exactly one GamePhase variant, e.g. `#[allowed_phase(PlaceBets)]`",
+        )
+    })?;
+    let mut ast = parse_method("allowed_phase", item)?;
+
+    let err_msg = format!("{} is only allowed in {} phase", ast.sig.ident, phase);
+    let early_return: syn::Stmt = syn::parse_quote! {
+        if self.current_game_phase != GamePhase::#phase {
+            return Err(String::from(#err_msg));
+        }
+    };
+    ast.block.stmts.insert(0, early_return);
+    Ok(quote!(#ast))
+}
+
+/// This macro is added before a `play_*` method of `Simulator` struct in the
+/// impl block. Use this macro to first check if the group currently being
+/// played has at least two cards. If not, the method will return an error
+/// message without dealing any card.
+///
+/// It guards the Play phase: every group must hold its two initial cards (or
+/// its original card plus the card dealt right after a split) before the
+/// player can stand, hit, double or surrender on it.
+///
+/// Put this macro above `#[allowed_phase(...)]`, so that the game phase is
+/// checked first and `current_playing_group_index` is always valid here.
+#[proc_macro_attribute]
+pub fn validate_hand_at_least_two_cards(attr: TokenStream, item: TokenStream) -> TokenStream {
+    validate_hand_at_least_two_cards_impl(attr.into(), item.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn validate_hand_at_least_two_cards_impl(
+    attr: TokenStream2,
+    item: TokenStream2,
+) -> syn::Result<TokenStream2> {
+    if !attr.is_empty() {
+        return Err(syn::Error::new(
+            attr.span(),
+            "validate_hand_at_least_two_cards takes no arguments",
+        ));
+    }
+    let mut ast = parse_method("validate_hand_at_least_two_cards", item)?;
+
+    let err_msg = format!(
+        "{} requires the current group to have at least two cards",
+        ast.sig.ident
     );
-    let early_return: TokenStream = code.parse().unwrap();
-    let early_return: syn::Stmt = syn::parse(early_return).unwrap();
+    let early_return: syn::Stmt = syn::parse_quote! {
+        if self.current_hand.get_cards(self.current_playing_group_index).len() < 2 {
+            return Err(String::from(#err_msg));
+        }
+    };
     ast.block.stmts.insert(0, early_return);
-    ast.into_token_stream().into()
+    Ok(quote!(#ast))
+}
+
+/// Parses the item that the attribute `macro_name` is added to, and makes sure it is a method
+/// taking `self`.
+fn parse_method(macro_name: &str, item: TokenStream2) -> syn::Result<syn::ImplItemFn> {
+    let ast: syn::ImplItemFn = syn::parse2(item.clone()).map_err(|_| {
+        syn::Error::new(
+            item.span(),
+            format!(
+                "{} can only be applied to a method in an impl block",
+                macro_name
+            ),
+        )
+    })?;
+    if !matches!(ast.sig.inputs.first(), Some(syn::FnArg::Receiver(_))) {
+        return Err(syn::Error::new(
+            ast.sig.span(),
+            format!(
+                "{} can only be applied to a method taking `self`",
+                macro_name
+            ),
+        ));
+    }
+    Ok(ast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expect_error(attr: TokenStream2, item: TokenStream2) -> String {
+        match allowed_phase_impl(attr, item) {
+            Ok(tokens) => panic!("Expected an error, but got: {}", tokens),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn inserts_phase_check() {
+        let tokens = allowed_phase_impl(
+            quote!(PlaceBets),
+            quote! {
+                pub fn place_bets(&mut self, bet: u32) -> Result<(), String> {
+                    Ok(())
+                }
+            },
+        )
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("GamePhase :: PlaceBets"));
+        assert!(tokens.contains("\"place_bets is only allowed in PlaceBets phase\""));
+    }
+
+    #[test]
+    fn tuple_struct_is_rejected() {
+        let err = expect_error(
+            quote!(PlaceBets),
+            quote!(
+                struct Bets(u32);
+            ),
+        );
+        assert!(err.contains("can only be applied to a method"));
+    }
+
+    #[test]
+    fn free_function_is_rejected() {
+        let err = expect_error(
+            quote!(PlaceBets),
+            quote!(
+                fn place_bets(bet: u32) {}
+            ),
+        );
+        assert!(err.contains("method taking `self`"));
+    }
+
+    #[test]
+    fn invalid_phase_is_rejected() {
+        let err = expect_error(
+            quote!(),
+            quote!(
+                fn f(&self) {}
+            ),
+        );
+        assert!(err.contains("exactly one GamePhase variant"));
+        let err = expect_error(
+            quote!(PlaceBets, Play),
+            quote!(
+                fn f(&self) {}
+            ),
+        );
+        assert!(err.contains("exactly one GamePhase variant"));
+    }
+
+    #[test]
+    fn inserts_two_cards_check() {
+        let tokens = validate_hand_at_least_two_cards_impl(
+            quote!(),
+            quote! {
+                pub fn play_hit(&mut self) -> Result<bool, String> {
+                    Ok(false)
+                }
+            },
+        )
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("len () < 2"));
+        assert!(
+            tokens.contains("\"play_hit requires the current group to have at least two cards\"")
+        );
+    }
+
+    #[test]
+    fn two_cards_check_rejects_arguments() {
+        let result = validate_hand_at_least_two_cards_impl(
+            quote!(Play),
+            quote!(
+                fn f(&self) {}
+            ),
+        );
+        assert!(result.is_err());
+    }
 }